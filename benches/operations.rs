@@ -1,19 +1,19 @@
 use std::thread;
 use criterion::{criterion_group, criterion_main, Criterion};
 
-use osm_geo_mapper::operations;
+use osm_geo_mapper::{ geojson_parser, operations, projection::TileGrid };
 
 fn bench_process_geojson() {
-    let geojson = operations::parse_geojson_file("resources/ottawa.xml.geojson");
-    operations::process_geojson(&geojson);
+    let geojson = geojson_parser::parse_geojson_file("resources/ottawa.xml.geojson").unwrap();
+    operations::process_geojson(&geojson, TileGrid::default()).unwrap();
 }
 
 fn bench_threaded_process_geojson() {
     let mut handles = Vec::new();
     for _ in 1..10 {
         handles.push(thread::spawn(|| {
-            let geojson = operations::parse_geojson_file("resources/ottawa.xml.geojson");
-            operations::process_geojson(&geojson);
+            let geojson = geojson_parser::parse_geojson_file("resources/ottawa.xml.geojson").unwrap();
+            operations::process_geojson(&geojson, TileGrid::default()).unwrap();
         }));
     }
     for h in handles {
@@ -24,8 +24,8 @@ fn bench_threaded_process_geojson() {
 fn criterion_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("process_geojson");
     group.sample_size(10);
-    group.bench_function("process_geojson", |b| b.iter(|| bench_process_geojson()));
-    group.bench_function("threaded_process_geojson", |b| b.iter(|| bench_threaded_process_geojson()));
+    group.bench_function("process_geojson", |b| b.iter(bench_process_geojson));
+    group.bench_function("threaded_process_geojson", |b| b.iter(bench_threaded_process_geojson));
     group.finish();
 }
 
@@ -1,9 +1,10 @@
 /// Helper macro to create the many enums used in this library.
+/// The enums can be (de)serialized, the calling crate needs serde (with the derive feature).
 #[macro_export]
 macro_rules! create_enum {
     ($($enum:ident [$($variant:ident),*$(,)*]),*$(,)*) => {
         $(
-            #[derive(Debug, Clone, Copy)]
+            #[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
             pub enum $enum {
                 $(
                     $variant,
@@ -14,12 +15,13 @@ macro_rules! create_enum {
 }
 
 /// You can find all features at https://wiki.openstreetmap.org/wiki/Map_Features
+/// Like create_enum, the GeoTile enum can be (de)serialized with serde.
 #[macro_export]
 macro_rules! implement_geotile {
     ($($variant:ident [$($attr:ident),*]),*$(,)*) => {
         paste! {
            // First generate the giant enum of GeoTiles.
-           #[derive(Debug, Clone)]
+           #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
            pub enum GeoTile {
                $(
                    $variant {
@@ -57,6 +59,43 @@ macro_rules! implement_geotile {
                    }
                }
             }
+            // Accessors for the fields shared by every variant.
+            impl GeoTile {
+                pub fn osm_id(&self) -> &str {
+                    match self {
+                        $(
+                            GeoTile::$variant { osm_id, .. } => osm_id,
+                        )*
+                    }
+                }
+
+                pub fn geometry(&self) -> &Geometry {
+                    match self {
+                        $(
+                            GeoTile::$variant { geometry, .. } => geometry,
+                        )*
+                    }
+                }
+
+                // A copy of this GeoTile drawn with another geometry.
+                pub fn with_geometry(&self, new_geometry: Geometry) -> GeoTile {
+                    let mut geo_tile = self.clone();
+                    match &mut geo_tile {
+                        $(
+                            GeoTile::$variant { geometry, .. } => *geometry = new_geometry,
+                        )*
+                    }
+                    geo_tile
+                }
+
+                pub fn address(&self) -> Option<&Address> {
+                    match self {
+                        $(
+                            GeoTile::$variant { address, .. } => address.as_ref(),
+                        )*
+                    }
+                }
+            }
         }
     }
 }
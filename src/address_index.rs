@@ -0,0 +1,444 @@
+use std::{ collections::{ HashMap, HashSet }, sync::Arc };
+use geo::algorithm::{ centroid::Centroid, euclidean_length::EuclideanLength };
+use geo_types as gt;
+
+use crate::{
+    features::{ Address, GeoTile, Geometry, UnclassifiedType },
+    projection::TileGrid,
+};
+
+// Common street type abbreviations, expanded when normalizing street names.
+const STREET_ABBREVIATIONS: &[(&str, &str)] = &[
+    ("av", "avenue"), ("ave", "avenue"), ("blvd", "boulevard"), ("cir", "circle"), ("crt", "court"),
+    ("cres", "crescent"), ("ct", "court"), ("dr", "drive"), ("hwy", "highway"), ("ln", "lane"),
+    ("pkwy", "parkway"), ("pl", "place"), ("rd", "road"), ("sq", "square"), ("st", "street"),
+    ("ter", "terrace"), ("trl", "trail"),
+];
+
+// A place found by AddressIndex::find.
+#[derive(Debug, Clone)]
+pub struct AddressMatch {
+    pub coordinates: gt::Coordinate<i32>,
+    // The features with this address, or the addr:interpolation way the location was estimated from.
+    pub geo_tiles: Vec<Arc<GeoTile>>,
+    pub interpolated: bool,
+}
+
+#[derive(Debug, Clone)]
+struct AddressEntry {
+    house_number: Option<String>,
+    street: Option<String>,
+    postcode: Option<String>,
+    coordinates: gt::Coordinate<i32>,
+    geo_tile: Arc<GeoTile>,
+}
+
+// The house numbers along an addr:interpolation way, alphabetic (12A, 12B, ...) ones aren't supported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InterpolationScheme {
+    All,
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Clone)]
+struct Interpolation {
+    street: String,
+    // House numbers at the start and the end of the way.
+    start: u32,
+    end: u32,
+    scheme: InterpolationScheme,
+    line_string: gt::LineString<f64>,
+    geo_tile: Arc<GeoTile>,
+}
+
+// Address lookups over features (built from their addr:* tags).
+// Matches are located on the given grid, the one the features were drawn on.
+// OSMGeoMapper keeps one up to date with the loaded features, see OSMGeoMapper::find_address.
+#[derive(Debug, Clone, Default)]
+pub struct AddressIndex {
+    // Keyed by the position they were added at, which stays the same when other entries are removed.
+    entries: HashMap<usize, AddressEntry>,
+    next_position: usize,
+    // Normalized street name, postcode, location and osm_id to the matching entries.
+    streets: HashMap<String, Vec<usize>>,
+    postcodes: HashMap<String, Vec<usize>>,
+    locations: HashMap<gt::Coordinate<i32>, Vec<usize>>,
+    osm_ids: HashMap<String, Vec<usize>>,
+    // Every addr:interpolation way, only the ones whose end nodes are indexed are in interpolations.
+    interpolation_tiles: Vec<Arc<GeoTile>>,
+    interpolations: Vec<Interpolation>,
+    grid: TileGrid,
+}
+
+impl AddressIndex {
+    // Each GeoTile should only be given once.
+    pub fn new(geo_tiles: &[Arc<GeoTile>], grid: TileGrid) -> AddressIndex {
+        let mut index = AddressIndex { grid, ..AddressIndex::default() };
+        index.add(geo_tiles);
+        index
+    }
+
+    // Indexes more features (eg. freshly loaded ones), each GeoTile should only be added once.
+    pub fn add(&mut self, geo_tiles: &[Arc<GeoTile>]) {
+        let mut added_interpolations = false;
+        for geo_tile in geo_tiles {
+            if let GeoTile::Unclassified { unclassified_type: UnclassifiedType::AddressInterpolation, .. } = geo_tile.as_ref() {
+                self.interpolation_tiles.push(geo_tile.clone());
+                added_interpolations = true;
+            } else if let (Some(address), Some(coordinates)) = (geo_tile.address(), representative_coordinates(geo_tile.geometry(), self.grid)) {
+                self.add_entry(address, coordinates, geo_tile.clone());
+                // Might be the end node of an interpolation way.
+                added_interpolations |= !self.interpolation_tiles.is_empty();
+            }
+        }
+        if added_interpolations {
+            self.locate_interpolations();
+        }
+    }
+
+    // Forgets the given features (eg. removed from the mapper), only their own entries are looked up.
+    pub fn remove(&mut self, geo_tiles: &[Arc<GeoTile>]) {
+        let removed_geo_tiles: HashSet<*const GeoTile> = geo_tiles.iter().map(Arc::as_ptr).collect();
+        let interpolation_count = self.interpolation_tiles.len();
+        let is_interpolation = |geo_tile: &Arc<GeoTile>| {
+            matches!(geo_tile.as_ref(), GeoTile::Unclassified { unclassified_type: UnclassifiedType::AddressInterpolation, .. })
+        };
+        if geo_tiles.iter().any(is_interpolation) {
+            self.interpolation_tiles.retain(|geo_tile| !removed_geo_tiles.contains(&Arc::as_ptr(geo_tile)));
+        }
+        let mut removed_interpolation_end = false;
+        for geo_tile in geo_tiles {
+            let positions: Vec<usize> = match self.osm_ids.get(geo_tile.osm_id()) {
+                Some(positions) => positions
+                    .iter()
+                    .copied()
+                    .filter(|position| Arc::ptr_eq(&self.entries[position].geo_tile, geo_tile))
+                    .collect(),
+                None => continue,
+            };
+            for position in positions {
+                let entry = self.unindex_entry(position);
+                removed_interpolation_end |= entry.house_number.is_some() && !self.interpolation_tiles.is_empty();
+            }
+        }
+        if removed_interpolation_end || self.interpolation_tiles.len() != interpolation_count {
+            self.locate_interpolations();
+        }
+    }
+
+    fn add_entry(&mut self, address: &Address, coordinates: gt::Coordinate<i32>, geo_tile: Arc<GeoTile>) {
+        let position = self.next_position;
+        self.next_position += 1;
+        self.entries.insert(position, AddressEntry {
+            house_number: address.house_number.as_deref().map(normalize_house_number),
+            street: address.street.as_deref().map(normalize_street),
+            postcode: address.postal_code.as_deref().map(normalize_postcode),
+            coordinates,
+            geo_tile,
+        });
+        self.index_entry(position);
+    }
+
+    fn index_entry(&mut self, position: usize) {
+        let entry = &self.entries[&position];
+        if let Some(street) = &entry.street {
+            self.streets.entry(street.clone()).or_default().push(position);
+        }
+        if let Some(postcode) = &entry.postcode {
+            self.postcodes.entry(postcode.clone()).or_default().push(position);
+        }
+        self.locations.entry(entry.coordinates).or_default().push(position);
+        self.osm_ids.entry(entry.geo_tile.osm_id().to_string()).or_default().push(position);
+    }
+
+    fn unindex_entry(&mut self, position: usize) -> AddressEntry {
+        let entry = self.entries.remove(&position).unwrap();
+        if let Some(street) = &entry.street {
+            remove_position(&mut self.streets, street, position);
+        }
+        if let Some(postcode) = &entry.postcode {
+            remove_position(&mut self.postcodes, postcode, position);
+        }
+        remove_position(&mut self.locations, &entry.coordinates, position);
+        remove_position(&mut self.osm_ids, entry.geo_tile.osm_id(), position);
+        entry
+    }
+
+    fn locate_interpolations(&mut self) {
+        self.interpolations = self.interpolation_tiles
+            .iter()
+            .filter_map(|geo_tile| self.interpolation(geo_tile.clone()))
+            .collect();
+    }
+
+    // The way's end nodes carry the house numbers (and usually the street).
+    fn interpolation(&self, geo_tile: Arc<GeoTile>) -> Option<Interpolation> {
+        let line_string = match geo_tile.geometry() {
+            Geometry::LineString(line_string) if line_string.0.len() >= 2 => line_string.clone(),
+            _ => return None,
+        };
+        let scheme = match geo_tile.as_ref() {
+            GeoTile::Unclassified { interpolation: Some(interpolation), .. } => match interpolation.as_str() {
+                "all" => InterpolationScheme::All,
+                "odd" => InterpolationScheme::Odd,
+                "even" => InterpolationScheme::Even,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let end_entry = |coordinate: &gt::Coordinate<f64>| -> Option<&AddressEntry> {
+            let positions = self.locations.get(&to_tile_coordinates(coordinate, self.grid))?;
+            positions
+                .iter()
+                .map(|position| &self.entries[position])
+                .find(|entry| entry.house_number.as_deref().and_then(parse_house_number).is_some())
+        };
+        let first = end_entry(&line_string.0[0])?;
+        let last = end_entry(line_string.0.last().unwrap())?;
+        let street = geo_tile.address()
+            .and_then(|address| address.street.as_deref().map(normalize_street))
+            .or_else(|| first.street.clone())
+            .or_else(|| last.street.clone());
+        let start = first.house_number.as_deref().and_then(parse_house_number)?;
+        let end = last.house_number.as_deref().and_then(parse_house_number)?;
+        Some(Interpolation { street: street?, start, end, scheme, line_string, geo_tile })
+    }
+
+    // Queries look like "123 Main St", "123 Main Street, K1A 0A9" or just a postcode.
+    // Parts other than the street, house number and a known postcode (eg. the city) are ignored.
+    pub fn find(&self, query: &str) -> Vec<AddressMatch> {
+        let mut parts = query.split(',').map(str::trim).filter(|part| !part.is_empty());
+        let first_part = match parts.next() {
+            Some(first_part) => first_part,
+            None => return Vec::new(),
+        };
+        let mut postcode = None;
+        let mut rest = Vec::new();
+        for part in std::iter::once(first_part).chain(parts) {
+            let normalized = normalize_postcode(part);
+            if postcode.is_none() && self.postcodes.contains_key(&normalized) {
+                postcode = Some(normalized);
+            } else {
+                rest.push(part);
+            }
+        }
+        let (house_number, street) = match rest.first() {
+            Some(street_part) => split_house_number(street_part),
+            None => (None, None),
+        };
+        let candidates: Vec<&AddressEntry> = match (&street, &postcode) {
+            (Some(street), _) => self.streets.get(street).into_iter().flatten().map(|position| &self.entries[position]).collect(),
+            (None, Some(postcode)) => self.postcodes[postcode].iter().map(|position| &self.entries[position]).collect(),
+            (None, None) => return Vec::new(),
+        };
+        let matching: Vec<&AddressEntry> = candidates
+            .into_iter()
+            .filter(|entry| postcode.is_none() || entry.postcode == postcode)
+            .filter(|entry| house_number.is_none() || entry.house_number == house_number)
+            .collect();
+        if matching.is_empty() {
+            if let (Some(street), Some(house_number)) = (&street, &house_number) {
+                return self.interpolate(street, house_number);
+            }
+        }
+        group_by_coordinates(matching)
+    }
+
+    fn interpolate(&self, street: &str, house_number: &str) -> Vec<AddressMatch> {
+        let number = match parse_house_number(house_number) {
+            Some(number) => number,
+            None => return Vec::new(),
+        };
+        let mut matches = Vec::new();
+        for interpolation in self.interpolations.iter().filter(|interpolation| interpolation.street == street) {
+            let (low, high) = (interpolation.start.min(interpolation.end), interpolation.start.max(interpolation.end));
+            let on_way = match interpolation.scheme {
+                InterpolationScheme::All => true,
+                InterpolationScheme::Odd => number % 2 == 1,
+                InterpolationScheme::Even => number % 2 == 0,
+            };
+            if number <= low || number >= high || !on_way {
+                continue;
+            }
+            let fraction = (number as f64 - interpolation.start as f64) / (interpolation.end as f64 - interpolation.start as f64);
+            let coordinate = point_along(&interpolation.line_string, fraction);
+            matches.push(AddressMatch {
+                coordinates: to_tile_coordinates(&coordinate, self.grid),
+                geo_tiles: vec![interpolation.geo_tile.clone()],
+                interpolated: true,
+            });
+        }
+        matches
+    }
+}
+
+// Removes an entry from one of the lookup maps, forgetting keys without entries left.
+fn remove_position<K, Q>(map: &mut HashMap<K, Vec<usize>>, key: &Q, position: usize)
+where
+    K: std::borrow::Borrow<Q> + std::hash::Hash + Eq,
+    Q: std::hash::Hash + Eq + ?Sized,
+{
+    if let Some(positions) = map.get_mut(key) {
+        positions.retain(|&other| other != position);
+        if positions.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+fn group_by_coordinates(entries: Vec<&AddressEntry>) -> Vec<AddressMatch> {
+    let mut matches: Vec<AddressMatch> = Vec::new();
+    for entry in entries {
+        match matches.iter_mut().find(|address_match| address_match.coordinates == entry.coordinates) {
+            Some(address_match) => address_match.geo_tiles.push(entry.geo_tile.clone()),
+            None => matches.push(AddressMatch {
+                coordinates: entry.coordinates,
+                geo_tiles: vec![entry.geo_tile.clone()],
+                interpolated: false,
+            }),
+        }
+    }
+    matches
+}
+
+// Points stay where they are, lines and polygons are represented by their centroid.
+fn representative_coordinates(geometry: &Geometry, grid: TileGrid) -> Option<gt::Coordinate<i32>> {
+    let point = match geometry {
+        Geometry::Point(point) => Some(*point),
+        Geometry::LineString(line_string) => line_string.centroid(),
+        Geometry::Polygon(polygon) => polygon.centroid(),
+    };
+    point.map(|point| to_tile_coordinates(&point.0, grid))
+}
+
+fn to_tile_coordinates(coordinate: &gt::Coordinate<f64>, grid: TileGrid) -> gt::Coordinate<i32> {
+    grid.to_tile(coordinate.x, coordinate.y)
+}
+
+// The coordinate at the given fraction (0 to 1) of the line's length.
+fn point_along(line_string: &gt::LineString<f64>, fraction: f64) -> gt::Coordinate<f64> {
+    let mut remaining = line_string.euclidean_length() * fraction;
+    for line in line_string.lines() {
+        let length = line.euclidean_length();
+        if remaining <= length && length > 0.0 {
+            let ratio = remaining / length;
+            return gt::Coordinate {
+                x: line.start.x + (line.end.x - line.start.x) * ratio,
+                y: line.start.y + (line.end.y - line.start.y) * ratio,
+            };
+        }
+        remaining -= length;
+    }
+    *line_string.0.last().unwrap()
+}
+
+// "123 Main St" -> (Some("123"), Some("main street")), "Main St" -> (None, Some("main street")).
+fn split_house_number(part: &str) -> (Option<String>, Option<String>) {
+    let mut words = part.split_whitespace().peekable();
+    let house_number = match words.peek() {
+        Some(word) if word.starts_with(|c: char| c.is_ascii_digit()) => words.next().map(normalize_house_number),
+        _ => None,
+    };
+    let street = words.collect::<Vec<&str>>().join(" ");
+    let street = if street.is_empty() { None } else { Some(normalize_street(&street)) };
+    (house_number, street)
+}
+
+fn normalize_house_number(house_number: &str) -> String {
+    house_number.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase()
+}
+
+fn normalize_street(street: &str) -> String {
+    street
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '.')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            STREET_ABBREVIATIONS
+                .iter()
+                .find(|(abbreviation, _)| *abbreviation == word)
+                .map_or(word, |(_, expansion)| expansion)
+        })
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn normalize_postcode(postcode: &str) -> String {
+    postcode.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase()
+}
+
+// Only plain numbers can be interpolated (not 12A or 12-14).
+fn parse_house_number(house_number: &str) -> Option<u32> {
+    house_number.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geojson as gj;
+    use std::{ collections::HashMap, sync::RwLock };
+    use crate::operations;
+
+    fn geo_tiles(geojson: &str) -> Vec<Arc<GeoTile>> {
+        let geojson: gj::GeoJson = geojson.parse().unwrap();
+        let data_structure = Arc::new(RwLock::new(HashMap::new()));
+        operations::process_geojson_with_data_structure(&geojson, data_structure, TileGrid::default(), false).unwrap().0
+    }
+
+    #[test]
+    fn test_interpolation_schemes() {
+        let interpolated = |scheme: &str, query: &str| {
+            let geo_tiles = geo_tiles(&format!(r#"{{"type": "FeatureCollection", "features": [
+                {{"type": "Feature", "properties": {{"id": "node/1", "addr:housenumber": "100", "addr:street": "Main Street"}}, "geometry": {{"type": "Point", "coordinates": [-75.0, 45.0]}}}},
+                {{"type": "Feature", "properties": {{"id": "node/2", "addr:housenumber": "111", "addr:street": "Main Street"}}, "geometry": {{"type": "Point", "coordinates": [-75.0011, 45.0]}}}},
+                {{"type": "Feature", "properties": {{"id": "way/3", "addr:interpolation": "{}"}}, "geometry": {{"type": "LineString", "coordinates": [[-75.0, 45.0], [-75.0011, 45.0]]}}}}
+            ]}}"#, scheme));
+            let matches = AddressIndex::new(&geo_tiles, TileGrid::default()).find(query);
+            matches.iter().map(|address_match| address_match.coordinates).collect::<Vec<_>>()
+        };
+        assert_eq!(interpolated("all", "103 Main St"), vec![gt::Coordinate { x: -7_500_030, y: 4_500_000 }]);
+        assert_eq!(interpolated("all", "110 Main St").len(), 1);
+        // The endpoints don't tell which side of the street the way holds.
+        assert!(interpolated("even", "103 Main St").is_empty());
+        assert_eq!(interpolated("even", "104 Main St").len(), 1);
+        assert_eq!(interpolated("odd", "103 Main St").len(), 1);
+        assert!(interpolated("odd", "104 Main St").is_empty());
+        assert!(interpolated("alphabetic", "103 Main St").is_empty());
+        assert!(interpolated("unknown", "103 Main St").is_empty());
+    }
+
+    #[test]
+    fn test_remove() {
+        let geo_tiles = geo_tiles(r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {"id": "node/1", "addr:housenumber": "100", "addr:street": "Main Street"}, "geometry": {"type": "Point", "coordinates": [-75.0, 45.0]}},
+            {"type": "Feature", "properties": {"id": "node/2", "addr:housenumber": "110", "addr:street": "Main Street"}, "geometry": {"type": "Point", "coordinates": [-75.001, 45.0]}},
+            {"type": "Feature", "properties": {"id": "node/3", "addr:housenumber": "7", "addr:street": "Elm Street", "addr:postcode": "K1A 0A9"}, "geometry": {"type": "Point", "coordinates": [-75.0, 45.001]}},
+            {"type": "Feature", "properties": {"id": "way/4", "addr:interpolation": "even"}, "geometry": {"type": "LineString", "coordinates": [[-75.0, 45.0], [-75.001, 45.0]]}}
+        ]}"#);
+        let mut index = AddressIndex::new(&geo_tiles, TileGrid::default());
+        assert!(index.find("104 Main St")[0].interpolated);
+        // Only the removed features are forgotten, along with the interpolations they ended.
+        index.remove(&geo_tiles[1..2]);
+        assert!(index.find("110 Main St").is_empty());
+        assert!(index.find("104 Main St").is_empty());
+        assert_eq!(index.find("100 Main St")[0].geo_tiles[0].osm_id(), "node/1");
+        index.remove(&geo_tiles[2..3]);
+        assert!(index.find("K1A 0A9").is_empty());
+        assert!(!index.streets.contains_key("elm street") && !index.postcodes.contains_key("K1A0A9"));
+        // Added back, the interpolation is located again.
+        index.add(&geo_tiles[1..3]);
+        assert!(index.find("104 Main St")[0].interpolated);
+        assert_eq!(index.find("7 Elm St, K1A 0A9").len(), 1);
+        index.remove(&geo_tiles);
+        assert!(index.entries.is_empty() && index.locations.is_empty() && index.osm_ids.is_empty() && index.interpolations.is_empty());
+    }
+
+    #[test]
+    fn test_normalize_street() {
+        assert_eq!(normalize_street("Main St."), "main street");
+        assert_eq!(normalize_street("  Rideau   Cres "), "rideau crescent");
+        assert_eq!(split_house_number("12B Bank St"), (Some("12b".to_string()), Some("bank street".to_string())));
+        assert_eq!(split_house_number("Bank Street"), (None, Some("bank street".to_string())));
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    env::{ self, temp_dir },
+    fs,
+    path::{ Path, PathBuf },
+    time::{ Duration, SystemTime },
+};
+use log::warn;
+
+use crate::{error::Error, features::TILE_SCALE, operations::to_tile_scale};
+
+// On-disk cache of the GeoJSON files produced from downloaded areas.
+// Entries are named after their bounding box in tile scale (bbox_<left>_<bottom>_<right>_<top>.geojson),
+// so requests for an area covered by a previously downloaded one are served without a download.
+// Caching is opt-in (Settings.cache is None by default), the default directory is per user.
+#[derive(Debug, Clone)]
+pub struct CacheSettings {
+    pub directory: PathBuf,
+    // Entries older than this are downloaded again (unless offline).
+    pub expiry: Option<Duration>,
+    // The oldest entries are removed once the cache grows larger than this (in bytes).
+    pub max_size: Option<u64>,
+}
+
+impl Default for CacheSettings {
+    fn default() -> CacheSettings {
+        CacheSettings {
+            directory: user_cache_directory(),
+            expiry: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+            max_size: Some(512 * 1024 * 1024),
+        }
+    }
+}
+
+// osm-geo-mapper in the per-user cache directory ($XDG_CACHE_HOME or ~/.cache, ~/Library/Caches on macOS,
+// %LOCALAPPDATA% on Windows), falling back to the temporary directory when none is known.
+fn user_cache_directory() -> PathBuf {
+    let env_directory = |name: &str| env::var_os(name).map(PathBuf::from).filter(|path| path.is_absolute());
+    let base = if cfg!(windows) {
+        env_directory("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_directory("HOME").map(|home| home.join("Library").join("Caches"))
+    } else {
+        env_directory("XDG_CACHE_HOME").or_else(|| env_directory("HOME").map(|home| home.join(".cache")))
+    };
+    base.unwrap_or_else(temp_dir).join("osm-geo-mapper")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CachedBbox {
+    left: i32,
+    bottom: i32,
+    right: i32,
+    top: i32,
+}
+
+impl CachedBbox {
+    fn new(left: f64, bottom: f64, right: f64, top: f64) -> CachedBbox {
+        CachedBbox {
+            left: to_tile_scale(left, TILE_SCALE),
+            bottom: to_tile_scale(bottom, TILE_SCALE),
+            right: to_tile_scale(right, TILE_SCALE),
+            top: to_tile_scale(top, TILE_SCALE),
+        }
+    }
+
+    fn from_file_name(file_name: &str) -> Option<CachedBbox> {
+        let mut parts = file_name.strip_prefix("bbox_")?.strip_suffix(".geojson")?.split('_');
+        let mut next = || -> Option<i32> { parts.next()?.parse().ok() };
+        let bbox = CachedBbox { left: next()?, bottom: next()?, right: next()?, top: next()? };
+        Some(bbox)
+    }
+
+    fn file_name(&self) -> String {
+        format!("bbox_{}_{}_{}_{}.geojson", self.left, self.bottom, self.right, self.top)
+    }
+
+    fn contains(&self, other: &CachedBbox) -> bool {
+        self.left <= other.left && self.bottom <= other.bottom && self.right >= other.right && self.top >= other.top
+    }
+}
+
+fn is_fresh(path: &Path, expiry: Option<Duration>) -> bool {
+    let expiry = match expiry {
+        Some(expiry) => expiry,
+        None => return true,
+    };
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified());
+    match modified.map(|modified| SystemTime::now().duration_since(modified)) {
+        Ok(Ok(age)) => age <= expiry,
+        Ok(Err(_)) => true, // Modified in the future.
+        Err(_) => false,
+    }
+}
+
+// Returns a cached GeoJSON file covering the bounding box. Expired entries are only used when offline.
+pub fn get_cached_geojson_file(
+    cache: &CacheSettings,
+    offline: bool,
+    left: f64,
+    bottom: f64,
+    right: f64,
+    top: f64,
+) -> Option<String> {
+    let requested = CachedBbox::new(left, bottom, right, top);
+    let exact = cache.directory.join(requested.file_name());
+    let entries = fs::read_dir(&cache.directory).ok()?;
+    // Prefer an exact match, then any entry covering the requested area.
+    let candidates = std::iter::once(exact.clone()).chain(
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| *path != exact)
+            .filter(|path| {
+                let file_name = path.file_name().and_then(|file_name| file_name.to_str()).unwrap_or_default();
+                CachedBbox::from_file_name(file_name).is_some_and(|bbox| bbox.contains(&requested))
+            }),
+    );
+    for path in candidates {
+        if path.is_file() && (offline || is_fresh(&path, cache.expiry)) {
+            return path.to_str().map(|path| path.to_string());
+        }
+    }
+    None
+}
+
+// Moves a freshly converted GeoJSON file into the cache and returns its new location.
+pub fn cache_geojson_file(
+    cache: &CacheSettings,
+    geojson_file: &str,
+    left: f64,
+    bottom: f64,
+    right: f64,
+    top: f64,
+) -> Result<String, Error> {
+    fs::create_dir_all(&cache.directory)?;
+    let path = cache.directory.join(CachedBbox::new(left, bottom, right, top).file_name());
+    // Renaming fails across file systems, fall back to copying.
+    if fs::rename(geojson_file, &path).is_err() {
+        fs::copy(geojson_file, &path)?;
+        fs::remove_file(geojson_file)?;
+    }
+    if let Some(max_size) = cache.max_size {
+        if let Err(error) = enforce_max_size(&cache.directory, max_size, &path) {
+            warn!("Could not enforce the cache size limit: {}", error);
+        }
+    }
+    Ok(path.to_str().unwrap().to_string())
+}
+
+// Removes the least recently downloaded entries (except the one just added) until under max_size.
+fn enforce_max_size(directory: &Path, max_size: u64, keep: &Path) -> Result<(), Error> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let file_name = path.file_name().and_then(|file_name| file_name.to_str()).unwrap_or_default();
+        if CachedBbox::from_file_name(file_name).is_none() {
+            continue;
+        }
+        let metadata = fs::metadata(&path)?;
+        entries.push((metadata.modified()?, metadata.len(), path));
+    }
+    let mut size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+    entries.sort_by_key(|(modified, _, _)| *modified);
+    for (_, len, path) in entries {
+        if size <= max_size {
+            break;
+        }
+        if path != keep {
+            fs::remove_file(&path)?;
+            size -= len;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache() {
+        let directory = temp_dir().join(format!("osm-geo-mapper-test-cache-{}", uuid::Uuid::new_v4()));
+        let cache = CacheSettings { directory: directory.clone(), expiry: None, max_size: Some(30) };
+        assert!(get_cached_geojson_file(&cache, false, -75.1, 45.0, -75.0, 45.1).is_none());
+        let download = |contents: &str| {
+            let file = temp_dir().join(format!("{}.geojson", uuid::Uuid::new_v4()));
+            fs::write(&file, contents).unwrap();
+            file.to_str().unwrap().to_string()
+        };
+        let cached = cache_geojson_file(&cache, &download("first area data"), -75.1, 45.0, -75.0, 45.1).unwrap();
+        // Same area, and an area inside of it.
+        assert_eq!(get_cached_geojson_file(&cache, false, -75.1, 45.0, -75.0, 45.1), Some(cached.clone()));
+        assert_eq!(get_cached_geojson_file(&cache, false, -75.08, 45.02, -75.02, 45.08), Some(cached.clone()));
+        assert!(get_cached_geojson_file(&cache, false, -75.2, 45.0, -75.0, 45.1).is_none());
+        // Going over the size limit drops the oldest entry.
+        std::thread::sleep(Duration::from_millis(10));
+        let second = cache_geojson_file(&cache, &download("second area data"), -76.1, 45.0, -76.0, 45.1).unwrap();
+        assert!(!Path::new(&cached).exists());
+        assert!(Path::new(&second).exists());
+        // Expired entries are only served when offline.
+        let expired = CacheSettings { expiry: Some(Duration::from_secs(0)), ..cache };
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(get_cached_geojson_file(&expired, false, -76.1, 45.0, -76.0, 45.1).is_none());
+        assert_eq!(get_cached_geojson_file(&expired, true, -76.1, 45.0, -76.0, 45.1), Some(second));
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_default_directory() {
+        let directory = CacheSettings::default().directory;
+        assert!(directory.ends_with("osm-geo-mapper"));
+        if !cfg!(windows) && env::var_os("HOME").is_some() && env::var_os("XDG_CACHE_HOME").is_none() {
+            assert!(!directory.starts_with(temp_dir()));
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    fs::File,
+    io::{ self, BufRead, BufReader },
+    path::Path,
+};
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+
+// Compressed inputs are recognized by their leading bytes, not their extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Bzip2,
+    Gzip,
+    Zstd,
+}
+
+// Extensions of compressed files, ignored when the format of the content is guessed from the file name.
+pub const COMPRESSION_EXTENSIONS: [&str; 4] = ["bz2", "gz", "zst", "zstd"];
+
+pub fn detect_compression(header: &[u8]) -> Compression {
+    if header.starts_with(b"BZh") {
+        Compression::Bzip2
+    } else if header.starts_with(&[0x1F, 0x8B]) {
+        Compression::Gzip
+    } else if header.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+        Compression::Zstd
+    } else {
+        Compression::None
+    }
+}
+
+// Opens a file, decompressing bzip2, gzip and zstd content on the fly.
+pub fn open_file<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let compression = detect_compression(reader.fill_buf()?);
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+    })
+}
+
+// The extension describing the content of the file ("osm" for both map.osm and map.osm.gz).
+pub fn content_extension(path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    if COMPRESSION_EXTENSIONS.contains(&extension.as_str()) {
+        path.file_stem().and_then(|stem| content_extension(Path::new(stem)))
+    } else {
+        Some(extension)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{ Read, Write };
+
+    #[test]
+    fn test_open_compressed_files() {
+        let content = b"{\"type\": \"FeatureCollection\", \"features\": []}";
+        let directory = std::env::temp_dir().join(format!("osm-geo-mapper-compression-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(content).unwrap();
+        let mut bzip2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bzip2.write_all(content).unwrap();
+        let files = vec![
+            ("plain.geojson", content.to_vec()),
+            ("map.geojson.gz", gzip.finish().unwrap()),
+            ("map.geojson.bz2", bzip2.finish().unwrap()),
+            ("map.geojson.zst", zstd::stream::encode_all(&content[..], 0).unwrap()),
+        ];
+        for (name, bytes) in files {
+            let path = directory.join(name);
+            std::fs::write(&path, bytes).unwrap();
+            let mut decompressed = Vec::new();
+            open_file(&path).unwrap().read_to_end(&mut decompressed).unwrap();
+            assert_eq!(decompressed, content.to_vec(), "{}", name);
+            assert_eq!(content_extension(&path).as_deref(), Some("geojson"));
+        }
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
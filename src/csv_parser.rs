@@ -0,0 +1,70 @@
+use std::path::Path;
+use serde_json::{ Map, Value as JsonValue };
+
+use crate::{ compression, error::ParseError, features::Geometry };
+
+// Extensions of tab separated files, other files are read as comma separated.
+const TSV_EXTENSIONS: [&str; 2] = ["tab", "tsv"];
+
+// Column names looked for (case insensitively) when no geometry column is given.
+const GEOMETRY_COLUMNS: [&str; 5] = ["geometry", "wkt", "wkb", "geom", "the_geom"];
+
+// The tags of a row (every non-empty column but the geometry one) and its geometry.
+pub type CsvRow = (Map<String, JsonValue>, Geometry);
+
+pub type CsvRows = Box<dyn Iterator<Item = Result<CsvRow, ParseError>>>;
+
+// Reads the rows of a CSV/TSV file one at a time. The geometry column holds WKT or hex encoded WKB
+// (eg. PostGIS exports), the other columns are tags (an "id" column is used as the osm_id, csv/<row index> without one).
+pub fn read_csv_rows(csv_file: &str, geometry_column: Option<&str>) -> Result<CsvRows, ParseError> {
+    let to_error = |message: String| ParseError::new(message).in_file(csv_file);
+    let reader = compression::open_file(csv_file).map_err(|error| to_error(error.to_string()))?;
+    let extension = compression::content_extension(Path::new(csv_file)).unwrap_or_default();
+    let delimiter = if TSV_EXTENSIONS.contains(&extension.as_str()) { b'\t' } else { b',' };
+    let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).flexible(true).from_reader(reader);
+    let headers: Vec<String> = reader.headers().map_err(|error| to_error(error.to_string()))?.iter().map(String::from).collect();
+    let geometry_position = match geometry_column {
+        Some(geometry_column) => headers.iter().position(|header| header == geometry_column),
+        None => GEOMETRY_COLUMNS
+            .iter()
+            .find_map(|column| headers.iter().position(|header| header.eq_ignore_ascii_case(column))),
+    };
+    let geometry_position = geometry_position.ok_or_else(|| to_error(match geometry_column {
+        Some(geometry_column) => format!("No {} column", geometry_column),
+        None => format!("No geometry column (one of {})", GEOMETRY_COLUMNS.join(", ")),
+    }))?;
+    let csv_file = csv_file.to_string();
+    let rows = reader.into_records().enumerate().map(move |(index, record)| {
+        let to_error = |message: String, osm_id: Option<String>| ParseError { file: Some(csv_file.clone()), feature_index: Some(index), osm_id, message };
+        let record = record.map_err(|error| to_error(error.to_string(), None))?;
+        let mut properties = Map::new();
+        for (header, value) in headers.iter().zip(record.iter()) {
+            if !value.is_empty() {
+                properties.insert(header.clone(), JsonValue::String(value.to_string()));
+            }
+        }
+        let geometry = properties.remove(&headers[geometry_position]);
+        // Rows without an id are identified by their position (csv/0 is the first row after the headers).
+        let osm_id = properties
+            .entry("id")
+            .or_insert_with(|| JsonValue::String(format!("csv/{}", index)))
+            .as_str()
+            .map(String::from);
+        let geometry = match geometry.as_ref().and_then(JsonValue::as_str) {
+            Some(geometry) => parse_geometry(geometry).map_err(|error| to_error(error.message, osm_id))?,
+            None => return Err(to_error("Missing geometry".to_string(), osm_id)),
+        };
+        Ok((properties, geometry))
+    });
+    Ok(Box::new(rows))
+}
+
+// Hex encoded WKB when the value only has hex digits, WKT otherwise.
+pub fn parse_geometry(geometry: &str) -> Result<Geometry, ParseError> {
+    let geometry = geometry.trim();
+    if geometry.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        Geometry::from_wkb_hex(geometry)
+    } else {
+        Geometry::from_wkt(geometry)
+    }
+}
@@ -0,0 +1,120 @@
+use std::{error, fmt, io, time::Duration};
+
+// Errors returned by the Overpass and Nominatim clients and the file loaders.
+// Higher level functions return them boxed, use `downcast_ref::<Error>()` to inspect them
+// (file loading failures are all Error::Parse).
+#[derive(Debug)]
+pub enum Error {
+    // The service asked us to slow down (HTTP 429, or 504 when Overpass is overloaded).
+    RateLimited { retry_after: Option<Duration> },
+    // The service accepted the request but failed running it (eg. Overpass query timeouts).
+    RemoteRuntimeError(String),
+    // Nominatim had no result for the given address.
+    NoGeocodingResult(String),
+    // The response isn't what we expected (eg. an HTML error page instead of OSM XML).
+    MalformedResponse(String),
+    // The response is larger than the configured maximum size (in bytes).
+    ResponseTooLarge(u64),
+    // Offline mode and the requested data isn't available locally.
+    Offline(String),
+    // A file or one of its features could not be parsed.
+    Parse(ParseError),
+    // Grids need a positive number of cells per degree, see TileGrid::validate.
+    InvalidTileScale(f64),
+    Http(reqwest::Error),
+    Io(io::Error),
+}
+
+// Where parsing failed, as precisely as known: the file, the position of the feature in it and its OSM id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub file: Option<String>,
+    pub feature_index: Option<usize>,
+    pub osm_id: Option<String>,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(message: String) -> ParseError {
+        ParseError { file: None, feature_index: None, osm_id: None, message }
+    }
+
+    pub fn in_file(mut self, file: &str) -> ParseError {
+        self.file = Some(file.to_string());
+        self
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file.as_deref().unwrap_or("<input>"))?;
+        if let Some(feature_index) = self.feature_index {
+            write!(f, ", feature {}", feature_index)?;
+        }
+        if let Some(osm_id) = &self.osm_id {
+            write!(f, " ({})", osm_id)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Error {
+    // Whether trying again later could succeed.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::RateLimited { .. } => true,
+            // Timeouts and connection failures (builder, redirect and status errors won't go away).
+            Error::Http(error) => error.is_timeout() || !(error.is_builder() || error.is_redirect() || error.is_status()),
+            _ => false,
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Parse(error) => Some(error),
+            Error::Http(error) => Some(error),
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::RateLimited { retry_after: Some(retry_after) } => write!(f, "Rate limited, retry after {} seconds", retry_after.as_secs()),
+            Error::RateLimited { retry_after: None } => write!(f, "Rate limited"),
+            Error::RemoteRuntimeError(message) => write!(f, "Remote runtime error: {}", message),
+            Error::NoGeocodingResult(address) => write!(f, "No geocoding result for address: {}", address),
+            Error::MalformedResponse(message) => write!(f, "Malformed response: {}", message),
+            Error::ResponseTooLarge(max_size) => write!(f, "Response larger than {} bytes", max_size),
+            Error::Offline(message) => write!(f, "Offline: {}", message),
+            Error::Parse(error) => write!(f, "Parse error: {}", error),
+            Error::InvalidTileScale(tile_scale) => write!(f, "Invalid tile scale: {}", tile_scale),
+            Error::Http(error) => write!(f, "HTTP error: {}", error),
+            Error::Io(error) => write!(f, "IO error: {}", error),
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Error {
+        Error::Http(error)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Error {
+        Error::Parse(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
@@ -5,8 +5,9 @@ use osmpbfreader::Tags;
 use osm_geo_mapper_macros::{ create_enum, implement_geotile, print_geotile_attributes };
 use osm_xml::Tag;
 use geo_types as gt;
+use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value as JsonValue};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::{fmt, cmp::Ordering, sync::{Arc, RwLock}};
 
@@ -40,8 +41,19 @@ pub mod tourism_feature;
 pub mod water_feature;
 pub mod waterway_feature;
 
+// Default number of grid cells per degree (roughly a meter at the equator), see OSMGeoMapper::tile_scale.
+// Radiuses (eg. OSMGeoMapper::radius) are always expressed in this unit, whatever the grid resolution.
 pub const TILE_SCALE: f64 = 100_000.0;
 pub type GeoTilesDataStructure = Arc<RwLock<HashMap<gt::Coordinate<i32>, Vec<Arc<GeoTile>>>>>;
+// Grid cells each OSM object (by osm_id) was drawn on, so that it can be removed or redrawn later.
+pub type OsmIdCellsDataStructure = Arc<RwLock<HashMap<String, HashSet<gt::Coordinate<i32>>>>>;
+// The GeoTiles of each OSM object (by osm_id), shared with every cell they were drawn on. Most objects have
+// one, multipolygons have one per polygon and multi-segment GPX tracks one per segment.
+pub type OsmIdGeoTilesDataStructure = Arc<RwLock<HashMap<String, Vec<Arc<GeoTile>>>>>;
+// The nodes (id and location, in order) of each loaded OSM way (by osm_id), so that the way can be redrawn when an
+// osmChange diff moves one of its nodes without modifying the way itself.
+pub type WayNodes = HashMap<String, Vec<(i64, gt::Coordinate<f64>)>>;
+pub type WayNodesDataStructure = Arc<RwLock<WayNodes>>;
 
 pub trait GeoTileProperties {
     fn has(&self, key: &str) -> bool;
@@ -65,9 +77,9 @@ impl GeoTileProperties for Map<String, JsonValue> {
             output.push_str(k);
             output.push_str(": ");
             output.push_str(&v.to_string());
-            output.push_str(",");
+            output.push(',');
         }
-        output.push_str("}");
+        output.push('}');
         output
     }
 }
@@ -95,9 +107,9 @@ impl GeoTileProperties for Vec<Tag> {
             output.push_str(tag.key.as_str());
             output.push_str(": ");
             output.push_str(&tag.val.to_string());
-            output.push_str(",");
+            output.push(',');
         }
-        output.push_str("}");
+        output.push('}');
         output
     }
 }
@@ -119,21 +131,21 @@ impl GeoTileProperties for Tags {
             output.push_str(k.as_str());
             output.push_str(": ");
             output.push_str(&v.to_string());
-            output.push_str(",");
+            output.push(',');
         }
-        output.push_str("}");
+        output.push('}');
         output
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Geometry {
     LineString(geo_types::LineString<f64>),
     Point(geo_types::Point<f64>),
     Polygon(geo_types::Polygon<f64>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Address {
     pub house_number: Option<String>,
     pub unit: Option<String>,
@@ -189,9 +201,11 @@ create_enum!(
     SportType [AmericanFootball, Aikido, Archery, Athletics, AustralianFootball, Badminton, Bandy, Baseball, Basketball, Beachvolleyball, Biathlon, Billiards, Bmx, Bobsleigh, Boules, Bowls, Boxing, Bullfighting, CanadianFootball, Canoe, Chess, CliffDiving, Climbing, ClimbingAdventure, Cockfighting, Cricket, Crossfit, Croquet, Curling, Cycling, Darts, DogAgility, DogRacing, Equestrian, Fencing, FieldHockey, Fitness, Floorball, FreeFlying, Futsal, GaelicGames, Golf, Gymnastics, Handball, Hapkido, Horseshoes, HorseRacing, IceHockey, IceSkating, IceStock, Jiu, Judo, Karate, Karting, Kickboxing, Kitesurfing, Korfball, Krachtbal, Lacrosse, MartialArts, MiniatureGolf, ModelAerodrome, Motocross, Motor, Multi, Netball, NinePin, ObstacleCourse, Orienteering, PaddleTennis, Padel, Parachuting, Parkour, Pelota, Pesapallo, Pickleball, Pilates, PoleDance, Racquet, RcCar, RollerSkating, Rowing, RugbyLeague, RugbyUnion, Running, Sailing, ScubaDiving, Shooting, Shot, Skateboard, SkiJumping, Skiing, Snooker, Soccer, Speedway, Squash, Sumo, Surfing, Swimming, TableTennis, TableSoccer, Taekwondo, Tennis, TenPin, Toboggan, Ultimate, Unclassified, Volleyball, Wakeboarding, WaterPolo, WaterSki, Weightlifting, Wrestling, Yoga],
     TelecomType [ConnectionPoint, DataCenter, DistributionPoint, Exchange, ServiceDevice, Unclassified],
     TourismType [AlpineHut, Apartment, Aquarium, Artwork, Attraction, CampPitch, CampSite, CaravanSite, Chalet, Gallery, GuestHouse, Hostel, Hotel, Information, Motel, Museum, PicnicSite, ThemePark, Tourism, Unclassified, Viewpoint, WildernessHut, Zoo],
-    UnclassifiedType [Unclassified],
+    TrackType [Route, Track],
+    UnclassifiedType [AddressInterpolation, Unclassified],
     WaterType [Basin, Canal, Ditch, FishPass, Lagoon, Lake, Lock, Moat, Oxbow, Pond, ReflectingPool, Reservoir, River, StreamPool, Unclassified, Wastewater],
     WaterwayType [Boatyard, Canal, Dam, Ditch, Dock, Drain, Fairway, Fuel, LockGate, Pressurised, River, Riverbank, Stream, TidalChannel, TurningPoint, Unclassified, Waterfall, WaterPoint, Weir],
+    WaypointType [Waypoint],
 );
 
 implement_geotile!(
@@ -222,9 +236,12 @@ implement_geotile!(
     Sport [access, alt_name, archery, area, athletics, baseball, billiards, boules, capacity, climbing, club, cricket_nets, darts, depth, ele, height, hoops, lanes, length, lit, name, note, opening_hours, operator, shooting, source, surface, takeoff, tidal, wave, website, width],
     Telecom [capacity, connection_point, location, manufacturer, name, operator, owner, street_cabinet, support],
     Tourism [name, access, artist_name, artwork_subject, artwork_type, attraction, backcountry, balcony, bar, beds, bbq, brand, cabins, camp_site, capacity, caravans, contact, covered, description, dog, drinking_water, ele, electricity, email, exhibit, fee, fireplace, group_only, heritage, hot_water, information, internet_access, kitchen, lit, material, mattress, motor_vehicle, museum, museum_type, nudism, number_of_apartments, openfire, opening_hours, operator, parking, payment, permanent_camping, picnic_table, phone, power_supply, reservation, rooms, sanitary_dump_station, scout, shower, smoking, stars, start_date, static_caravans, subject, surface, swimming_pool, tents, toilets, washing_machine, waste_disposal, website, wheelchair, wikipedia, winter_room, zoo],
-    Unclassified [],
+    // Overlays loaded from GPX files (see gpx_parser), not OSM features.
+    Track [name, description, start_time, end_time, min_elevation, max_elevation],
+    Unclassified [interpolation],
     Water [basin, intermittent, lock, name, reservoir_type, salt, seasonal],
     Waterway [access, boat, canoe, cemt, depth, diameter, dock, draft, fuel, height, industrial, intermittent, layer, location, lock, maxheight, maxlength, maxspeed, maxwidth, motorboat, name, operator, salt, ship, tidal, tunnel, usage, width],
+    Waypoint [name, description, time, elevation, symbol],
 );
 
 // Another option for sorting is to have add a "priority" int field to GeoTile types and sort by that int.
@@ -264,7 +281,9 @@ pub fn geotile_sort(a: &Arc<GeoTile>, b: &Arc<GeoTile>) -> Ordering {
         GeoTile::Place { .. } |
         GeoTile::Railway { .. } |
         GeoTile::Route { .. } |
-        GeoTile::Unclassified { .. } => Ordering::Less,
+        GeoTile::Track { .. } |
+        GeoTile::Unclassified { .. } |
+        GeoTile::Waypoint { .. } => Ordering::Less,
         // 2nd highest priority for display.
         GeoTile::Building { .. } |
         GeoTile::Boundary { .. } |
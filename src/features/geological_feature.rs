@@ -1,6 +1,7 @@
 use crate::{
     features::{GeoTile, GeoTileProperties, GeoTilesDataStructure, Geometry, GeologicalType},
     operations::{line_string_operations::draw_line, address_from_properties, property_to_option_string},
+    projection::TileGrid,
 };
 use osm_geo_mapper_macros::{ extract_type_from_string, geotile_from_properties };
 use paste::paste; // Required for the extract_type_from_string macro.
@@ -19,6 +20,7 @@ pub fn draw_geological_line_string(
     data_structure: GeoTilesDataStructure,
     _geological_type: GeologicalType,
     line_string: gt::LineString<f64>,
+    grid: TileGrid,
 ) {
     let points = line_string.into_points();
     let mut first_iteration = true;
@@ -28,7 +30,7 @@ pub fn draw_geological_line_string(
             first_iteration = false;
             continue;
         }
-        draw_line(&last_point, &point, 1, geo_tile.clone(), data_structure.clone());
+        draw_line(&last_point, &point, 1, geo_tile.clone(), data_structure.clone(), grid);
         last_point = point;
     }
 }
@@ -1,6 +1,7 @@
 use crate::{
     features::{GeoTile, GeoTileProperties, GeoTilesDataStructure, Geometry, HighwayType},
     operations::{line_string_operations::draw_line, address_from_properties, property_to_option_string},
+    projection::TileGrid,
 };
 use osm_geo_mapper_macros::{ extract_type_from_string, geotile_from_properties };
 use paste::paste; // Required for the extract_type_from_string macro.
@@ -24,6 +25,7 @@ pub fn draw_highway_line_string(
     data_structure: GeoTilesDataStructure,
     highway_type: HighwayType,
     line_string: gt::LineString<f64>,
+    grid: TileGrid,
 ) {
     let points = line_string.into_points();
     let mut first_iteration = true;
@@ -75,6 +77,7 @@ pub fn draw_highway_line_string(
             thickness,
             geo_tile.clone(),
             data_structure.clone(),
+            grid,
         );
 
         
@@ -1,6 +1,7 @@
 use crate::{
     features::{GeoTile, GeoTileProperties, GeoTilesDataStructure, Geometry, RouteType},
     operations::{line_string_operations::draw_line, address_from_properties, property_to_option_string},
+    projection::TileGrid,
 };
 use osm_geo_mapper_macros::{ extract_type_from_string, geotile_from_properties };
 use paste::paste; // Required for the extract_type_from_string macro.
@@ -9,10 +10,7 @@ use log::warn;
 use std::sync::Arc;
 
 pub fn get_route_geo_tile(props: &dyn GeoTileProperties, geometry: Geometry, route: Option<&str>) -> GeoTile {
-    let mut route_type_key = "route";
-    if route.is_some() {
-        route_type_key = route.unwrap();
-    }
+    let route_type_key = route.unwrap_or("route");
     let route_type_str = props.fetch(route_type_key).unwrap_or("route");
     let route_type = extract_type_from_string!(route_type_str<props> => RouteType [Bicycle, Bus, Canoe, Detour, Ferry, Foot, Hiking, Horse, IceSkate, InlineSkates, LightRail, MTB, Piste, Power, Railway, Road, Running, Ski, Subway, Train, Tracks, Tram, Trolleybus, Unclassified]);
     geotile_from_properties!(geometry<props> => Route<route_type> [name, area, bicycle, colour, description, distance, duration, fee, foot, from, lit, network, oneway, operator, piste_difficulty, piste_type, roundtrip, seasonal, symbol, to]);
@@ -23,6 +21,7 @@ pub fn draw_route_line_string(
     data_structure: GeoTilesDataStructure,
     route_type: RouteType,
     line_string: gt::LineString<f64>,
+    grid: TileGrid,
 ) {
     let points = line_string.into_points();
     let mut first_iteration = true;
@@ -51,6 +50,7 @@ pub fn draw_route_line_string(
             thickness,
             geo_tile.clone(),
             data_structure.clone(),
+            grid,
         );
         last_point = point;
     }
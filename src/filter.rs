@@ -0,0 +1,148 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use crate::features::GeoTileProperties;
+
+// Tags that do not make an object worth displaying on their own (shared by the OSM XML and PBF processing).
+pub const UNINTERESTING_TAGS: [&str; 9] = [
+    "source",
+    "source_ref",
+    "source:ref",
+    "history",
+    "attribution",
+    "created_by",
+    "tiger:county",
+    "tiger:tlid",
+    "tiger:upload_uuid",
+];
+
+// GeoTile kinds and the tag keys that produce them (see the *_feature_to_geo_tile functions).
+const GEOTILE_KIND_KEYS: [(&str, &[&str]); 29] = [
+    ("Aerialway", &["aerialway"]),
+    ("Aeroway", &["aeroway"]),
+    ("Amenity", &["amenity"]),
+    ("Barrier", &["barrier"]),
+    ("Boundary", &["boundary"]),
+    ("Building", &["building", "building:part"]),
+    ("Craft", &["craft"]),
+    ("Emergency", &["emergency"]),
+    ("Geological", &["geological"]),
+    ("Healthcare", &["healthcare"]),
+    ("Highway", &["highway"]),
+    ("Historic", &["historic"]),
+    ("Landuse", &["landuse", "landcover"]),
+    ("Leisure", &["leisure"]),
+    ("ManMade", &["man_made"]),
+    ("Military", &["military"]),
+    ("Natural", &["natural"]),
+    ("Office", &["office"]),
+    ("Place", &["place"]),
+    ("Power", &["power"]),
+    ("PublicTransport", &["public_transport"]),
+    ("Railway", &["railway"]),
+    ("Route", &["route", "piste:type"]),
+    ("Shop", &["shop"]),
+    ("Sport", &["sport"]),
+    ("Telecom", &["telecom"]),
+    ("Tourism", &["tourism"]),
+    ("Water", &["water"]),
+    ("Waterway", &["waterway"]),
+];
+
+#[derive(Debug)]
+pub struct FilterParseError {
+    message: String
+}
+impl Error for FilterParseError {}
+impl fmt::Display for FilterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterRule {
+    Key(String),
+    KeyValue(String, String),
+}
+
+impl FilterRule {
+    fn matches(&self, tags: &dyn GeoTileProperties) -> bool {
+        match self {
+            FilterRule::Key(key) => tags.has(key),
+            FilterRule::KeyValue(key, val) => tags.fetch(key) == Some(val.as_str()),
+        }
+    }
+}
+
+// Keeps the objects matching any of its rules.
+// Parsed from a comma separated list of tag keys (highway), key=value pairs (amenity=cafe)
+// and GeoTile kinds (Building, ManMade, etc.) - eg. "Building,highway,amenity=cafe".
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    pub rules: Vec<FilterRule>,
+}
+
+impl Filter {
+    pub fn new(rules: Vec<FilterRule>) -> Filter {
+        Filter { rules }
+    }
+
+    pub fn matches(&self, tags: &dyn GeoTileProperties) -> bool {
+        self.rules.iter().any(|rule| rule.matches(tags))
+    }
+}
+
+// Convenience for the optional filters taken by the parsing/processing functions.
+pub fn matches(filter: Option<&Filter>, tags: &dyn GeoTileProperties) -> bool {
+    filter.map_or(true, |filter| filter.matches(tags))
+}
+
+impl FromStr for Filter {
+    type Err = FilterParseError;
+
+    fn from_str(spec: &str) -> Result<Filter, FilterParseError> {
+        let mut rules = Vec::new();
+        for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            if let Some((key, val)) = item.split_once('=') {
+                rules.push(FilterRule::KeyValue(key.trim().to_string(), val.trim().to_string()));
+            } else if item.starts_with(char::is_uppercase) {
+                let keys = GEOTILE_KIND_KEYS
+                    .iter()
+                    .find(|(kind, _)| *kind == item)
+                    .map(|(_, keys)| keys)
+                    .ok_or_else(|| FilterParseError { message: format!("Unknown GeoTile kind in filter: {}", item) })?;
+                rules.extend(keys.iter().map(|key| FilterRule::Key(key.to_string())));
+            } else {
+                rules.push(FilterRule::Key(item.to_string()));
+            }
+        }
+        if rules.is_empty() {
+            return Err(FilterParseError { message: "Empty filter".to_string() });
+        }
+        Ok(Filter::new(rules))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let filter: Filter = "Building, highway,amenity=cafe".parse().unwrap();
+        assert_eq!(filter.rules, vec![
+            FilterRule::Key("building".to_string()),
+            FilterRule::Key("building:part".to_string()),
+            FilterRule::Key("highway".to_string()),
+            FilterRule::KeyValue("amenity".to_string(), "cafe".to_string()),
+        ]);
+        let tags = |json: serde_json::Value| json.as_object().unwrap().clone();
+        assert!(filter.matches(&tags(serde_json::json!({ "highway": "residential" }))));
+        assert!(filter.matches(&tags(serde_json::json!({ "building:part": "yes" }))));
+        assert!(filter.matches(&tags(serde_json::json!({ "amenity": "cafe" }))));
+        assert!(!filter.matches(&tags(serde_json::json!({ "amenity": "bar" }))));
+        assert!(!filter.matches(&tags(serde_json::json!({}))));
+        assert!("Unknown".parse::<Filter>().is_err());
+        assert!(" , ".parse::<Filter>().is_err());
+    }
+}
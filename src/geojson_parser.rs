@@ -1,17 +1,20 @@
 use std::{
-    fs::File,
-    io::{ BufReader, Read },
+    io::{ BufRead, Read },
+    path::Path,
 };
 use geojson as gj;
+use log::warn;
+use serde_json::Value as JsonValue;
 
-pub fn parse_geojson_file(geojson_file: &str) -> gj::GeoJson {
-    let mut input_file =
-        BufReader::new(File::open(geojson_file).expect("Could not open input file"));
-    let mut geojson_str = "".to_owned();
-    input_file
-        .read_to_string(&mut geojson_str)
-        .expect("Could not read geojson data to string");
-    geojson_str.parse::<gj::GeoJson>().unwrap()
+use crate::{ compression, error::ParseError };
+
+// Fails on the first malformed feature (reporting its position and OSM id).
+pub fn parse_geojson_file(geojson_file: &str) -> Result<gj::GeoJson, ParseError> {
+    let (geojson, mut skipped) = parse_geojson_file_leniently(geojson_file)?;
+    if !skipped.is_empty() {
+        return Err(skipped.remove(0));
+    }
+    Ok(geojson)
     // TODO: Running into issues on Windows where osmtogeojson produces non UTF-8 files.
     //let mut geojson_data = b"".to_owned();
     //input_file
@@ -20,4 +23,420 @@ pub fn parse_geojson_file(geojson_file: &str) -> gj::GeoJson {
     //let geojson_str = UTF_8.decode(&geojson_data, DecoderTrap::Strict).ok().unwrap();
     //let geojson_str_encoded = UTF_8.encode(&geojson_str, DecoderTrap::Strict).unwrap();
     //geojson_str_encoded.parse::<gj::GeoJson>().unwrap()
-}
\ No newline at end of file
+}
+
+// Malformed features of a feature collection are returned instead of failing the whole file.
+// They are replaced by features without a geometry (which are not drawn) so that the positions
+// of the other features stay the same.
+pub fn parse_geojson_file_leniently(geojson_file: &str) -> Result<(gj::GeoJson, Vec<ParseError>), ParseError> {
+    let geojson_str = read_geojson_file(geojson_file)?;
+    let mut value: JsonValue = serde_json::from_str(&geojson_str)
+        .map_err(|error| ParseError::new(error.to_string()).in_file(geojson_file))?;
+    let feature_values = match value.get_mut("features").map(JsonValue::take) {
+        Some(JsonValue::Array(feature_values)) if value["type"] == "FeatureCollection" => feature_values,
+        _ => return Ok((parse_geojson_value(value, geojson_file)?, Vec::new())),
+    };
+    let mut features = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, feature_value) in feature_values.into_iter().enumerate() {
+        let osm_id = feature_value.pointer("/properties/id").and_then(JsonValue::as_str).map(String::from);
+        let properties = feature_value.get("properties").and_then(JsonValue::as_object).cloned();
+        match gj::Feature::from_json_value(feature_value) {
+            Ok(feature) => features.push(feature),
+            Err(error) => {
+                skipped.push(ParseError { file: Some(geojson_file.to_string()), feature_index: Some(index), osm_id, message: error.to_string() });
+                features.push(gj::Feature { bbox: None, geometry: None, id: None, properties, foreign_members: None });
+            }
+        }
+    }
+    let feature_collection = gj::FeatureCollection { bbox: None, features, foreign_members: None };
+    Ok((gj::GeoJson::FeatureCollection(feature_collection), skipped))
+}
+
+fn read_geojson_file(geojson_file: &str) -> Result<String, ParseError> {
+    let to_error = |error: std::io::Error| ParseError::new(error.to_string()).in_file(geojson_file);
+    let mut geojson_str = String::new();
+    compression::open_file(geojson_file).map_err(to_error)?.read_to_string(&mut geojson_str).map_err(to_error)?;
+    Ok(geojson_str)
+}
+
+fn parse_geojson_value(value: JsonValue, geojson_file: &str) -> Result<gj::GeoJson, ParseError> {
+    gj::GeoJson::from_json_value(value).map_err(|error| ParseError::new(error.to_string()).in_file(geojson_file))
+}
+
+// Extensions of newline-delimited GeoJSON (GeoJSONSeq, RFC 8142) files.
+pub const GEOJSON_SEQ_EXTENSIONS: [&str; 5] = ["geojsonl", "geojsons", "geojsonseq", "jsonl", "ndjson"];
+
+// Record separator starting every text of a GeoJSON text sequence (RFC 8142).
+const RECORD_SEPARATOR: u8 = 0x1E;
+
+pub type GeoJsonFeatures = Box<dyn Iterator<Item = Result<gj::Feature, ParseError>>>;
+
+// Reads the features of a GeoJSON (or GeoJSONSeq) file one at a time so that memory stays bounded by
+// the largest feature instead of the whole file. GeoJSONSeq files are recognized by their extension
+// (eg. .geojsonl or .geojsonl.gz) or their leading record separator. Compressed files are decompressed on the fly.
+pub fn read_geojson_features(geojson_file: &str) -> Result<GeoJsonFeatures, ParseError> {
+    let mut reader = compression::open_file(geojson_file).map_err(|error| ParseError::new(error.to_string()).in_file(geojson_file))?;
+    let extension = compression::content_extension(Path::new(geojson_file)).unwrap_or_default();
+    let starts_with_record_separator = reader
+        .fill_buf()
+        .map_err(|error| ParseError::new(error.to_string()).in_file(geojson_file))?
+        .iter()
+        .find(|byte| !byte.is_ascii_whitespace())
+        == Some(&RECORD_SEPARATOR);
+    let features: GeoJsonFeatures = if starts_with_record_separator || GEOJSON_SEQ_EXTENSIONS.contains(&extension.as_str()) {
+        Box::new(GeoJsonSeqReader::new(reader))
+    } else {
+        Box::new(FeatureCollectionReader::new(reader))
+    };
+    let geojson_file = geojson_file.to_string();
+    Ok(Box::new(features.map(move |feature| feature.map_err(|error| error.in_file(&geojson_file)))))
+}
+
+fn feature_from_slice(bytes: &[u8], index: usize) -> Result<gj::Feature, ParseError> {
+    let to_error = |message: String, osm_id: Option<String>| ParseError { file: None, feature_index: Some(index), osm_id, message };
+    let value: JsonValue = serde_json::from_slice(bytes).map_err(|error| to_error(error.to_string(), None))?;
+    let osm_id = value.pointer("/properties/id").and_then(JsonValue::as_str).map(String::from);
+    gj::Feature::from_json_value(value).map_err(|error| to_error(error.to_string(), osm_id))
+}
+
+// Yields the features of a FeatureCollection without holding the rest of the document.
+// Other members (type, bbox, etc.) are skipped, a top-level Feature is yielded on its own.
+// Syntax errors end the iteration since the reader can't find where the next feature starts.
+pub struct FeatureCollectionReader<R: BufRead> {
+    scanner: JsonScanner<R>,
+    state: FeatureCollectionState,
+    // Members other than features, used when the document turns out to be a single Feature.
+    members: Vec<(String, Vec<u8>)>,
+    features_found: bool,
+    index: usize,
+}
+
+#[derive(PartialEq)]
+enum FeatureCollectionState {
+    Start,
+    Members,
+    Features,
+    Done,
+}
+
+impl<R: BufRead> FeatureCollectionReader<R> {
+    pub fn new(reader: R) -> FeatureCollectionReader<R> {
+        FeatureCollectionReader {
+            scanner: JsonScanner { reader },
+            state: FeatureCollectionState::Start,
+            members: Vec::new(),
+            features_found: false,
+            index: 0,
+        }
+    }
+
+    // The outer error is a syntax error (fatal), the inner one a malformed feature (skippable).
+    fn next_feature(&mut self) -> Result<Option<Result<gj::Feature, ParseError>>, String> {
+        loop {
+            match self.state {
+                FeatureCollectionState::Start => {
+                    self.scanner.expect(b'{')?;
+                    self.state = FeatureCollectionState::Members;
+                }
+                FeatureCollectionState::Members => {
+                    match self.scanner.skip_whitespace()? {
+                        Some(b',') => self.scanner.consume(),
+                        Some(b'}') => {
+                            self.scanner.consume();
+                            self.state = FeatureCollectionState::Done;
+                            return Ok(self.single_feature()?.map(Ok));
+                        }
+                        _ => {
+                            let mut key = Vec::new();
+                            self.scanner.read_value(&mut key)?;
+                            let key: String = serde_json::from_slice(&key).map_err(|error| error.to_string())?;
+                            self.scanner.expect(b':')?;
+                            if key == "features" {
+                                self.scanner.expect(b'[')?;
+                                self.features_found = true;
+                                self.state = FeatureCollectionState::Features;
+                            } else {
+                                let mut value = Vec::new();
+                                self.scanner.read_value(&mut value)?;
+                                self.members.push((key, value));
+                            }
+                        }
+                    }
+                }
+                FeatureCollectionState::Features => {
+                    match self.scanner.skip_whitespace()? {
+                        Some(b',') => self.scanner.consume(),
+                        Some(b']') => {
+                            self.scanner.consume();
+                            self.state = FeatureCollectionState::Members;
+                        }
+                        _ => {
+                            let mut feature = Vec::new();
+                            self.scanner.read_value(&mut feature)?;
+                            self.index += 1;
+                            return Ok(Some(feature_from_slice(&feature, self.index - 1)));
+                        }
+                    }
+                }
+                FeatureCollectionState::Done => return Ok(None),
+            }
+        }
+    }
+
+    fn single_feature(&mut self) -> Result<Option<gj::Feature>, String> {
+        if self.features_found {
+            return Ok(None);
+        }
+        let mut object = serde_json::Map::new();
+        for (key, value) in self.members.drain(..) {
+            object.insert(key, serde_json::from_slice(&value).map_err(|error| error.to_string())?);
+        }
+        match gj::GeoJson::from_json_object(object).map_err(|error| error.to_string())? {
+            gj::GeoJson::Feature(feature) => Ok(Some(feature)),
+            _ => {
+                warn!("Found top-level geometry");
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for FeatureCollectionReader<R> {
+    type Item = Result<gj::Feature, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_feature() {
+            Ok(feature) => feature,
+            Err(message) => {
+                let feature_index = if self.state == FeatureCollectionState::Features { Some(self.index) } else { None };
+                self.state = FeatureCollectionState::Done;
+                Some(Err(ParseError { file: None, feature_index, osm_id: None, message }))
+            }
+        }
+    }
+}
+
+// Yields the features of a GeoJSONSeq file, one GeoJSON text per line (optionally preceded by a
+// record separator). Texts spanning several lines are supported when they start with a record separator.
+pub struct GeoJsonSeqReader<R: BufRead> {
+    reader: R,
+    index: usize,
+    record_separated: Option<bool>,
+    // Features of a FeatureCollection text still to be yielded.
+    pending: std::vec::IntoIter<gj::Feature>,
+    done: bool,
+}
+
+impl<R: BufRead> GeoJsonSeqReader<R> {
+    pub fn new(reader: R) -> GeoJsonSeqReader<R> {
+        GeoJsonSeqReader { reader, index: 0, record_separated: None, pending: Vec::new().into_iter(), done: false }
+    }
+
+    fn next_text(&mut self) -> Result<Option<Vec<u8>>, String> {
+        let mut text = Vec::new();
+        loop {
+            let record_separated = match self.record_separated {
+                Some(record_separated) => record_separated,
+                None => {
+                    let buffer = self.reader.fill_buf().map_err(|error| error.to_string())?;
+                    let first = buffer.iter().find(|byte| !byte.is_ascii_whitespace());
+                    *self.record_separated.get_or_insert(first == Some(&RECORD_SEPARATOR))
+                }
+            };
+            let delimiter = if record_separated { RECORD_SEPARATOR } else { b'\n' };
+            let read = self.reader.read_until(delimiter, &mut text).map_err(|error| error.to_string())?;
+            let trimmed = text.iter().filter(|byte| !byte.is_ascii_whitespace() && **byte != RECORD_SEPARATOR).count();
+            if read == 0 {
+                return Ok(if trimmed == 0 { None } else { Some(text) });
+            }
+            if trimmed > 0 {
+                return Ok(Some(text));
+            }
+            text.clear();
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for GeoJsonSeqReader<R> {
+    type Item = Result<gj::Feature, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(feature) = self.pending.next() {
+            return Some(Ok(feature));
+        }
+        if self.done {
+            return None;
+        }
+        let text = match self.next_text() {
+            Ok(Some(text)) => text,
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Err(message) => {
+                self.done = true;
+                return Some(Err(ParseError::new(message)));
+            }
+        };
+        let index = self.index;
+        self.index += 1;
+        let text: Vec<u8> = text.into_iter().filter(|byte| *byte != RECORD_SEPARATOR).collect();
+        // Every text is usually a Feature, FeatureCollections are flattened.
+        let value: JsonValue = match serde_json::from_slice(&text) {
+            Ok(value) => value,
+            Err(error) => return Some(Err(ParseError { file: None, feature_index: Some(index), osm_id: None, message: error.to_string() })),
+        };
+        if value["type"] == "FeatureCollection" {
+            match gj::FeatureCollection::from_json_value(value) {
+                Ok(feature_collection) => {
+                    self.pending = feature_collection.features.into_iter();
+                    self.next()
+                }
+                Err(error) => Some(Err(ParseError { file: None, feature_index: Some(index), osm_id: None, message: error.to_string() })),
+            }
+        } else {
+            let osm_id = value.pointer("/properties/id").and_then(JsonValue::as_str).map(String::from);
+            Some(gj::Feature::from_json_value(value).map_err(|error| ParseError { file: None, feature_index: Some(index), osm_id, message: error.to_string() }))
+        }
+    }
+}
+
+// Minimal JSON tokenizer copying one value at a time (strings and nested containers included).
+struct JsonScanner<R: BufRead> {
+    reader: R,
+}
+
+impl<R: BufRead> JsonScanner<R> {
+    fn peek(&mut self) -> Result<Option<u8>, String> {
+        Ok(self.reader.fill_buf().map_err(|error| error.to_string())?.first().copied())
+    }
+
+    fn consume(&mut self) {
+        self.reader.consume(1);
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>, String> {
+        let byte = self.peek()?;
+        if byte.is_some() {
+            self.consume();
+        }
+        Ok(byte)
+    }
+
+    fn skip_whitespace(&mut self) -> Result<Option<u8>, String> {
+        loop {
+            match self.peek()? {
+                Some(byte) if byte.is_ascii_whitespace() => self.consume(),
+                byte => return Ok(byte),
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), String> {
+        match self.skip_whitespace()? {
+            Some(byte) if byte == expected => {
+                self.consume();
+                Ok(())
+            }
+            Some(byte) => Err(format!("Expected '{}', found '{}'", expected as char, byte as char)),
+            None => Err(format!("Expected '{}', found the end of the file", expected as char)),
+        }
+    }
+
+    fn read_value(&mut self, out: &mut Vec<u8>) -> Result<(), String> {
+        match self.skip_whitespace()? {
+            None => Err("Unexpected end of the file".to_string()),
+            Some(b'"') => self.read_string(out),
+            Some(b'{') | Some(b'[') => {
+                let mut depth = 0;
+                loop {
+                    let byte = self.peek()?.ok_or_else(|| "Unexpected end of the file".to_string())?;
+                    match byte {
+                        b'"' => {
+                            self.read_string(out)?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => depth -= 1,
+                        _ => {}
+                    }
+                    out.push(byte);
+                    self.consume();
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+            }
+            Some(_) => {
+                // Numbers, booleans and null.
+                while let Some(byte) = self.peek()? {
+                    if byte.is_ascii_whitespace() || byte == b',' || byte == b'}' || byte == b']' {
+                        break;
+                    }
+                    out.push(byte);
+                    self.consume();
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn read_string(&mut self, out: &mut Vec<u8>) -> Result<(), String> {
+        out.push(self.next_byte()?.unwrap_or(b'"'));
+        loop {
+            let byte = self.next_byte()?.ok_or_else(|| "Unterminated string".to_string())?;
+            out.push(byte);
+            match byte {
+                b'\\' => out.push(self.next_byte()?.ok_or_else(|| "Unterminated string".to_string())?),
+                b'"' => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(features: Vec<Result<gj::Feature, ParseError>>) -> Vec<Result<String, Option<usize>>> {
+        features
+            .into_iter()
+            .map(|feature| match feature {
+                Ok(feature) => Ok(feature.properties.unwrap()["id"].as_str().unwrap().to_string()),
+                Err(error) => Err(error.feature_index),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_feature_collection_reader() {
+        let geojson = r#"{"type": "FeatureCollection", "bbox": [0, 0, 1, 1], "features": [
+            {"type": "Feature", "properties": {"id": "node/1", "name": "a \"quoted\" ]} name"}, "geometry": {"type": "Point", "coordinates": [1, 2]}},
+            {"type": "Feature", "properties": {"id": "node/2"}, "geometry": {"type": "Pointless", "coordinates": [1, 2]}},
+            {"type": "Feature", "properties": {"id": "node/3"}, "geometry": null}
+        ], "foreign": {"nested": [1, 2, {"a": null}]}}"#;
+        let features: Vec<_> = FeatureCollectionReader::new(geojson.as_bytes()).collect();
+        assert_eq!(ids(features), vec![Ok("node/1".to_string()), Err(Some(1)), Ok("node/3".to_string())]);
+        // A single feature works too.
+        let geojson = r#"{"properties": {"id": "way/1"}, "type": "Feature", "geometry": {"type": "Point", "coordinates": [1, 2]}}"#;
+        let features: Vec<_> = FeatureCollectionReader::new(geojson.as_bytes()).collect();
+        assert_eq!(ids(features), vec![Ok("way/1".to_string())]);
+        // Syntax errors end the iteration.
+        let geojson = r#"{"type": "FeatureCollection", "features": [{"type": "Feature", "properties": {"id": "node/1"}, "geometry": null}, {"type": "#;
+        let features: Vec<_> = FeatureCollectionReader::new(geojson.as_bytes()).collect();
+        assert_eq!(ids(features), vec![Ok("node/1".to_string()), Err(Some(1))]);
+    }
+
+    #[test]
+    fn test_geojson_seq_reader() {
+        let geojson_seq = "{\"type\": \"Feature\", \"properties\": {\"id\": \"node/1\"}, \"geometry\": null}\n\n{\"type\": \"Feature\"\n{\"type\": \"Feature\", \"properties\": {\"id\": \"node/2\"}, \"geometry\": null}\n";
+        let features: Vec<_> = GeoJsonSeqReader::new(geojson_seq.as_bytes()).collect();
+        assert_eq!(ids(features), vec![Ok("node/1".to_string()), Err(Some(1)), Ok("node/2".to_string())]);
+        // Record separated texts can span several lines.
+        let geojson_seq = "\x1e{\"type\": \"Feature\",\n \"properties\": {\"id\": \"node/1\"}, \"geometry\": null}\n\x1e{\"type\": \"FeatureCollection\", \"features\": [{\"type\": \"Feature\", \"properties\": {\"id\": \"node/2\"}, \"geometry\": null}]}\n";
+        let features: Vec<_> = GeoJsonSeqReader::new(geojson_seq.as_bytes()).collect();
+        assert_eq!(ids(features), vec![Ok("node/1".to_string()), Ok("node/2".to_string())]);
+    }
+}
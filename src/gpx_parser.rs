@@ -0,0 +1,131 @@
+use std::io::Read;
+use xml::reader::{ EventReader, XmlEvent };
+
+use crate::{ compression, error::ParseError };
+
+// Waypoints, tracks and routes of a GPX file (https://www.topografix.com/GPX/1/1/).
+#[derive(Debug, Clone, Default)]
+pub struct Gpx {
+    pub waypoints: Vec<GpxPoint>,
+    pub tracks: Vec<GpxPath>,
+    pub routes: Vec<GpxPath>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GpxPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub elevation: Option<f64>,
+    pub time: Option<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub symbol: Option<String>,
+}
+
+// A track made of segments (recording interruptions) or a route, which always has a single segment.
+#[derive(Debug, Clone, Default)]
+pub struct GpxPath {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub segments: Vec<Vec<GpxPoint>>,
+}
+
+pub fn parse_gpx_file(filename: &str) -> Result<Gpx, ParseError> {
+    let file = compression::open_file(filename).map_err(|error| ParseError::new(error.to_string()).in_file(filename))?;
+    parse_gpx(file).map_err(|error| error.in_file(filename))
+}
+
+pub fn parse_gpx<R: Read>(source: R) -> Result<Gpx, ParseError> {
+    let mut gpx = Gpx::default();
+    let mut path: Option<GpxPath> = None;
+    let mut point: Option<GpxPoint> = None;
+    let mut text = String::new();
+    for event in EventReader::new(source) {
+        match event.map_err(|error| ParseError::new(error.to_string()))? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                text.clear();
+                match name.local_name.as_str() {
+                    "wpt" | "trkpt" | "rtept" => {
+                        let coordinate = |key: &str| -> Result<f64, ParseError> {
+                            attributes
+                                .iter()
+                                .find(|attribute| attribute.name.local_name == key)
+                                .and_then(|attribute| attribute.value.parse().ok())
+                                .filter(|value: &f64| value.is_finite())
+                                .ok_or_else(|| ParseError::new(format!("<{}> without a valid {} attribute", name.local_name, key)))
+                        };
+                        point = Some(GpxPoint { latitude: coordinate("lat")?, longitude: coordinate("lon")?, ..GpxPoint::default() });
+                    }
+                    "trk" => path = Some(GpxPath::default()),
+                    "rte" => path = Some(GpxPath { segments: vec![Vec::new()], ..GpxPath::default() }),
+                    "trkseg" => {
+                        if let Some(path) = path.as_mut() {
+                            path.segments.push(Vec::new());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::Characters(characters) | XmlEvent::CData(characters) => text.push_str(&characters),
+            XmlEvent::EndElement { name } => {
+                let value = || Some(text.trim().to_string()).filter(|value| !value.is_empty());
+                match (name.local_name.as_str(), point.as_mut(), path.as_mut()) {
+                    ("ele", Some(point), _) => point.elevation = value().and_then(|value| value.parse().ok()),
+                    ("time", Some(point), _) => point.time = value(),
+                    ("sym", Some(point), _) => point.symbol = value(),
+                    ("name", Some(point), _) => point.name = value(),
+                    ("desc", Some(point), _) => point.description = value(),
+                    ("name", None, Some(path)) => path.name = value(),
+                    ("desc", None, Some(path)) => path.description = value(),
+                    ("wpt", ..) => gpx.waypoints.extend(point.take()),
+                    ("trkpt", _, Some(path)) | ("rtept", _, Some(path)) => {
+                        if let (Some(segment), Some(point)) = (path.segments.last_mut(), point.take()) {
+                            segment.push(point);
+                        }
+                    }
+                    ("trk", ..) => gpx.tracks.extend(path.take()),
+                    ("rte", ..) => gpx.routes.extend(path.take()),
+                    _ => {}
+                }
+                text.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(gpx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gpx() {
+        let gpx = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+              <metadata><name>Survey</name></metadata>
+              <wpt lat="45.0" lon="-75.0"><ele>70.5</ele><name>Bench</name><sym>Flag</sym></wpt>
+              <trk>
+                <name>Morning walk</name>
+                <trkseg>
+                  <trkpt lat="45.0" lon="-75.0"><time>2020-07-01T10:00:00Z</time></trkpt>
+                  <trkpt lat="45.001" lon="-75.001"><time>2020-07-01T10:05:00Z</time></trkpt>
+                </trkseg>
+                <trkseg><trkpt lat="45.002" lon="-75.002"/></trkseg>
+              </trk>
+              <rte><rtept lat="45.0" lon="-75.0"/><rtept lat="45.0" lon="-75.01"><name>Turn</name></rtept></rte>
+            </gpx>"#;
+        let gpx = parse_gpx(gpx.as_bytes()).unwrap();
+        assert_eq!(gpx.waypoints.len(), 1);
+        assert_eq!(gpx.waypoints[0].elevation, Some(70.5));
+        assert_eq!(gpx.waypoints[0].name.as_deref(), Some("Bench"));
+        assert_eq!(gpx.waypoints[0].symbol.as_deref(), Some("Flag"));
+        assert_eq!(gpx.tracks.len(), 1);
+        assert_eq!(gpx.tracks[0].name.as_deref(), Some("Morning walk"));
+        assert_eq!(gpx.tracks[0].segments.iter().map(Vec::len).collect::<Vec<usize>>(), vec![2, 1]);
+        assert_eq!(gpx.tracks[0].segments[0][1].time.as_deref(), Some("2020-07-01T10:05:00Z"));
+        assert_eq!(gpx.routes.len(), 1);
+        assert_eq!(gpx.routes[0].segments[0][1].name.as_deref(), Some("Turn"));
+        assert!(parse_gpx(r#"<gpx><wpt lat="north" lon="-75.0"/></gpx>"#.as_bytes()).is_err());
+    }
+}
@@ -1,13 +1,29 @@
 use log::warn;
 use geo_types;
-use std::sync::Arc;
+use osmpbfreader::{ OsmId, OsmObj };
+use std::{ collections::{ BTreeMap, HashMap, HashSet }, path::Path, sync::{ Arc, RwLock } };
 use crate::{
-    features::{ GeoTile, GeoTilesDataStructure },
+    address_index::{ AddressIndex, AddressMatch },
+    compression,
+    csv_parser,
+    error::{ Error, ParseError },
+    features::{ GeoTile, GeoTilesDataStructure, OsmIdCellsDataStructure, OsmIdGeoTilesDataStructure, WayNodesDataStructure, TILE_SCALE },
+    filter::Filter,
     geojson_parser,
+    gpx_parser,
+    mbtiles_parser,
     nominatim,
+    o5m_parser,
     operations,
+    opl_parser,
+    osc_parser,
     osm_parser,
+    osmtogeojson,
     pbf_parser,
+    projection::{ Projection, TileGrid },
+    settings::Settings,
+    snapshot,
+    spatial_index::SpatialIndex,
 };
 
 #[derive(Debug, Clone)]
@@ -19,146 +35,551 @@ pub enum Location {
     Center
 }
 
+// Restricts which part of a (potentially very large) file gets loaded.
+#[derive(Debug, Clone)]
+pub enum Area {
+    BoundingBox {
+        left: f64,
+        bottom: f64,
+        right: f64,
+        top: f64
+    },
+    Radius {
+        latitude: f64,
+        longitude: f64,
+        radius: u32
+    }
+}
+
+impl Area {
+    // Returns (left, bottom, right, top), the same order used for Overpass bounding boxes.
+    pub fn to_bbox(&self) -> (f64, f64, f64, f64) {
+        match *self {
+            Area::BoundingBox { left, bottom, right, top } => (left, bottom, right, top),
+            Area::Radius { latitude, longitude, radius } => {
+                let radiusf = operations::from_tile_scale(radius as i32, TILE_SCALE);
+                (longitude - radiusf, latitude - radiusf, longitude + radiusf, latitude + radiusf)
+            }
+        }
+    }
+}
+
+// How the OSMGeoMapper constructors load their data, by default loading fails on the first malformed feature.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadOptions {
+    // Skip the malformed features (collected in OSMGeoMapper::skipped_features) instead of failing,
+    // the mapper keeps doing so in the load_more_* methods.
+    pub lenient: bool,
+    // The grid the features are drawn on (the mapper's tile_scale and projection), see OSMGeoMapper::set_grid.
+    pub grid: TileGrid,
+}
+
+// File formats the mapper can load, see FileFormat::from_path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    Osm,
+    O5m,
+    Opl,
+    Pbf,
+    GeoJson,
+    Gpx,
+    Csv,
+    MBTiles,
+}
+
+impl FileFormat {
+    // Detected from the extension, ignoring compression extensions (eg. map.osm.bz2 is an OSM XML file).
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<FileFormat> {
+        let extension = compression::content_extension(path.as_ref())?;
+        match extension.as_str() {
+            "osm" | "xml" => Some(FileFormat::Osm),
+            "o5m" => Some(FileFormat::O5m),
+            "opl" => Some(FileFormat::Opl),
+            "pbf" => Some(FileFormat::Pbf),
+            "geojson" | "json" => Some(FileFormat::GeoJson),
+            extension if geojson_parser::GEOJSON_SEQ_EXTENSIONS.contains(&extension) => Some(FileFormat::GeoJson),
+            "gpx" => Some(FileFormat::Gpx),
+            "csv" | "tsv" | "tab" => Some(FileFormat::Csv),
+            "mbtiles" => Some(FileFormat::MBTiles),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct OSMGeoMapper {
     pub data_structure: GeoTilesDataStructure,
     pub coordinates: geo_types::Coordinate<i32>,
-    pub radius: u32
+    // Radius (in 100,000th of a degree) of the areas fetched around the coordinates.
+    pub radius: u32,
+    // Number of grid cells per degree, TILE_SCALE unless changed with set_tile_scale.
+    pub tile_scale: f64,
+    // How locations are laid out on the grid, Projection::Degrees unless changed with set_projection.
+    pub projection: Projection,
+    pub osm_id_cells: OsmIdCellsDataStructure,
+    pub osm_id_geo_tiles: OsmIdGeoTilesDataStructure,
+    // Nodes of the ways loaded from OSM data (OSM XML, o5m, OPL and PBF files), see apply_osm_change.
+    pub way_nodes: WayNodesDataStructure,
+    // Address lookups and spatial queries over the loaded features, updated whenever features are loaded or removed.
+    pub address_index: Arc<RwLock<AddressIndex>>,
+    pub spatial_index: Arc<RwLock<SpatialIndex>>,
+    // Used when fetching more data from the Overpass and Nominatim services.
+    pub settings: Settings,
+    // The load_more_* methods skip malformed features (recorded in skipped_features) instead of failing,
+    // set with LoadOptions::lenient when constructing the mapper.
+    pub lenient: bool,
+    pub skipped_features: Arc<RwLock<Vec<ParseError>>>,
+}
+
+// An empty mapper, eg. to load data from several sources with the load_more_* methods.
+impl Default for OSMGeoMapper {
+    fn default() -> OSMGeoMapper {
+        OSMGeoMapper {
+            data_structure: Arc::new(RwLock::new(HashMap::new())),
+            coordinates: geo_types::Coordinate { x: 0, y: 0 },
+            radius: 0,
+            tile_scale: TILE_SCALE,
+            projection: Projection::Degrees,
+            osm_id_cells: Arc::new(RwLock::new(HashMap::new())),
+            osm_id_geo_tiles: Arc::new(RwLock::new(HashMap::new())),
+            way_nodes: Arc::new(RwLock::new(HashMap::new())),
+            address_index: Arc::new(RwLock::new(AddressIndex::default())),
+            spatial_index: Arc::new(RwLock::new(SpatialIndex::default())),
+            settings: Settings::default(),
+            lenient: false,
+            skipped_features: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
 }
 
 impl OSMGeoMapper {
-    pub fn from_geojson_file_with_radius(geojson_file: String, radius: u32, location: Option<Location>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
-        let geojson = geojson_parser::parse_geojson_file(&geojson_file);
-        let data_structure = operations::process_geojson(&geojson);
-        let coordinates = match location {
-            Some(Location::Coordinates { latitude, longitude }) => {
-                geo_types::Coordinate {
-                    x: operations::to_tile_scale(longitude),
-                    y: operations::to_tile_scale(latitude)
-                }
-            },
-            Some(Location::Center) => {
-                warn!("Finding center location of geojson file not supported yet");
-                geo_types::Coordinate { x: 0, y: 0 }
-            },
-            None => {
-                let (x, y) = data_structure.read().unwrap().keys().nth(0).unwrap().x_y();
-                geo_types::Coordinate { x, y }
-            }
-        };
-        Ok(OSMGeoMapper { data_structure, coordinates, radius })
+    // Indexes freshly loaded GeoTiles (drawn on the given grid in data_structure) by osm_id, address and location.
+    fn from_data_structure(
+        data_structure: GeoTilesDataStructure,
+        geo_tiles: Vec<Arc<GeoTile>>,
+        grid: TileGrid,
+        coordinates: geo_types::Coordinate<i32>,
+        radius: u32,
+        options: LoadOptions,
+        skipped: Vec<ParseError>,
+    ) -> OSMGeoMapper {
+        let osm_id_cells = operations::index_osm_id_cells(&data_structure);
+        let osm_id_geo_tiles = operations::index_osm_id_geo_tiles(&geo_tiles);
+        let address_index = Arc::new(RwLock::new(AddressIndex::new(&geo_tiles, grid)));
+        let spatial_index = Arc::new(RwLock::new(SpatialIndex::new(&geo_tiles)));
+        OSMGeoMapper {
+            data_structure,
+            coordinates,
+            radius,
+            tile_scale: grid.scale,
+            projection: grid.projection,
+            osm_id_cells,
+            osm_id_geo_tiles,
+            address_index,
+            spatial_index,
+            lenient: options.lenient,
+            skipped_features: Arc::new(RwLock::new(skipped)),
+            ..OSMGeoMapper::default()
+        }
     }
 
-    pub fn from_geojson_file(geojson_file: String, location: Option<Location>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
-        OSMGeoMapper::from_geojson_file_with_radius(geojson_file, 0, location)
+    pub fn from_geojson_file_with_radius(geojson_file: String, radius: u32, location: Option<Location>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        let options = load_options(options)?;
+        let (data_structure, geo_tiles, skipped) = load_geojson_file(&geojson_file, options.lenient, None, options.grid)?;
+        let coordinates = start_coordinates(location, &None, &data_structure, options.grid, &geojson_file)?;
+        Ok(OSMGeoMapper::from_data_structure(data_structure, geo_tiles, options.grid, coordinates, radius, options, skipped))
     }
 
-    pub fn from_osm_file(osm_file: String, location: Option<Location>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
-        let osm_data = osm_parser::parse_osm_file(osm_file.to_string())?;
-        let radius = 0;
-        let data_structure = operations::process_osm(&osm_data);
-        let coordinates = match location {
-            Some(Location::Coordinates { latitude, longitude }) => {
-                geo_types::Coordinate {
-                    x: operations::to_tile_scale(longitude),
-                    y: operations::to_tile_scale(latitude)
-                }
-            },
-            Some(Location::Center) => {
-                warn!("Finding center location of osm file not supported yet");
-                geo_types::Coordinate { x: 0, y: 0 }
-            },
-            None => {
-                let (x, y) = data_structure.read().unwrap().keys().nth(0).unwrap().x_y();
-                geo_types::Coordinate { x, y }
-            }
+    pub fn from_geojson_file(geojson_file: String, location: Option<Location>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        OSMGeoMapper::from_geojson_file_with_radius(geojson_file, 0, location, options)
+    }
+
+    pub fn from_osm_file(osm_file: String, location: Option<Location>, filter: Option<Filter>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        OSMGeoMapper::from_osm_data_file(osm_file, osm_parser::parse_osm_file, location, filter, options)
+    }
+
+    pub fn from_o5m_file(o5m_file: String, location: Option<Location>, filter: Option<Filter>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        OSMGeoMapper::from_osm_data_file(o5m_file, o5m_parser::parse_o5m_file, location, filter, options)
+    }
+
+    pub fn from_opl_file(opl_file: String, location: Option<Location>, filter: Option<Filter>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        OSMGeoMapper::from_osm_data_file(opl_file, opl_parser::parse_opl_file, location, filter, options)
+    }
+
+    // OSM XML, o5m and OPL files all produce the same node/way/relation model.
+    fn from_osm_data_file(file: String, parse: OsmDataParser, location: Option<Location>, filter: Option<Filter>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        let options = load_options(options)?;
+        let way_nodes: WayNodesDataStructure = Arc::new(RwLock::new(HashMap::new()));
+        let (data_structure, geo_tiles, skipped) = load_osm_data_file(&file, parse, filter.as_ref(), None, options.grid, options.lenient, &way_nodes)?;
+        let coordinates = start_coordinates(location, &None, &data_structure, options.grid, &file)?;
+        Ok(OSMGeoMapper { way_nodes, ..OSMGeoMapper::from_data_structure(data_structure, geo_tiles, options.grid, coordinates, 0, options, skipped) })
+    }
+
+    // The geometry column (WKT or hex encoded WKB) defaults to the first of geometry, wkt, wkb, geom or the_geom.
+    pub fn from_csv_file(csv_file: String, geometry_column: Option<String>, location: Option<Location>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        let options = load_options(options)?;
+        let (data_structure, geo_tiles, skipped) = load_csv_file(&csv_file, geometry_column.as_deref(), None, options.grid, options.lenient)?;
+        let coordinates = start_coordinates(location, &None, &data_structure, options.grid, &csv_file)?;
+        Ok(OSMGeoMapper::from_data_structure(data_structure, geo_tiles, options.grid, coordinates, 0, options, skipped))
+    }
+
+    // Starts from the tracks, routes and waypoints of a GPX file alone (see load_gpx_file).
+    // GPX points are checked when parsing, there are no malformed features to skip.
+    pub fn from_gpx_file(gpx_file: String, location: Option<Location>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        let options = load_options(options)?;
+        let (data_structure, geo_tiles) = load_gpx_file(&gpx_file, options.grid)?;
+        let coordinates = start_coordinates(location, &None, &data_structure, options.grid, &gpx_file)?;
+        Ok(OSMGeoMapper::from_data_structure(data_structure, geo_tiles, options.grid, coordinates, 0, options, Vec::new()))
+    }
+
+    pub fn from_pbf_file(pbf_file: String, location: Option<Location>, area: Option<Area>, filter: Option<Filter>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        let options = load_options(options)?;
+        let way_nodes: WayNodesDataStructure = Arc::new(RwLock::new(HashMap::new()));
+        let (data_structure, geo_tiles, skipped) = load_pbf_file(&pbf_file, &area, filter.as_ref(), None, options.grid, options.lenient, &way_nodes)?;
+        let radius = match area {
+            Some(Area::Radius { radius, .. }) => radius,
+            _ => 0,
         };
-        Ok(OSMGeoMapper {
-            data_structure,
-            coordinates,
-            radius,
-        })
-    }
-
-    pub fn from_pbf_file(pbf_file: String, location: Option<Location>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
-        let pbf_data = pbf_parser::parse_pbf_file(pbf_file.to_string())?;
-        let radius = 0;
-        let data_structure = operations::process_pbf(&pbf_data);
-        let coordinates = match location {
-            Some(Location::Coordinates { latitude, longitude }) => {
-                geo_types::Coordinate {
-                    x: operations::to_tile_scale(longitude),
-                    y: operations::to_tile_scale(latitude)
-                }
-            },
-            Some(Location::Center) => {
-                warn!("Finding center location of pbf file not supported yet");
-                geo_types::Coordinate { x: 0, y: 0 }
-            },
-            None => {
-                let (x, y) = data_structure.read().unwrap().keys().nth(0).unwrap().x_y();
-                geo_types::Coordinate { x, y }
-            }
+        let coordinates = start_coordinates(location, &area, &data_structure, options.grid, &pbf_file)?;
+        Ok(OSMGeoMapper { way_nodes, ..OSMGeoMapper::from_data_structure(data_structure, geo_tiles, options.grid, coordinates, radius, options, skipped) })
+    }
+
+    // Reads the vector tiles of an MBTiles archive (eg. an OpenMapTiles extract) at the given zoom level,
+    // the most detailed one by default. Only the tiles intersecting the area are read when there is one.
+    pub fn from_mbtiles_file(mbtiles_file: String, zoom: Option<u8>, location: Option<Location>, area: Option<Area>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        let options = load_options(options)?;
+        let (data_structure, geo_tiles, skipped) = load_mbtiles_file(&mbtiles_file, zoom, &area, None, options.grid, options.lenient)?;
+        let radius = match area {
+            Some(Area::Radius { radius, .. }) => radius,
+            _ => 0,
         };
-        Ok(OSMGeoMapper {
-            data_structure,
-            coordinates,
-            radius,
-        })
+        let coordinates = start_coordinates(location, &area, &data_structure, options.grid, &mbtiles_file)?;
+        Ok(OSMGeoMapper::from_data_structure(data_structure, geo_tiles, options.grid, coordinates, radius, options, skipped))
+    }
+
+    pub fn from_address(address: String, radius: Option<u32>, settings: Option<Settings>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        let settings = settings.unwrap_or_default();
+        let (latitude, longitude) = nominatim::get_address_lat_lon(address, &settings)?;
+        OSMGeoMapper::from_lat_lon(latitude, longitude, radius, Some(settings), options)
+    }
+
+    // Loads the area covered by the best match's bounding box, limited to max_radius around it.
+    pub fn from_address_bounding_box(address: String, max_radius: Option<u32>, settings: Option<Settings>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        let settings = settings.unwrap_or_default();
+        let candidates = nominatim::get_address_candidates(address, 1, &settings)?;
+        let radius = candidates[0].bounding_box_radius();
+        let radius = max_radius.map_or(radius, |max_radius| radius.min(max_radius));
+        OSMGeoMapper::from_geocoding_candidate(&candidates[0], Some(radius), Some(settings), options)
     }
 
-    pub fn from_address(address: String, radius: Option<u32>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
-        let (latitude, longitude) = nominatim::get_address_lat_lon(address)?;
-        OSMGeoMapper::from_lat_lon(latitude, longitude, radius)
+    // Without a radius the load is sized from the candidate's bounding box.
+    pub fn from_geocoding_candidate(candidate: &nominatim::GeocodingCandidate, radius: Option<u32>, settings: Option<Settings>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        let radius = radius.unwrap_or_else(|| candidate.bounding_box_radius());
+        OSMGeoMapper::from_lat_lon(candidate.latitude, candidate.longitude, Some(radius), settings, options)
     }
 
-    pub fn from_lat_lon(latitude: f64, longitude: f64, radius: Option<u32>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+    pub fn from_lat_lon(latitude: f64, longitude: f64, radius: Option<u32>, settings: Option<Settings>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        let settings = settings.unwrap_or_default();
         let rad = radius.unwrap_or(200);
-        let radiusf = operations::from_tile_scale(rad as i32);
-        let geojson_file = operations::get_geojson_file_by_lat_lon(latitude, longitude, radiusf)?;
-        OSMGeoMapper::from_geojson_file_with_radius(
-            geojson_file,
+        let radiusf = operations::from_tile_scale(rad as i32, TILE_SCALE);
+        let geojson_file = operations::get_geojson_file_by_lat_lon(latitude, longitude, radiusf, &settings)?;
+        let mut mapper = OSMGeoMapper::from_geojson_file_with_radius(
+            geojson_file.path().to_string(),
             rad,
             Some(
                 Location::Coordinates {
                     latitude,
                     longitude
                 }
-            )
-        )
+            ),
+            options
+        )?;
+        mapper.settings = settings;
+        Ok(mapper)
+    }
+
+    // See openstreetmap::download_osm_data_by_overpass_query for the expected query format.
+    pub fn from_overpass_query(query: String, location: Option<Location>, settings: Option<Settings>, options: Option<LoadOptions>) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        let settings = settings.unwrap_or_default();
+        let geojson_file = operations::get_geojson_file_by_overpass_query(&query, &settings)?;
+        let mut mapper = OSMGeoMapper::from_geojson_file(geojson_file.path().to_string(), location, options)?;
+        mapper.settings = settings;
+        Ok(mapper)
+    }
+
+    // Restores a mapper written by save_snapshot, its features are drawn again on the saved grid.
+    pub fn load_snapshot(snapshot_file: String) -> Result<OSMGeoMapper, Box<dyn std::error::Error>> {
+        let snapshot = snapshot::read_snapshot(&snapshot_file)?;
+        let data_structure = operations::draw_geo_tiles(&snapshot.features, snapshot.grid);
+        Ok(OSMGeoMapper::from_data_structure(data_structure, snapshot.features, snapshot.grid, snapshot.coordinates, snapshot.radius, LoadOptions::default(), Vec::new()))
     }
 
     pub fn load_more_from_lat_lon(&mut self, latitude: f64, longitude: f64, radius: Option<u32>) -> Result<(), Box<dyn std::error::Error>> {
-        let radiusf = operations::from_tile_scale(radius.unwrap_or(200) as i32);
-        let geojson_file = operations::get_geojson_file_by_lat_lon(latitude, longitude, radiusf)?;
-        let geojson = geojson_parser::parse_geojson_file(&geojson_file.to_string());
-        operations::process_geojson_with_data_structure(&geojson, self.data_structure.clone());
+        let radiusf = operations::from_tile_scale(radius.unwrap_or(200) as i32, TILE_SCALE);
+        let geojson_file = operations::get_geojson_file_by_lat_lon(latitude, longitude, radiusf, &self.settings)?;
+        let (data_structure, geo_tiles, skipped) = load_geojson_file(geojson_file.path(), self.lenient, Some(&self.osm_id_geo_tiles), self.grid())?;
+        self.merge(data_structure, &geo_tiles);
+        self.skipped_features.write().unwrap().extend(skipped);
         Ok(())
     }
 
     pub fn load_more_from_address(&mut self, address: String, radius: Option<u32>) -> Result<(), Box<dyn std::error::Error>> {
-        let (latitude, longitude) = nominatim::get_address_lat_lon(address)?;
+        let (latitude, longitude) = nominatim::get_address_lat_lon(address, &self.settings)?;
         self.load_more_from_lat_lon(latitude, longitude, radius)
     }
 
+    // Searches the addresses of the loaded features (eg. "123 Main St"), no Nominatim call involved.
+    pub fn find_address(&self, query: &str) -> Vec<AddressMatch> {
+        self.address_index.read().unwrap().find(query)
+    }
+
+    // Features whose original geometry intersects the bounding box, whatever the grid resolution.
+    pub fn features_in_bbox(&self, left: f64, bottom: f64, right: f64, top: f64) -> Vec<Arc<GeoTile>> {
+        self.spatial_index.read().unwrap().features_in_bbox(left, bottom, right, top)
+    }
+
+    // Features closer than the given distance (in meters) to the location, with their distance, closest first.
+    pub fn features_within(&self, lat: f64, lon: f64, meters: f64) -> Vec<(Arc<GeoTile>, f64)> {
+        self.spatial_index.read().unwrap().features_within(lat, lon, meters)
+    }
+
+    // The k features closest to the location for which filter returns true, with their distance in meters.
+    pub fn nearest<F>(&self, lat: f64, lon: f64, k: usize, filter: F) -> Vec<(Arc<GeoTile>, f64)>
+    where
+        F: Fn(&GeoTile) -> bool,
+    {
+        self.spatial_index.read().unwrap().nearest(lat, lon, k, filter)
+    }
+
+    // Looks up the address at the given location through Nominatim.
+    pub fn reverse_geocode(&self, latitude: f64, longitude: f64) -> Result<nominatim::ReverseGeocodedAddress, Box<dyn std::error::Error>> {
+        Ok(nominatim::get_lat_lon_address(latitude, longitude, &self.settings)?)
+    }
+
+    pub fn load_more_from_overpass_query(&mut self, query: String) -> Result<(), Box<dyn std::error::Error>> {
+        let geojson_file = operations::get_geojson_file_by_overpass_query(&query, &self.settings)?;
+        self.load_more_from_geojson_file(geojson_file.path().to_string())
+    }
+
     pub fn load_more_from_geojson_file(&mut self, geojson_file: String) -> Result<(), Box<dyn std::error::Error>> {
-        let geojson = geojson_parser::parse_geojson_file(&geojson_file.to_string());
-        operations::process_geojson_with_data_structure(&geojson, self.data_structure.clone());
+        let (data_structure, geo_tiles, skipped) = load_geojson_file(&geojson_file, self.lenient, Some(&self.osm_id_geo_tiles), self.grid())?;
+        self.merge(data_structure, &geo_tiles);
+        self.skipped_features.write().unwrap().extend(skipped);
         Ok(())
     }
 
-    pub fn load_more_from_osm_file(&mut self, osm_file: String) -> Result<(), Box<dyn std::error::Error>> {
-        let osm_data = osm_parser::parse_osm_file(osm_file.to_string())?;
-        operations::process_osm_with_data_structure(&osm_data, self.data_structure.clone());
+    pub fn load_more_from_osm_file(&mut self, osm_file: String, filter: Option<Filter>) -> Result<(), Box<dyn std::error::Error>> {
+        self.load_more_from_osm_data_file(osm_file, osm_parser::parse_osm_file, filter)
+    }
+
+    pub fn load_more_from_o5m_file(&mut self, o5m_file: String, filter: Option<Filter>) -> Result<(), Box<dyn std::error::Error>> {
+        self.load_more_from_osm_data_file(o5m_file, o5m_parser::parse_o5m_file, filter)
+    }
+
+    pub fn load_more_from_opl_file(&mut self, opl_file: String, filter: Option<Filter>) -> Result<(), Box<dyn std::error::Error>> {
+        self.load_more_from_osm_data_file(opl_file, opl_parser::parse_opl_file, filter)
+    }
+
+    fn load_more_from_osm_data_file(&mut self, file: String, parse: OsmDataParser, filter: Option<Filter>) -> Result<(), Box<dyn std::error::Error>> {
+        let (data_structure, geo_tiles, skipped) = load_osm_data_file(&file, parse, filter.as_ref(), Some(&self.osm_id_geo_tiles), self.grid(), self.lenient, &self.way_nodes)?;
+        self.merge(data_structure, &geo_tiles);
+        self.skipped_features.write().unwrap().extend(skipped);
+        Ok(())
+    }
+
+    pub fn load_more_from_csv_file(&mut self, csv_file: String, geometry_column: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+        let (data_structure, geo_tiles, skipped) = load_csv_file(&csv_file, geometry_column.as_deref(), Some(&self.osm_id_geo_tiles), self.grid(), self.lenient)?;
+        self.merge(data_structure, &geo_tiles);
+        self.skipped_features.write().unwrap().extend(skipped);
+        Ok(())
+    }
+
+    pub fn load_more_from_pbf_file(&mut self, pbf_file: String, area: Option<Area>, filter: Option<Filter>) -> Result<(), Box<dyn std::error::Error>> {
+        let (data_structure, geo_tiles, skipped) = load_pbf_file(&pbf_file, &area, filter.as_ref(), Some(&self.osm_id_geo_tiles), self.grid(), self.lenient, &self.way_nodes)?;
+        self.merge(data_structure, &geo_tiles);
+        self.skipped_features.write().unwrap().extend(skipped);
+        Ok(())
+    }
+
+    pub fn load_more_from_mbtiles_file(&mut self, mbtiles_file: String, zoom: Option<u8>, area: Option<Area>) -> Result<(), Box<dyn std::error::Error>> {
+        let (data_structure, geo_tiles, skipped) = load_mbtiles_file(&mbtiles_file, zoom, &area, Some(&self.osm_id_geo_tiles), self.grid(), self.lenient)?;
+        self.merge(data_structure, &geo_tiles);
+        self.skipped_features.write().unwrap().extend(skipped);
         Ok(())
     }
 
-    pub fn load_more_from_pbf_file(&mut self, pbf_file: String) -> Result<(), Box<dyn std::error::Error>> {
-        let pbf_data = pbf_parser::parse_pbf_file(pbf_file.to_string())?;
-        operations::process_pbf_with_data_structure(&pbf_data, self.data_structure.clone());
+    // Overlays the tracks, routes and waypoints of a GPX file (GeoTile::Track and GeoTile::Waypoint) on the loaded data.
+    pub fn load_gpx_file(&mut self, gpx_file: String) -> Result<(), Box<dyn std::error::Error>> {
+        let (data_structure, geo_tiles) = load_gpx_file(&gpx_file, self.grid())?;
+        self.merge(data_structure, &geo_tiles);
         Ok(())
     }
 
+    // Applies an osmChange (.osc) diff: deleted and modified objects are removed from the grid and
+    // created/modified ones are drawn again. Modified ways and relations whose nodes or member ways
+    // aren't part of the diff keep their previous geometry with the new tags. Loaded ways whose nodes
+    // are moved by the diff are drawn again (only the ones loaded from OSM data, see way_nodes).
+    pub fn apply_osm_change(&mut self, osc_file: String) -> Result<(), Box<dyn std::error::Error>> {
+        let mut osm_change = osc_parser::parse_osm_change_file(osc_file.clone()).map_err(Error::Parse)?;
+        self.redraw_moved_ways(&osm_change.modify);
+        let data_structure: GeoTilesDataStructure = Arc::new(RwLock::new(HashMap::new()));
+        let mut geo_tiles = Vec::new();
+        let unresolved = osc_parser::take_unresolved_objects(&mut osm_change.modify);
+        for (osm_id, mut tags) in unresolved.into_iter() {
+            let previous_geo_tiles = self.erase_feature(&osm_id).unwrap_or_default();
+            if previous_geo_tiles.is_empty() {
+                warn!("Could not find the geometry of modified object {}, skipping", osm_id);
+            }
+            tags.push(osm_xml::Tag { key: "id".to_string(), val: osm_id });
+            for geo_tile in previous_geo_tiles {
+                operations::draw_geometry(&tags, geo_tile.geometry().clone(), data_structure.clone(), self.grid(), &mut geo_tiles);
+            }
+        }
+        for osm_id in osc_parser::osm_ids(&osm_change.modify).iter().chain(osm_change.delete.iter()) {
+            self.remove_feature(osm_id);
+        }
+        for osm_data in [&osm_change.create, &osm_change.modify].iter() {
+            // Untagged nodes are only in the diff because they were added to or moved in a way.
+            let way_node_ids: HashSet<String> = osm_data.nodes
+                .values()
+                .filter(|node| !osmtogeojson::has_interesting_tags(&node.tags, |_| false))
+                .map(|node| format!("node/{}", node.id))
+                .collect();
+            let mut geojson = osmtogeojson::osm_to_geojson(osm_data);
+            if let geojson::GeoJson::FeatureCollection(ref mut ctn) = geojson {
+                ctn.features.retain(|feature| {
+                    let id = feature.properties.as_ref().and_then(|properties| properties.get("id")?.as_str());
+                    !id.is_some_and(|id| way_node_ids.contains(id))
+                });
+            }
+            let (processed_geo_tiles, _) = operations::process_geojson_with_data_structure(&geojson, data_structure.clone(), self.grid(), false)
+                .map_err(|error| Error::Parse(error.in_file(&osc_file)))?;
+            operations::register_way_nodes(osmtogeojson::way_nodes(osm_data), &processed_geo_tiles, &self.way_nodes);
+            geo_tiles.extend(processed_geo_tiles);
+        }
+        self.merge(data_structure, &geo_tiles);
+        Ok(())
+    }
+
+    // Draws the loaded ways using the modified nodes at their new location. Relations aren't drawn again,
+    // the ways they are made of aren't registered.
+    fn redraw_moved_ways(&self, modified: &osm_xml::OSM) {
+        let moved_nodes: HashMap<i64, geo_types::Coordinate<f64>> = modified.nodes
+            .values()
+            .map(|node| (node.id, geo_types::Coordinate { x: node.lon, y: node.lat }))
+            .collect();
+        let mut geo_tiles = Vec::new();
+        for (osm_id, nodes) in operations::move_way_nodes(&self.way_nodes, &moved_nodes) {
+            for geo_tile in self.erase_feature(&osm_id).unwrap_or_default() {
+                match operations::way_geometry(geo_tile.geometry(), &nodes) {
+                    Some(geometry) => geo_tiles.push(Arc::new(geo_tile.with_geometry(geometry))),
+                    None => geo_tiles.push(geo_tile),
+                }
+            }
+        }
+        self.merge(operations::draw_geo_tiles(&geo_tiles, self.grid()), &geo_tiles);
+    }
+
+    // Moves freshly processed GeoTiles (drawn in data_structure) into the mapper while keeping track of the cells
+    // they occupy. They are all registered and indexed, even the ones that don't cover any cell.
+    fn merge(&self, data_structure: GeoTilesDataStructure, geo_tiles: &[Arc<GeoTile>]) {
+        let geo_tiles = operations::merge_data_structures(
+            data_structure,
+            geo_tiles,
+            self.data_structure.clone(),
+            self.osm_id_cells.clone(),
+            self.osm_id_geo_tiles.clone(),
+        );
+        self.address_index.write().unwrap().add(&geo_tiles);
+        self.spatial_index.write().unwrap().add(&geo_tiles);
+    }
+
+    // The GeoTiles of an OSM object (eg. "way/123"), shared by every cell they were drawn on.
+    // Most objects have a single one, multipolygons have one per polygon.
+    pub fn get_by_osm_id(&self, osm_id: &str) -> Option<Vec<Arc<GeoTile>>> {
+        self.osm_id_geo_tiles.read().unwrap().get(osm_id).cloned()
+    }
+
+    // Every loaded GeoTile once, whether or not it covers a cell of the grid.
+    pub fn geo_tiles(&self) -> Vec<Arc<GeoTile>> {
+        self.osm_id_geo_tiles.read().unwrap().values().flatten().cloned().collect()
+    }
+
+    // The grid cells an OSM object was drawn on.
+    pub fn cells_of(&self, osm_id: &str) -> Option<HashSet<geo_types::Coordinate<i32>>> {
+        self.osm_id_cells.read().unwrap().get(osm_id).cloned()
+    }
+
+    // Removes an OSM object from every cell it was drawn on, returns its GeoTiles if it was loaded.
+    pub fn remove_feature(&self, osm_id: &str) -> Option<Vec<Arc<GeoTile>>> {
+        self.way_nodes.write().unwrap().remove(osm_id);
+        self.erase_feature(osm_id)
+    }
+
+    // Same as remove_feature but the nodes of a way stay registered, for features drawn again right after.
+    fn erase_feature(&self, osm_id: &str) -> Option<Vec<Arc<GeoTile>>> {
+        operations::remove_osm_id(osm_id, self.data_structure.clone(), self.osm_id_cells.clone());
+        let geo_tiles = self.osm_id_geo_tiles.write().unwrap().remove(osm_id)?;
+        self.address_index.write().unwrap().remove(&geo_tiles);
+        self.spatial_index.write().unwrap().remove(&geo_tiles);
+        Some(geo_tiles)
+    }
+
+    // The grid the features are drawn on, see TileGrid::to_tile and TileGrid::from_tile to convert lon/lat locations.
+    pub fn grid(&self) -> TileGrid {
+        TileGrid::new(self.tile_scale, self.projection)
+    }
+
+    // Changes the grid resolution (number of cells per degree), see set_grid.
+    pub fn set_tile_scale(&mut self, tile_scale: f64) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_grid(tile_scale, self.projection)
+    }
+
+    // Eg. Projection::TransverseMercator centered on the loaded area so that cells are square in meters, see set_grid.
+    pub fn set_projection(&mut self, projection: Projection) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_grid(self.tile_scale, projection)
+    }
+
+    // The loaded features are drawn again on the new grid and the coordinates moved to the same location.
+    // Data loaded afterwards uses the new grid too. Pass the grid to the constructors (see LoadOptions::grid)
+    // to avoid drawing the features twice. Fails (without changing anything) on invalid tile scales.
+    pub fn set_grid(&mut self, tile_scale: f64, projection: Projection) -> Result<(), Box<dyn std::error::Error>> {
+        TileGrid::new(tile_scale, projection).validate()?;
+        let (longitude, latitude) = self.grid().from_tile(self.coordinates);
+        self.tile_scale = tile_scale;
+        self.projection = projection;
+        let geo_tiles = self.geo_tiles();
+        let redrawn_data_structure = operations::draw_geo_tiles(&geo_tiles, self.grid());
+        let redrawn_data_structure = std::mem::take(&mut *redrawn_data_structure.write().unwrap());
+        // Replaced in place so that atomic clones of the mapper see the new grid.
+        *self.data_structure.write().unwrap() = redrawn_data_structure;
+        let osm_id_cells = operations::index_osm_id_cells(&self.data_structure);
+        *self.osm_id_cells.write().unwrap() = std::mem::take(&mut *osm_id_cells.write().unwrap());
+        *self.address_index.write().unwrap() = AddressIndex::new(&geo_tiles, self.grid());
+        self.coordinates = self.grid().to_tile(longitude, latitude);
+        Ok(())
+    }
+
+    // The (latitude, longitude) of the coordinates.
+    pub fn location(&self) -> (f64, f64) {
+        let (longitude, latitude) = self.grid().from_tile(self.coordinates);
+        (latitude, longitude)
+    }
+
+    // Writes the loaded features (once each), the grid, coordinates and radius to a compact binary file,
+    // much faster to load back with load_snapshot than the original data. Settings and way_nodes aren't saved.
+    pub fn save_snapshot(&self, snapshot_file: String) -> Result<(), Box<dyn std::error::Error>> {
+        let snapshot = snapshot::Snapshot {
+            grid: self.grid(),
+            coordinates: self.coordinates,
+            radius: self.radius,
+            features: self.geo_tiles(),
+        };
+        snapshot::write_snapshot(&snapshot_file, &snapshot)?;
+        Ok(())
+    }
+
+    // Takes grid coordinates, see get_real for lat/lon.
     pub fn get(&self, lat: i32, lon: i32) -> Option<Vec<Arc<GeoTile>>> {
         let locked_data_structure = self.data_structure.read().unwrap();
         if let Some(geo_tiles) = locked_data_structure.get(&geo_types::Coordinate { x: lon, y: lat }) {
@@ -168,9 +589,8 @@ impl OSMGeoMapper {
     }
 
     pub fn get_real(&self, lat: f64, lon: f64) -> Option<Vec<Arc<GeoTile>>> {
-        let lat = operations::to_tile_scale(lat);
-        let lon = operations::to_tile_scale(lon);
-        self.get(lat, lon)
+        let coordinates = self.grid().to_tile(lon, lat);
+        self.get(coordinates.y, coordinates.x)
     }
 
     pub fn atomic_clone(&self) -> OSMGeoMapper {
@@ -178,6 +598,142 @@ impl OSMGeoMapper {
             data_structure: self.data_structure.clone(),
             coordinates: self.coordinates,
             radius: self.radius,
+            tile_scale: self.tile_scale,
+            projection: self.projection,
+            osm_id_cells: self.osm_id_cells.clone(),
+            osm_id_geo_tiles: self.osm_id_geo_tiles.clone(),
+            way_nodes: self.way_nodes.clone(),
+            address_index: self.address_index.clone(),
+            spatial_index: self.spatial_index.clone(),
+            settings: self.settings.clone(),
+            lenient: self.lenient,
+            skipped_features: self.skipped_features.clone(),
         }
     }
-}
\ No newline at end of file
+}
+
+// Fails early on grids the features can't be drawn on.
+fn load_options(options: Option<LoadOptions>) -> Result<LoadOptions, Error> {
+    let options = options.unwrap_or_default();
+    options.grid.validate()?;
+    Ok(options)
+}
+
+// The loaded features drawn on the grid, their GeoTiles (once each) and the skipped malformed features.
+type Loaded = (GeoTilesDataStructure, Vec<Arc<GeoTile>>, Vec<ParseError>);
+
+// Streams the features of a GeoJSON (or GeoJSONSeq) file, when lenient the malformed ones are skipped and returned.
+fn load_geojson_file(geojson_file: &str, lenient: bool, osm_id_geo_tiles: Option<&OsmIdGeoTilesDataStructure>, grid: TileGrid) -> Result<Loaded, Error> {
+    let features = geojson_parser::read_geojson_features(geojson_file)?;
+    let data_structure: GeoTilesDataStructure = Arc::new(RwLock::new(HashMap::new()));
+    let (geo_tiles, skipped) = operations::process_geojson_features_with_data_structure(features, data_structure.clone(), osm_id_geo_tiles, grid, lenient)
+        .map_err(|error| error.in_file(geojson_file))?;
+    Ok((data_structure, geo_tiles, skipped.into_iter().map(|error| error.in_file(geojson_file)).collect()))
+}
+
+type OsmDataParser = fn(String) -> Result<osm_xml::OSM, ParseError>;
+
+// The nodes of the drawn ways are registered in way_nodes.
+fn load_osm_data_file(
+    osm_file: &str,
+    parse: OsmDataParser,
+    filter: Option<&Filter>,
+    osm_id_geo_tiles: Option<&OsmIdGeoTilesDataStructure>,
+    grid: TileGrid,
+    lenient: bool,
+    way_nodes: &WayNodesDataStructure,
+) -> Result<Loaded, Error> {
+    let osm_data = parse(osm_file.to_string())?;
+    let data_structure: GeoTilesDataStructure = Arc::new(RwLock::new(HashMap::new()));
+    let (geo_tiles, skipped) = operations::process_osm_with_data_structure(&osm_data, data_structure.clone(), osm_id_geo_tiles, filter, grid, lenient)
+        .map_err(|error| error.in_file(osm_file))?;
+    operations::register_way_nodes(osmtogeojson::way_nodes(&osm_data), &geo_tiles, way_nodes);
+    Ok((data_structure, geo_tiles, skipped.into_iter().map(|error| error.in_file(osm_file)).collect()))
+}
+
+fn load_csv_file(csv_file: &str, geometry_column: Option<&str>, osm_id_geo_tiles: Option<&OsmIdGeoTilesDataStructure>, grid: TileGrid, lenient: bool) -> Result<Loaded, Error> {
+    let rows = csv_parser::read_csv_rows(csv_file, geometry_column)?;
+    let data_structure: GeoTilesDataStructure = Arc::new(RwLock::new(HashMap::new()));
+    let (geo_tiles, skipped) = operations::process_csv_rows_with_data_structure(rows, data_structure.clone(), osm_id_geo_tiles, grid, lenient)?;
+    Ok((data_structure, geo_tiles, skipped))
+}
+
+// The nodes of the drawn ways are registered in way_nodes.
+fn load_pbf_file(
+    pbf_file: &str,
+    area: &Option<Area>,
+    filter: Option<&Filter>,
+    osm_id_geo_tiles: Option<&OsmIdGeoTilesDataStructure>,
+    grid: TileGrid,
+    lenient: bool,
+    way_nodes: &WayNodesDataStructure,
+) -> Result<Loaded, Error> {
+    let pbf_data = parse_pbf_file_in_area(pbf_file.to_string(), area, filter)
+        .map_err(|error| ParseError::new(error.to_string()).in_file(pbf_file))?;
+    let data_structure: GeoTilesDataStructure = Arc::new(RwLock::new(HashMap::new()));
+    let (geo_tiles, skipped) = operations::process_pbf_with_data_structure(&pbf_data, data_structure.clone(), osm_id_geo_tiles, filter, grid, lenient)
+        .map_err(|error| error.in_file(pbf_file))?;
+    operations::register_way_nodes(pbf_parser::way_nodes(&pbf_data), &geo_tiles, way_nodes);
+    Ok((data_structure, geo_tiles, skipped.into_iter().map(|error| error.in_file(pbf_file)).collect()))
+}
+
+fn load_mbtiles_file(
+    mbtiles_file: &str,
+    zoom: Option<u8>,
+    area: &Option<Area>,
+    osm_id_geo_tiles: Option<&OsmIdGeoTilesDataStructure>,
+    grid: TileGrid,
+    lenient: bool,
+) -> Result<Loaded, Error> {
+    let features = mbtiles_parser::read_mbtiles_features(mbtiles_file, zoom, area.as_ref().map(Area::to_bbox))?;
+    let data_structure: GeoTilesDataStructure = Arc::new(RwLock::new(HashMap::new()));
+    let (geo_tiles, skipped) = operations::process_mbtiles_features_with_data_structure(features, data_structure.clone(), osm_id_geo_tiles, grid, lenient)?;
+    Ok((data_structure, geo_tiles, skipped))
+}
+
+fn load_gpx_file(gpx_file: &str, grid: TileGrid) -> Result<(GeoTilesDataStructure, Vec<Arc<GeoTile>>), Error> {
+    let gpx = gpx_parser::parse_gpx_file(gpx_file)?;
+    let data_structure: GeoTilesDataStructure = Arc::new(RwLock::new(HashMap::new()));
+    let geo_tiles = operations::process_gpx_with_data_structure(&gpx, data_structure.clone(), grid);
+    Ok((data_structure, geo_tiles))
+}
+
+// Where a mapper loaded from a file starts: the given location, else the middle of the requested area,
+// else the first drawn cell (fails when there is nothing to show).
+fn start_coordinates(
+    location: Option<Location>,
+    area: &Option<Area>,
+    data_structure: &GeoTilesDataStructure,
+    grid: TileGrid,
+    file: &str,
+) -> Result<geo_types::Coordinate<i32>, Error> {
+    match (location, area) {
+        (Some(Location::Coordinates { latitude, longitude }), _) => Ok(grid.to_tile(longitude, latitude)),
+        (Some(Location::Center), None) => {
+            warn!("Finding center location of {} not supported yet", file);
+            Ok(geo_types::Coordinate { x: 0, y: 0 })
+        },
+        (_, Some(area)) => {
+            let (left, bottom, right, top) = area.to_bbox();
+            Ok(grid.to_tile((left + right) / 2.0, (bottom + top) / 2.0))
+        },
+        (None, None) => first_coordinates(data_structure, file),
+    }
+}
+
+fn first_coordinates(data_structure: &GeoTilesDataStructure, file: &str) -> Result<geo_types::Coordinate<i32>, Error> {
+    match data_structure.read().unwrap().keys().next() {
+        Some(coordinates) => Ok(*coordinates),
+        None => Err(Error::Parse(ParseError::new("No features to display".to_string()).in_file(file))),
+    }
+}
+
+fn parse_pbf_file_in_area(pbf_file: String, area: &Option<Area>, filter: Option<&Filter>) -> Result<BTreeMap<OsmId, OsmObj>, osmpbfreader::Error> {
+    match area {
+        Some(area) => {
+            let (left, bottom, right, top) = area.to_bbox();
+            pbf_parser::parse_pbf_file_by_bbox(pbf_file, left, bottom, right, top, filter)
+        },
+        None => pbf_parser::parse_pbf_file(pbf_file, filter),
+    }
+}
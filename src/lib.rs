@@ -1,12 +1,31 @@
 pub extern crate geo_types;
 
+pub mod address_index;
+pub mod cache;
+pub mod compression;
+pub mod csv_parser;
+pub mod error;
 pub mod features;
+pub mod filter;
 pub mod geojson_parser;
+pub mod gpx_parser;
+pub mod mbtiles_parser;
 pub mod nominatim;
+pub mod o5m_parser;
+pub mod openmaptiles;
 pub mod openstreetmap;
+pub mod osc_parser;
 pub mod operations;
+pub mod opl_parser;
 pub mod osmtogeojson;
 pub mod osm_parser;
 pub mod pbf_parser;
+pub mod projection;
+pub mod retry;
+pub mod settings;
+pub mod snapshot;
+pub mod spatial_index;
 pub mod viewer;
+pub mod wkb;
+pub mod wkt;
 pub mod interface;
\ No newline at end of file
@@ -6,17 +6,35 @@ use std::io;
 use tui::backend::CrosstermBackend;
 use tui::Terminal;
 use structopt::StructOpt;
-#[cfg(debug_assertions)] use log4rs;
 
+pub mod address_index;
+pub mod cache;
+pub mod compression;
+pub mod csv_parser;
+pub mod error;
 pub mod features;
+pub mod filter;
 pub mod geojson_parser;
+pub mod gpx_parser;
+pub mod mbtiles_parser;
 pub mod nominatim;
+pub mod o5m_parser;
+pub mod openmaptiles;
 pub mod openstreetmap;
+pub mod osc_parser;
 pub mod operations;
+pub mod opl_parser;
 pub mod osmtogeojson;
 pub mod osm_parser;
 pub mod pbf_parser;
+pub mod projection;
+pub mod retry;
+pub mod settings;
+pub mod snapshot;
+pub mod spatial_index;
 pub mod viewer;
+pub mod wkb;
+pub mod wkt;
 pub mod interface;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -0,0 +1,483 @@
+use std::{ f64::consts::PI, io::Read };
+use flate2::read::{ MultiGzDecoder, ZlibDecoder };
+use geo_types as gt;
+use rusqlite::{ params, Connection, OpenFlags, NO_PARAMS };
+use serde_json::{ Map, Value as JsonValue };
+
+use crate::{ compression::{ self, Compression }, error::ParseError, features::Geometry, openmaptiles };
+
+// Tile numbers are u32, and no tile set goes anywhere near that deep.
+pub const MAX_ZOOM: u8 = 30;
+
+// Tile coordinates are divided in 4096 units unless the layer says otherwise.
+const DEFAULT_EXTENT: u32 = 4096;
+
+// Geometry commands of the Mapbox Vector Tile format (https://github.com/mapbox/vector-tile-spec).
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+const CLOSE_PATH: u32 = 7;
+
+// Feature geometry types.
+const POINT: u64 = 1;
+const LINE_STRING: u64 = 2;
+const POLYGON: u64 = 3;
+
+// The tags of a feature (converted from OpenMapTiles classes, see openmaptiles::osm_tags) and its geometries.
+pub type MbtilesFeature = (Map<String, JsonValue>, Vec<Geometry>);
+
+pub type MbtilesFeatures = Box<dyn Iterator<Item = Result<MbtilesFeature, ParseError>>>;
+
+// A feature as found in a vector tile, its coordinates already converted to lon/lat.
+#[derive(Debug, Clone)]
+pub struct VectorTileFeature {
+    pub layer: String,
+    pub id: Option<u64>,
+    pub properties: Map<String, JsonValue>,
+    pub geometries: Vec<Geometry>,
+}
+
+// Reads the features of the tiles of an MBTiles archive at the given zoom level (the most detailed one by default),
+// only the tiles intersecting the (left, bottom, right, top) bounding box when there is one.
+// Features without an id get one from their tile and position ("building/14/4751/5886/3").
+pub fn read_mbtiles_features(mbtiles_file: &str, zoom: Option<u8>, bbox: Option<(f64, f64, f64, f64)>) -> Result<MbtilesFeatures, ParseError> {
+    let to_error = |error: rusqlite::Error| ParseError::new(error.to_string()).in_file(mbtiles_file);
+    let connection = Connection::open_with_flags(mbtiles_file, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(to_error)?;
+    let zoom = match zoom {
+        Some(zoom) => zoom,
+        None => {
+            let max_zoom: Option<u8> = connection
+                .query_row("SELECT MAX(zoom_level) FROM tiles", NO_PARAMS, |row| row.get(0))
+                .map_err(to_error)?;
+            max_zoom.ok_or_else(|| ParseError::new("No tiles".to_string()).in_file(mbtiles_file))?
+        }
+    };
+    if zoom > MAX_ZOOM {
+        return Err(ParseError::new(format!("Zoom level {} is above {}", zoom, MAX_ZOOM)).in_file(mbtiles_file));
+    }
+    let last_tile = (1u32 << zoom) - 1;
+    let (min_x, min_y, max_x, max_y) = match bbox {
+        Some((left, bottom, right, top)) => (lon_to_tile_x(left, zoom), lat_to_tile_y(top, zoom), lon_to_tile_x(right, zoom), lat_to_tile_y(bottom, zoom)),
+        None => (0, 0, last_tile, last_tile),
+    };
+    // Rows are numbered from the south (TMS), unlike the y of XYZ tiles.
+    let mut statement = connection
+        .prepare("SELECT tile_column, tile_row, tile_data FROM tiles WHERE zoom_level = ?1 AND tile_column BETWEEN ?2 AND ?3 AND tile_row BETWEEN ?4 AND ?5")
+        .map_err(to_error)?;
+    let tiles: Vec<(u32, u32, Vec<u8>)> = statement
+        .query_map(params![zoom, min_x, max_x, last_tile - max_y, last_tile - min_y], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(to_error)?
+        .collect::<Result<_, _>>()
+        .map_err(to_error)?;
+    let mbtiles_file = mbtiles_file.to_string();
+    let features = tiles.into_iter().flat_map(move |(x, row, data)| {
+        let y = last_tile - row;
+        let to_error = |message: String| ParseError::new(format!("tile {}/{}/{}: {}", zoom, x, y, message)).in_file(&mbtiles_file);
+        let features = match decompress_tile(&data).and_then(|data| decode_vector_tile(&data, zoom, x, y)) {
+            Ok(features) => features,
+            Err(message) => return vec![Err(to_error(message))],
+        };
+        features
+            .into_iter()
+            .enumerate()
+            .filter(|(_, feature)| !openmaptiles::LABEL_LAYERS.contains(&feature.layer.as_str()))
+            .map(|(index, feature)| {
+                let mut properties = openmaptiles::osm_tags(&feature.layer, &feature.properties);
+                let id = match feature.id {
+                    Some(id) => format!("{}/{}", feature.layer, id),
+                    None => format!("{}/{}/{}/{}/{}", feature.layer, zoom, x, y, index),
+                };
+                properties.insert("id".to_string(), JsonValue::String(id));
+                Ok((properties, feature.geometries))
+            })
+            .collect::<Vec<_>>()
+    });
+    Ok(Box::new(features))
+}
+
+// Tiles are usually gzip compressed, sometimes zlib compressed.
+fn decompress_tile(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decompressed = Vec::new();
+    let result = match compression::detect_compression(data) {
+        Compression::Gzip => MultiGzDecoder::new(data).read_to_end(&mut decompressed),
+        _ if data.first() == Some(&0x78) => ZlibDecoder::new(data).read_to_end(&mut decompressed),
+        _ => return Ok(data.to_vec()),
+    };
+    result.map_err(|error| error.to_string())?;
+    Ok(decompressed)
+}
+
+// The x of the tile containing the given longitude (slippy map tile names).
+pub fn lon_to_tile_x(lon: f64, zoom: u8) -> u32 {
+    let tiles = f64::from(1u32 << zoom);
+    (((lon + 180.0) / 360.0 * tiles).floor().max(0.0) as u32).min((1u32 << zoom) - 1)
+}
+
+// The y of the tile containing the given latitude, counted from the north.
+pub fn lat_to_tile_y(lat: f64, zoom: u8) -> u32 {
+    let tiles = f64::from(1u32 << zoom);
+    let lat = lat.to_radians();
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * tiles;
+    (y.floor().max(0.0) as u32).min((1u32 << zoom) - 1)
+}
+
+// Converts fractional tile coordinates (x, y) to lon/lat.
+fn tile_to_lon_lat(x: f64, y: f64, zoom: u8) -> (f64, f64) {
+    let tiles = f64::from(1u32 << zoom);
+    let lon = x / tiles * 360.0 - 180.0;
+    let lat = (PI * (1.0 - 2.0 * y / tiles)).sinh().atan().to_degrees();
+    (lon, lat)
+}
+
+// Decodes the (uncompressed) protobuf of the tile x/y at the given zoom level.
+pub fn decode_vector_tile(data: &[u8], zoom: u8, x: u32, y: u32) -> Result<Vec<VectorTileFeature>, String> {
+    if zoom > MAX_ZOOM {
+        return Err(format!("Zoom level {} is above {}", zoom, MAX_ZOOM));
+    }
+    let mut reader = ProtobufReader { data, position: 0 };
+    let mut features = Vec::new();
+    while let Some((field, wire_type)) = reader.key()? {
+        match field {
+            // Layers.
+            3 => {
+                let layer = reader.bytes()?;
+                features.extend(decode_layer(layer, zoom, x, y)?);
+            }
+            _ => reader.skip(wire_type)?,
+        }
+    }
+    Ok(features)
+}
+
+fn decode_layer(data: &[u8], zoom: u8, x: u32, y: u32) -> Result<Vec<VectorTileFeature>, String> {
+    let mut reader = ProtobufReader { data, position: 0 };
+    let mut name = String::new();
+    let mut raw_features = Vec::new();
+    let mut keys = Vec::new();
+    let mut values = Vec::new();
+    let mut extent = DEFAULT_EXTENT;
+    while let Some((field, wire_type)) = reader.key()? {
+        match field {
+            1 => name = reader.string()?,
+            2 => raw_features.push(reader.bytes()?),
+            3 => keys.push(reader.string()?),
+            4 => values.push(decode_value(reader.bytes()?)?),
+            5 => extent = reader.unsigned()? as u32,
+            _ => reader.skip(wire_type)?,
+        }
+    }
+    if extent == 0 {
+        return Err(format!("layer {}: invalid extent", name));
+    }
+    let to_lon_lat = |(px, py): (i64, i64)| {
+        let (lon, lat) = tile_to_lon_lat(
+            f64::from(x) + px as f64 / f64::from(extent),
+            f64::from(y) + py as f64 / f64::from(extent),
+            zoom,
+        );
+        gt::Coordinate { x: lon, y: lat }
+    };
+    let mut features = Vec::new();
+    for data in raw_features {
+        let feature = decode_feature(data, &keys, &values, &to_lon_lat).map_err(|message| format!("layer {}: {}", name, message))?;
+        if let Some((id, properties, geometries)) = feature {
+            features.push(VectorTileFeature { layer: name.clone(), id, properties, geometries });
+        }
+    }
+    Ok(features)
+}
+
+// Values are stored as strings, like OSM tags.
+fn decode_value(data: &[u8]) -> Result<JsonValue, String> {
+    let mut reader = ProtobufReader { data, position: 0 };
+    let mut value = JsonValue::Null;
+    while let Some((field, wire_type)) = reader.key()? {
+        let text = match field {
+            1 => reader.string()?,
+            2 => f32::from_bits(reader.fixed32()?).to_string(),
+            3 => f64::from_bits(reader.fixed64()?).to_string(),
+            4 => (reader.unsigned()? as i64).to_string(),
+            5 => reader.unsigned()?.to_string(),
+            6 => zigzag(reader.unsigned()?).to_string(),
+            7 => (reader.unsigned()? != 0).to_string(),
+            _ => {
+                reader.skip(wire_type)?;
+                continue;
+            }
+        };
+        value = JsonValue::String(text);
+    }
+    Ok(value)
+}
+
+type DecodedFeature = (Option<u64>, Map<String, JsonValue>, Vec<Geometry>);
+
+// Features of unknown geometry types are ignored.
+fn decode_feature(
+    data: &[u8],
+    keys: &[String],
+    values: &[JsonValue],
+    to_lon_lat: &dyn Fn((i64, i64)) -> gt::Coordinate<f64>,
+) -> Result<Option<DecodedFeature>, String> {
+    let mut reader = ProtobufReader { data, position: 0 };
+    let mut id = None;
+    let mut tags = Vec::new();
+    let mut geometry_type = 0;
+    let mut commands = Vec::new();
+    while let Some((field, wire_type)) = reader.key()? {
+        match field {
+            1 => id = Some(reader.unsigned()?),
+            2 => tags.extend(reader.packed(wire_type)?),
+            3 => geometry_type = reader.unsigned()?,
+            4 => commands.extend(reader.packed(wire_type)?),
+            _ => reader.skip(wire_type)?,
+        }
+    }
+    if tags.len() % 2 != 0 {
+        return Err("odd number of tag indexes".to_string());
+    }
+    let mut properties = Map::new();
+    for pair in tags.chunks(2) {
+        let key = keys.get(pair[0] as usize).ok_or_else(|| format!("invalid key index {}", pair[0]))?;
+        let value = values.get(pair[1] as usize).ok_or_else(|| format!("invalid value index {}", pair[1]))?;
+        properties.insert(key.clone(), value.clone());
+    }
+    let paths = decode_geometry(&commands)?;
+    let coordinates: Vec<Vec<gt::Coordinate<f64>>> = paths.iter().map(|path| path.iter().copied().map(to_lon_lat).collect()).collect();
+    let geometries = match geometry_type {
+        POINT => coordinates.into_iter().flatten().map(|coordinate| Geometry::Point(coordinate.into())).collect(),
+        LINE_STRING => coordinates
+            .into_iter()
+            .filter(|path| path.len() >= 2)
+            .map(|path| Geometry::LineString(path.into()))
+            .collect(),
+        POLYGON => polygons(&paths, coordinates),
+        _ => return Ok(None),
+    };
+    Ok(Some((id, properties, geometries)))
+}
+
+// Every MoveTo starts a new path of tile coordinates (the points of a multi point are one path).
+fn decode_geometry(commands: &[u32]) -> Result<Vec<Vec<(i64, i64)>>, String> {
+    let mut paths: Vec<Vec<(i64, i64)>> = Vec::new();
+    let (mut x, mut y) = (0i64, 0i64);
+    let mut commands = commands.iter();
+    while let Some(command) = commands.next() {
+        let (id, count) = (command & 0x7, command >> 3);
+        match id {
+            MOVE_TO | LINE_TO => {
+                if id == MOVE_TO {
+                    paths.push(Vec::new());
+                }
+                let path = paths.last_mut().ok_or("LineTo before MoveTo")?;
+                for _ in 0..count {
+                    let dx = commands.next().ok_or("missing command parameter")?;
+                    let dy = commands.next().ok_or("missing command parameter")?;
+                    x += zigzag(u64::from(*dx));
+                    y += zigzag(u64::from(*dy));
+                    path.push((x, y));
+                }
+            }
+            CLOSE_PATH => {
+                let path = paths.last_mut().ok_or("ClosePath before MoveTo")?;
+                if let Some(first) = path.first().copied() {
+                    path.push(first);
+                }
+            }
+            _ => return Err(format!("unknown geometry command {}", id)),
+        }
+    }
+    Ok(paths)
+}
+
+// Rings with a positive area (clockwise, y pointing down) are exterior rings,
+// followed by the interior rings of the same polygon.
+fn polygons(rings: &[Vec<(i64, i64)>], coordinates: Vec<Vec<gt::Coordinate<f64>>>) -> Vec<Geometry> {
+    let mut polygons: Vec<gt::Polygon<f64>> = Vec::new();
+    for (ring, coordinates) in rings.iter().zip(coordinates) {
+        if coordinates.len() < 4 {
+            continue;
+        }
+        let area: i64 = ring.windows(2).map(|pair| pair[0].0 * pair[1].1 - pair[1].0 * pair[0].1).sum();
+        match polygons.last_mut() {
+            Some(polygon) if area < 0 => polygon.interiors_push(coordinates),
+            _ => polygons.push(gt::Polygon::new(coordinates.into(), vec![])),
+        }
+    }
+    polygons.into_iter().map(Geometry::Polygon).collect()
+}
+
+fn zigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+struct ProtobufReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ProtobufReader<'a> {
+    // The field number and wire type of the next field, None at the end of the message.
+    fn key(&mut self) -> Result<Option<(u64, u8)>, String> {
+        if self.position >= self.data.len() {
+            return Ok(None);
+        }
+        let key = self.unsigned()?;
+        Ok(Some((key >> 3, (key & 0x7) as u8)))
+    }
+
+    fn unsigned(&mut self) -> Result<u64, String> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.position).ok_or("unexpected end of data")?;
+            self.position += 1;
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("number too large".to_string())
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.position..self.position + length).ok_or("unexpected end of data")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn fixed32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn fixed64(&mut self) -> Result<u64, String> {
+        let bytes = self.take(8)?;
+        let mut array = [0; 8];
+        array.copy_from_slice(bytes);
+        Ok(u64::from_le_bytes(array))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let length = self.unsigned()? as usize;
+        self.take(length)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        Ok(String::from_utf8_lossy(self.bytes()?).into_owned())
+    }
+
+    // Repeated integers are usually packed, but may also be written one field at a time.
+    fn packed(&mut self, wire_type: u8) -> Result<Vec<u32>, String> {
+        if wire_type != 2 {
+            return Ok(vec![self.unsigned()? as u32]);
+        }
+        let mut packed = ProtobufReader { data: self.bytes()?, position: 0 };
+        let mut values = Vec::new();
+        while packed.position < packed.data.len() {
+            values.push(packed.unsigned()? as u32);
+        }
+        Ok(values)
+    }
+
+    fn skip(&mut self, wire_type: u8) -> Result<(), String> {
+        match wire_type {
+            0 => self.unsigned().map(|_| ()),
+            1 => self.take(8).map(|_| ()),
+            2 => self.bytes().map(|_| ()),
+            5 => self.take(4).map(|_| ()),
+            _ => Err(format!("unsupported wire type {}", wire_type)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unsigned(mut value: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte);
+                return bytes;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+
+    fn field(number: u64, payload: &[u8]) -> Vec<u8> {
+        [unsigned(number << 3 | 2), unsigned(payload.len() as u64), payload.to_vec()].concat()
+    }
+
+    fn packed(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| unsigned(u64::from(*value))).collect()
+    }
+
+    fn command(id: u32, count: u32) -> u32 {
+        id | count << 3
+    }
+
+    fn parameter(value: i32) -> u32 {
+        ((value << 1) ^ (value >> 31)) as u32
+    }
+
+    #[test]
+    fn test_decode_geometry() {
+        // A 3x3 square with a 1x1 hole.
+        let commands = [
+            command(MOVE_TO, 1), parameter(0), parameter(0),
+            command(LINE_TO, 3), parameter(3), parameter(0), parameter(0), parameter(3), parameter(-3), parameter(0),
+            command(CLOSE_PATH, 1),
+            command(MOVE_TO, 1), parameter(1), parameter(-2),
+            command(LINE_TO, 3), parameter(0), parameter(1), parameter(1), parameter(0), parameter(0), parameter(-1),
+            command(CLOSE_PATH, 1),
+        ];
+        let rings = decode_geometry(&commands).unwrap();
+        assert_eq!(rings[0], vec![(0, 0), (3, 0), (3, 3), (0, 3), (0, 0)]);
+        assert_eq!(rings[1], vec![(1, 1), (1, 2), (2, 2), (2, 1), (1, 1)]);
+        let coordinates = rings.iter().map(|ring| ring.iter().map(|(x, y)| gt::Coordinate { x: *x as f64, y: *y as f64 }).collect()).collect();
+        match &polygons(&rings, coordinates)[..] {
+            [Geometry::Polygon(polygon)] => assert_eq!(polygon.interiors().len(), 1),
+            geometries => panic!("Expected one polygon, found {:?}", geometries),
+        }
+        assert!(decode_geometry(&[command(LINE_TO, 1), parameter(1), parameter(1)]).is_err());
+    }
+
+    #[test]
+    fn test_decode_vector_tile() {
+        let feature = [
+            [unsigned(1 << 3), unsigned(42)].concat(),
+            field(2, &packed(&[0, 0])),
+            [unsigned(3 << 3), unsigned(POINT)].concat(),
+            field(4, &packed(&[command(MOVE_TO, 1), parameter(2048), parameter(2048)])),
+        ].concat();
+        let layer = [
+            field(1, b"poi"),
+            field(2, &feature),
+            field(3, b"class"),
+            field(4, &field(1, b"cafe")),
+            [unsigned(5 << 3), unsigned(4096)].concat(),
+        ].concat();
+        let tile = field(3, &layer);
+        let features = decode_vector_tile(&tile, 1, 0, 0).unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!((features[0].layer.as_str(), features[0].id), ("poi", Some(42)));
+        assert_eq!(features[0].properties.get("class"), Some(&JsonValue::String("cafe".to_string())));
+        match features[0].geometries[..] {
+            [Geometry::Point(point)] => {
+                assert!((point.x() + 90.0).abs() < 1e-9);
+                assert!((point.y() - 66.51326044311186).abs() < 1e-9);
+            }
+            ref geometries => panic!("Expected one point, found {:?}", geometries),
+        }
+        assert!(decode_vector_tile(&tile[..tile.len() - 1], 1, 0, 0).is_err());
+        assert!(decode_vector_tile(&tile, 32, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_tile_coordinates() {
+        assert_eq!((lon_to_tile_x(-75.69, 14), lat_to_tile_y(45.42, 14)), (4747, 5866));
+        assert_eq!((lon_to_tile_x(180.0, 2), lat_to_tile_y(-89.0, 2)), (3, 3));
+    }
+}
@@ -1,25 +1,160 @@
+use std::fmt;
 use serde::Deserialize;
 
+use crate::{error::Error, features::TILE_SCALE, openstreetmap::check_status, operations, settings::Settings};
+
+// A place matching a searched address.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeocodingCandidate {
+    pub display_name: String,
+    // OSM tag key and value of the place (eg. place and city, or amenity and cafe).
+    pub class: String,
+    pub kind: String,
+    pub importance: Option<f64>,
+    pub latitude: f64,
+    pub longitude: f64,
+    // (left, bottom, right, top), the same order used for Overpass bounding boxes.
+    pub bounding_box: (f64, f64, f64, f64),
+}
+
+impl GeocodingCandidate {
+    // Smallest radius (in 100,000th of a degree) around the candidate's location covering its bounding box.
+    pub fn bounding_box_radius(&self) -> u32 {
+        let (left, bottom, right, top) = self.bounding_box;
+        let distances = [self.longitude - left, right - self.longitude, self.latitude - bottom, top - self.latitude];
+        let radius = distances.iter().cloned().fold(0.0, f64::max);
+        operations::to_tile_scale(radius, TILE_SCALE).max(1) as u32
+    }
+}
+
+impl fmt::Display for GeocodingCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}={})", self.display_name, self.class, self.kind)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResult {
+    display_name: String,
+    class: String,
+    #[serde(rename = "type")]
+    kind: String,
+    importance: Option<f64>,
+    lat: String,
+    lon: String,
+    // South, north, west, east.
+    boundingbox: [String; 4],
+}
+
+impl SearchResult {
+    fn to_candidate(&self) -> Result<GeocodingCandidate, Error> {
+        let parse = |value: &String| value.parse::<f64>()
+            .map_err(|_| Error::MalformedResponse(format!("Invalid coordinates: {:?}", self)));
+        let [south, north, west, east] = &self.boundingbox;
+        Ok(GeocodingCandidate {
+            display_name: self.display_name.clone(),
+            class: self.class.clone(),
+            kind: self.kind.clone(),
+            importance: self.importance,
+            latitude: parse(&self.lat)?,
+            longitude: parse(&self.lon)?,
+            bounding_box: (parse(west)?, parse(south)?, parse(east)?, parse(north)?),
+        })
+    }
+}
+
+// Address found by a reverse lookup, the parts missing from the OSM data are None.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ReverseGeocodedAddress {
+    pub house_number: Option<String>,
+    pub road: Option<String>,
+    pub city: Option<String>,
+    pub postcode: Option<String>,
+    pub country: Option<String>,
+    pub display_name: String,
+}
+
+impl fmt::Display for ReverseGeocodedAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display_name)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReverseResult {
+    error: Option<String>,
+    display_name: Option<String>,
+    address: Option<ReverseResultAddress>,
+}
+
 #[derive(Debug, Deserialize)]
-pub struct LatLon {
-    pub lat: String,
-    pub lon: String,
+struct ReverseResultAddress {
+    house_number: Option<String>,
+    road: Option<String>,
+    // Nominatim names the settlement after its size.
+    city: Option<String>,
+    town: Option<String>,
+    village: Option<String>,
+    hamlet: Option<String>,
+    postcode: Option<String>,
+    country: Option<String>,
 }
 
 pub fn get_address_lat_lon(
     address: String,
-) -> Result<(f64, f64), Box<dyn std::error::Error>> {
-    let query = format!(
-        "https://nominatim.openstreetmap.org/?addressdetails=1&format=json&limit=1&q={}",
-        address
-    );
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("osm-geo-mapper")
-        .build()?;
-    let response = client.get(&query).send()?;
-    let result = response.json::<Vec<LatLon>>()?;
-    Ok((
-        result[0].lat.parse::<f64>().unwrap(),
-        result[0].lon.parse::<f64>().unwrap(),
-    ))
+    settings: &Settings,
+) -> Result<(f64, f64), Error> {
+    let candidates = get_address_candidates(address, 1, settings)?;
+    Ok((candidates[0].latitude, candidates[0].longitude))
+}
+
+// Returns up to limit places matching the address, most relevant first.
+pub fn get_address_candidates(
+    address: String,
+    limit: u32,
+    settings: &Settings,
+) -> Result<Vec<GeocodingCandidate>, Error> {
+    let limit = limit.to_string();
+    let query = [("addressdetails", "1"), ("format", "json"), ("limit", limit.as_str()), ("q", address.as_str())];
+    let client = settings.http_client()?;
+    let url = settings.nominatim_search_url();
+    let result = settings.retry_policy.retry(|| {
+        let response = check_status(client.get(&url).query(&query).send()?, settings)?;
+        let text = settings.read_response(response)?;
+        serde_json::from_str::<Vec<SearchResult>>(&text).map_err(|error| Error::MalformedResponse(error.to_string()))
+    })?;
+    if result.is_empty() {
+        return Err(Error::NoGeocodingResult(address));
+    }
+    result.iter().map(|search_result| search_result.to_candidate()).collect()
+}
+
+pub fn get_lat_lon_address(
+    latitude: f64,
+    longitude: f64,
+    settings: &Settings,
+) -> Result<ReverseGeocodedAddress, Error> {
+    let (lat, lon) = (latitude.to_string(), longitude.to_string());
+    let query = [("addressdetails", "1"), ("format", "json"), ("lat", lat.as_str()), ("lon", lon.as_str())];
+    let client = settings.http_client()?;
+    let url = settings.nominatim_reverse_url();
+    let result = settings.retry_policy.retry(|| {
+        let response = check_status(client.get(&url).query(&query).send()?, settings)?;
+        let text = settings.read_response(response)?;
+        serde_json::from_str::<ReverseResult>(&text).map_err(|error| Error::MalformedResponse(error.to_string()))
+    })?;
+    // Nominatim answers with {"error": "Unable to geocode"} when there is nothing around.
+    if let Some(error) = result.error {
+        return Err(Error::NoGeocodingResult(format!("{},{} ({})", latitude, longitude, error)));
+    }
+    let display_name = result.display_name.ok_or_else(|| Error::MalformedResponse("Missing display_name".to_string()))?;
+    let address = result.address.ok_or_else(|| Error::MalformedResponse("Missing address".to_string()))?;
+    Ok(ReverseGeocodedAddress {
+        house_number: address.house_number,
+        road: address.road,
+        city: address.city.or(address.town).or(address.village).or(address.hamlet),
+        postcode: address.postcode,
+        country: address.country,
+        display_name,
+    })
 }
@@ -0,0 +1,305 @@
+use std::{ collections::VecDeque, io::Read };
+use osm_xml;
+
+use crate::{ compression, error::ParseError };
+
+// Dataset types of the o5m format (https://wiki.openstreetmap.org/wiki/O5m).
+const NODE: u8 = 0x10;
+const WAY: u8 = 0x11;
+const RELATION: u8 = 0x12;
+const END_OF_FILE: u8 = 0xFE;
+const RESET: u8 = 0xFF;
+
+// Only the last 15000 strings can be referenced, and only strings (or string pairs) of up to 250 bytes are kept.
+const STRING_TABLE_SIZE: usize = 15_000;
+const MAX_TABLE_STRING_LENGTH: usize = 250;
+
+pub fn parse_o5m_file(filename: String) -> Result<osm_xml::OSM, ParseError> {
+    let file = compression::open_file(&filename).map_err(|error| ParseError::new(error.to_string()).in_file(&filename))?;
+    parse_o5m(file).map_err(|error| error.in_file(&filename))
+}
+
+pub fn parse_o5m<R: Read>(mut source: R) -> Result<osm_xml::OSM, ParseError> {
+    let mut data = Vec::new();
+    source.read_to_end(&mut data).map_err(|error| ParseError::new(error.to_string()))?;
+    let mut osm_data = osm_xml::OSM { bounds: None, nodes: Default::default(), ways: Default::default(), relations: Default::default() };
+    let mut state = DeltaState::default();
+    let mut reader = O5mReader { data: &data, position: 0 };
+    while let Some(dataset_type) = reader.next_byte() {
+        match dataset_type {
+            END_OF_FILE => break,
+            RESET => state = DeltaState::default(),
+            // Other single byte datasets carry no data.
+            0xF0..=0xFD => {}
+            _ => {
+                let start = reader.position;
+                let length = reader.unsigned().map_err(|message| to_error(start, message))? as usize;
+                let payload = reader.bytes(length).map_err(|message| to_error(start, message))?;
+                let mut dataset = O5mReader { data: payload, position: 0 };
+                let result = match dataset_type {
+                    NODE => state.read_node(&mut dataset, &mut osm_data),
+                    WAY => state.read_way(&mut dataset, &mut osm_data),
+                    RELATION => state.read_relation(&mut dataset, &mut osm_data),
+                    // Header, bounding box, timestamp, etc.
+                    _ => Ok(()),
+                };
+                result.map_err(|message| to_error(start + dataset.position, message))?;
+            }
+        }
+    }
+    Ok(osm_data)
+}
+
+fn to_error(position: usize, message: String) -> ParseError {
+    ParseError::new(format!("byte {}: {}", position, message))
+}
+
+struct O5mReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> O5mReader<'a> {
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let byte = self.data.get(self.position).copied();
+        self.position += 1;
+        byte
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.position..self.position + length).ok_or("unexpected end of data")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    // 7 bits per byte, least significant group first, the high bit tells if another byte follows.
+    fn unsigned(&mut self) -> Result<u64, String> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.next_byte().ok_or("unexpected end of data")?;
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("number too large".to_string())
+    }
+
+    // The lowest bit of signed numbers is the sign.
+    fn signed(&mut self) -> Result<i64, String> {
+        let value = self.unsigned()?;
+        Ok(if value & 1 == 1 { -((value >> 1) as i64) - 1 } else { (value >> 1) as i64 })
+    }
+
+    fn zero_terminated(&mut self) -> Result<&'a [u8], String> {
+        let length = self.data[self.position.min(self.data.len())..]
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or("unterminated string")?;
+        let bytes = self.bytes(length)?;
+        self.position += 1;
+        Ok(bytes)
+    }
+}
+
+// Ids, coordinates and references are stored as differences with the previous ones, reset by RESET datasets.
+#[derive(Default)]
+struct DeltaState {
+    node_id: i64,
+    longitude: i64,
+    latitude: i64,
+    way_id: i64,
+    way_node_id: i64,
+    relation_id: i64,
+    // Member ids, one per member type (node, way, relation).
+    member_ids: [i64; 3],
+    timestamp: i64,
+    changeset: i64,
+    strings: VecDeque<Vec<Vec<u8>>>,
+}
+
+impl DeltaState {
+    // A string (or string pair) is either written inline after a 0 byte or references a previous one.
+    fn strings(&mut self, reader: &mut O5mReader, count: usize) -> Result<Vec<Vec<u8>>, String> {
+        let reference = reader.unsigned()? as usize;
+        if reference > 0 {
+            return self.strings.get(reference - 1).cloned().ok_or_else(|| format!("invalid string reference {}", reference));
+        }
+        let strings: Vec<Vec<u8>> = (0..count).map(|_| reader.zero_terminated().map(<[u8]>::to_vec)).collect::<Result<_, _>>()?;
+        if strings.iter().map(Vec::len).sum::<usize>() <= MAX_TABLE_STRING_LENGTH {
+            self.strings.push_front(strings.clone());
+            self.strings.truncate(STRING_TABLE_SIZE);
+        }
+        Ok(strings)
+    }
+
+    // Returns false for deleted objects (nothing follows their version).
+    fn skip_version(&mut self, reader: &mut O5mReader) -> Result<bool, String> {
+        let version = reader.unsigned()?;
+        if version != 0 {
+            self.timestamp += reader.signed()?;
+            if self.timestamp != 0 {
+                self.changeset += reader.signed()?;
+                // User id and name.
+                self.strings(reader, 2)?;
+            }
+        }
+        Ok(!reader.is_empty())
+    }
+
+    fn tags(&mut self, reader: &mut O5mReader) -> Result<Vec<osm_xml::Tag>, String> {
+        let mut tags = Vec::new();
+        while !reader.is_empty() {
+            let pair = self.strings(reader, 2)?;
+            tags.push(osm_xml::Tag {
+                key: String::from_utf8_lossy(&pair[0]).into_owned(),
+                val: String::from_utf8_lossy(&pair[1]).into_owned(),
+            });
+        }
+        Ok(tags)
+    }
+
+    fn read_node(&mut self, reader: &mut O5mReader, osm_data: &mut osm_xml::OSM) -> Result<(), String> {
+        self.node_id += reader.signed()?;
+        let id = self.node_id;
+        if !self.skip_version(reader)? {
+            return Ok(());
+        }
+        self.longitude += reader.signed()?;
+        self.latitude += reader.signed()?;
+        let tags = self.tags(reader)?;
+        let (lat, lon) = (self.latitude as f64 / 1e7, self.longitude as f64 / 1e7);
+        osm_data.nodes.insert(id, osm_xml::Node { id, lat, lon, tags });
+        Ok(())
+    }
+
+    fn read_way(&mut self, reader: &mut O5mReader, osm_data: &mut osm_xml::OSM) -> Result<(), String> {
+        self.way_id += reader.signed()?;
+        let id = self.way_id;
+        if !self.skip_version(reader)? {
+            return Ok(());
+        }
+        let length = reader.unsigned()? as usize;
+        let mut references = O5mReader { data: reader.bytes(length)?, position: 0 };
+        let mut nodes = Vec::new();
+        while !references.is_empty() {
+            self.way_node_id += references.signed()?;
+            nodes.push(osm_xml::UnresolvedReference::Node(self.way_node_id));
+        }
+        let tags = self.tags(reader)?;
+        osm_data.ways.insert(id, osm_xml::Way { id, tags, nodes });
+        Ok(())
+    }
+
+    fn read_relation(&mut self, reader: &mut O5mReader, osm_data: &mut osm_xml::OSM) -> Result<(), String> {
+        self.relation_id += reader.signed()?;
+        let id = self.relation_id;
+        if !self.skip_version(reader)? {
+            return Ok(());
+        }
+        let length = reader.unsigned()? as usize;
+        let mut references = O5mReader { data: reader.bytes(length)?, position: 0 };
+        let mut members = Vec::new();
+        while !references.is_empty() {
+            let delta = references.signed()?;
+            // The member type ('0' node, '1' way, '2' relation) followed by its role.
+            let type_and_role = self.strings(&mut references, 1)?.remove(0);
+            let member_type = match type_and_role.first() {
+                Some(member_type @ b'0'..=b'2') => (member_type - b'0') as usize,
+                _ => return Err("invalid relation member type".to_string()),
+            };
+            self.member_ids[member_type] += delta;
+            let member_id = self.member_ids[member_type];
+            let role = String::from_utf8_lossy(&type_and_role[1..]).into_owned();
+            members.push(match member_type {
+                0 => osm_xml::Member::Node(osm_xml::UnresolvedReference::Node(member_id), role),
+                1 => osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(member_id), role),
+                _ => osm_xml::Member::Relation(osm_xml::UnresolvedReference::Relation(member_id), role),
+            });
+        }
+        let tags = self.tags(reader)?;
+        osm_data.relations.insert(id, osm_xml::Relation { id, members, tags });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unsigned(mut value: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte);
+                return bytes;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+
+    fn signed(value: i64) -> Vec<u8> {
+        unsigned(if value < 0 { ((-value - 1) as u64) << 1 | 1 } else { (value as u64) << 1 })
+    }
+
+    fn dataset(dataset_type: u8, payload: Vec<u8>) -> Vec<u8> {
+        let mut bytes = vec![dataset_type];
+        bytes.extend(unsigned(payload.len() as u64));
+        bytes.extend(payload);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_o5m() {
+        let mut o5m = vec![RESET];
+        o5m.extend(dataset(0xE0, b"o5m2".to_vec()));
+        // Node 1 (version 1 without author information) with an amenity=bench tag.
+        let mut node = signed(1);
+        node.extend(vec![1, 0]);
+        node.extend(signed(-750_000_000));
+        node.extend(signed(450_000_000));
+        node.extend(b"\0amenity\0bench\0");
+        o5m.extend(dataset(NODE, node));
+        // Node 2, the tag pair is a reference to the previous one.
+        let mut node = signed(1);
+        node.extend(vec![0]);
+        node.extend(signed(-10_000));
+        node.extend(signed(0));
+        node.extend(unsigned(1));
+        o5m.extend(dataset(NODE, node));
+        // Node 3 is deleted.
+        o5m.extend(dataset(NODE, [signed(1), vec![0]].concat()));
+        // Way 10 with nodes 1 and 2.
+        let mut way = signed(10);
+        way.extend(vec![0]);
+        let references = [signed(1), signed(1)].concat();
+        way.extend(unsigned(references.len() as u64));
+        way.extend(references);
+        way.extend(b"\0highway\0path\0");
+        o5m.extend(dataset(WAY, way));
+        // Relation 20 with way 10 as outer member.
+        let mut relation = signed(20);
+        relation.extend(vec![0]);
+        let members = [signed(10), b"\x001outer\0".to_vec()].concat();
+        relation.extend(unsigned(members.len() as u64));
+        relation.extend(members);
+        relation.extend(b"\0type\0multipolygon\0");
+        o5m.extend(dataset(RELATION, relation));
+        o5m.push(END_OF_FILE);
+
+        let osm_data = parse_o5m(&o5m[..]).unwrap();
+        assert_eq!(osm_data.nodes.len(), 2);
+        assert_eq!((osm_data.nodes[&1].lat, osm_data.nodes[&1].lon), (45.0, -75.0));
+        assert_eq!(osm_data.nodes[&2].lon, -75.001);
+        assert_eq!(osm_data.nodes[&2].tags, osm_data.nodes[&1].tags);
+        assert_eq!(osm_data.ways[&10].nodes, vec![osm_xml::UnresolvedReference::Node(1), osm_xml::UnresolvedReference::Node(2)]);
+        assert_eq!(osm_data.relations[&20].members, vec![osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(10), "outer".to_string())]);
+        assert!(parse_o5m(&o5m[..o5m.len() - 10]).is_err());
+    }
+}
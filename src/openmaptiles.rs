@@ -0,0 +1,147 @@
+use serde_json::{ Map, Value as JsonValue };
+
+// OpenMapTiles (https://openmaptiles.org/schema/) layers only holding labels of features drawn from other layers.
+pub const LABEL_LAYERS: [&str; 3] = ["water_name", "transportation_name", "aerodrome_label"];
+
+// The OSM tag (key, value) of each OpenMapTiles (layer, class), "*" values are copied from the class
+// ("subclass" for the transportation paths and rails, which hold the original OSM value).
+pub const CLASS_TAGS: [(&str, &str, &str, &str); 64] = [
+    ("aeroway", "*", "aeroway", "*"),
+    ("boundary", "*", "boundary", "administrative"),
+    ("building", "*", "building", "yes"),
+    ("landcover", "farmland", "landuse", "farmland"),
+    ("landcover", "grass", "landuse", "grass"),
+    ("landcover", "ice", "natural", "glacier"),
+    ("landcover", "rock", "natural", "bare_rock"),
+    ("landcover", "sand", "natural", "sand"),
+    ("landcover", "wetland", "natural", "wetland"),
+    ("landcover", "wood", "natural", "wood"),
+    ("landuse", "bus_station", "amenity", "bus_station"),
+    ("landuse", "college", "amenity", "college"),
+    ("landuse", "hospital", "amenity", "hospital"),
+    ("landuse", "kindergarten", "amenity", "kindergarten"),
+    ("landuse", "library", "amenity", "library"),
+    ("landuse", "school", "amenity", "school"),
+    ("landuse", "university", "amenity", "university"),
+    ("landuse", "pitch", "leisure", "pitch"),
+    ("landuse", "playground", "leisure", "playground"),
+    ("landuse", "stadium", "leisure", "stadium"),
+    ("landuse", "track", "leisure", "track"),
+    ("landuse", "theme_park", "tourism", "theme_park"),
+    ("landuse", "zoo", "tourism", "zoo"),
+    ("landuse", "dam", "waterway", "dam"),
+    ("landuse", "neighbourhood", "place", "neighbourhood"),
+    ("landuse", "quarter", "place", "quarter"),
+    ("landuse", "suburb", "place", "suburb"),
+    ("landuse", "*", "landuse", "*"),
+    ("mountain_peak", "*", "natural", "*"),
+    ("park", "national_park", "boundary", "national_park"),
+    ("park", "protected_area", "boundary", "protected_area"),
+    ("park", "nature_reserve", "leisure", "nature_reserve"),
+    ("park", "*", "leisure", "park"),
+    ("place", "*", "place", "*"),
+    ("transportation", "motorway", "highway", "motorway"),
+    ("transportation", "trunk", "highway", "trunk"),
+    ("transportation", "primary", "highway", "primary"),
+    ("transportation", "secondary", "highway", "secondary"),
+    ("transportation", "tertiary", "highway", "tertiary"),
+    ("transportation", "minor", "highway", "residential"),
+    ("transportation", "service", "highway", "service"),
+    ("transportation", "track", "highway", "track"),
+    ("transportation", "path", "highway", "*"),
+    ("transportation", "raceway", "highway", "raceway"),
+    ("transportation", "busway", "highway", "busway"),
+    ("transportation", "bus_guideway", "highway", "bus_guideway"),
+    ("transportation", "rail", "railway", "*"),
+    ("transportation", "transit", "railway", "*"),
+    ("transportation", "cable_car", "aerialway", "cable_car"),
+    ("transportation", "gondola", "aerialway", "gondola"),
+    ("transportation", "ferry", "route", "ferry"),
+    ("transportation", "pier", "man_made", "pier"),
+    ("water", "lake", "water", "lake"),
+    ("water", "pond", "water", "pond"),
+    ("water", "river", "water", "river"),
+    ("water", "swimming_pool", "leisure", "swimming_pool"),
+    ("water", "*", "natural", "water"),
+    ("waterway", "*", "waterway", "*"),
+    ("poi", "shop", "shop", "*"),
+    ("poi", "grocery", "shop", "*"),
+    ("poi", "lodging", "tourism", "*"),
+    ("poi", "attraction", "tourism", "*"),
+    ("poi", "railway", "railway", "*"),
+    ("poi", "*", "amenity", "*"),
+];
+
+// Converts the properties of an OpenMapTiles feature to OSM tags, the original properties (name, etc.) are kept.
+// Unknown layers and classes are returned as is.
+pub fn osm_tags(layer: &str, properties: &Map<String, JsonValue>) -> Map<String, JsonValue> {
+    let mut tags = properties.clone();
+    let class = properties.get("class").and_then(JsonValue::as_str).unwrap_or_default();
+    // Roads under construction (eg. motorway_construction) keep the class of the finished road.
+    let (class, under_construction) = match class.strip_suffix("_construction") {
+        Some(class) => (class, true),
+        None => (class, false),
+    };
+    let class_tag = CLASS_TAGS
+        .iter()
+        .find(|(tag_layer, tag_class, _, _)| *tag_layer == layer && (*tag_class == class || *tag_class == "*"));
+    if let Some((_, _, key, value)) = class_tag {
+        let subclass = properties.get("subclass").and_then(JsonValue::as_str);
+        // The POI subclasses and transportation subclasses are OSM values, the classes are groups of them.
+        let value = match (*value, subclass) {
+            ("*", Some(subclass)) if layer == "poi" || layer == "transportation" => subclass,
+            ("*", _) if class.is_empty() => "yes",
+            ("*", _) => class,
+            (value, _) => value,
+        };
+        let value = if under_construction { "construction" } else { value };
+        tags.insert(key.to_string(), JsonValue::String(value.to_string()));
+        // Water features also need the generic natural=water.
+        if *key == "water" {
+            tags.insert("natural".to_string(), JsonValue::String("water".to_string()));
+        }
+    }
+    match layer {
+        "boundary" => {
+            if let Some(admin_level) = properties.get("admin_level").and_then(JsonValue::as_str) {
+                tags.insert("admin_level".to_string(), JsonValue::String(admin_level.to_string()));
+            }
+        }
+        "housenumber" => {
+            if let Some(housenumber) = properties.get("housenumber").and_then(JsonValue::as_str) {
+                tags.insert("addr:housenumber".to_string(), JsonValue::String(housenumber.to_string()));
+            }
+        }
+        "aerodrome_label" => {
+            tags.insert("aeroway".to_string(), JsonValue::String("aerodrome".to_string()));
+        }
+        _ => {}
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(pairs: &[(&str, &str)]) -> Map<String, JsonValue> {
+        pairs.iter().map(|(key, value)| (key.to_string(), JsonValue::String(value.to_string()))).collect()
+    }
+
+    #[test]
+    fn test_osm_tags() {
+        let tags = osm_tags("transportation", &properties(&[("class", "minor")]));
+        assert_eq!(tags.get("highway").and_then(JsonValue::as_str), Some("residential"));
+        let tags = osm_tags("transportation", &properties(&[("class", "path"), ("subclass", "footway")]));
+        assert_eq!(tags.get("highway").and_then(JsonValue::as_str), Some("footway"));
+        let tags = osm_tags("transportation", &properties(&[("class", "primary_construction")]));
+        assert_eq!(tags.get("highway").and_then(JsonValue::as_str), Some("construction"));
+        let tags = osm_tags("poi", &properties(&[("class", "cafe"), ("subclass", "cafe"), ("name", "Bridgehead")]));
+        assert_eq!(tags.get("amenity").and_then(JsonValue::as_str), Some("cafe"));
+        assert_eq!(tags.get("name").and_then(JsonValue::as_str), Some("Bridgehead"));
+        let tags = osm_tags("water", &properties(&[("class", "lake")]));
+        assert_eq!(tags.get("natural").and_then(JsonValue::as_str), Some("water"));
+        assert_eq!(tags.get("building"), None);
+        assert_eq!(osm_tags("unknown", &properties(&[("class", "lake")])), properties(&[("class", "lake")]));
+    }
+}
@@ -1,23 +1,92 @@
 use std::env::temp_dir;
 use std::fs::File;
 use std::io::Write;
+use std::time::{Duration, SystemTime};
+use reqwest::{blocking::{RequestBuilder, Response}, header::RETRY_AFTER, StatusCode};
 use uuid::Uuid;
 
+use crate::{error::Error, settings::Settings};
+
 pub fn download_osm_data_by_bbox(
     left: f64,
     bottom: f64,
     right: f64,
     top: f64,
-) -> Result<String, Box<dyn std::error::Error>> {
+    settings: &Settings,
+) -> Result<String, Error> {
     let query = format!(
-        "https://overpass-api.de/api/map?bbox={},{},{},{}",
-        left, bottom, right, top
+        "{}?bbox={},{},{},{}",
+        settings.overpass_map_url(), left, bottom, right, top
     );
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("osm-geo-mapper")
-        .build()?;
-    let response = client.get(&query).send()?;
-    let result = response.text()?;
+    let client = settings.http_client()?;
+    let result = settings.retry_policy.retry(|| fetch_osm_data(client.get(&query), settings))?;
+    write_osm_data_to_temp_file(result)
+}
+
+// Runs an Overpass QL script (which must output XML, the default) against the Overpass interpreter.
+// Ways and relations need their nodes for their geometry, so scripts should recurse down before
+// printing - eg. "way[railway=rail](...); (._;>;); out body;"
+pub fn download_osm_data_by_overpass_query(
+    query: &str,
+    settings: &Settings,
+) -> Result<String, Error> {
+    let client = settings.http_client()?;
+    let url = settings.overpass_interpreter_url();
+    let result = settings.retry_policy.retry(|| fetch_osm_data(client.post(&url).form(&[("data", query)]), settings))?;
+    write_osm_data_to_temp_file(result)
+}
+
+fn fetch_osm_data(request: RequestBuilder, settings: &Settings) -> Result<String, Error> {
+    let response = check_status(request.send()?, settings)?;
+    check_osm_data(settings.read_response(response)?)
+}
+
+// Error responses are consumed, their body is kept in the RemoteRuntimeError.
+pub(crate) fn check_status(response: Response, settings: &Settings) -> Result<Response, Error> {
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::GATEWAY_TIMEOUT {
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|retry_after| parse_retry_after(retry_after.to_str().ok()?));
+        return Err(Error::RateLimited { retry_after });
+    }
+    if !status.is_success() {
+        let body = settings.read_response(response).unwrap_or_default();
+        return Err(Error::RemoteRuntimeError(format!("HTTP {}: {}", status, body.trim())));
+    }
+    Ok(response)
+}
+
+// Retry-After is either a number of seconds or an HTTP date, dates in the past mean no wait.
+fn parse_retry_after(retry_after: &str) -> Option<Duration> {
+    let retry_after = retry_after.trim();
+    if let Ok(seconds) = retry_after.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(retry_after).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+// Overpass reports errors happening while running a query (timeouts, out of memory, etc.)
+// as a remark inside an otherwise valid document, and server errors as HTML pages.
+fn check_osm_data(result: String) -> Result<String, Error> {
+    let start = result.trim_start();
+    if !start.starts_with("<?xml") && !start.starts_with("<osm") {
+        let excerpt: String = start.chars().take(100).collect();
+        return Err(Error::MalformedResponse(format!("Expected OSM XML data, got: {}", excerpt)));
+    }
+    if let Some(remark_start) = result.find("<remark>") {
+        let remark = &result[remark_start + "<remark>".len()..];
+        let remark = remark[..remark.find("</remark>").unwrap_or(remark.len())].trim();
+        if remark.starts_with("runtime error") {
+            return Err(Error::RemoteRuntimeError(remark.to_string()));
+        }
+    }
+    Ok(result)
+}
+
+fn write_osm_data_to_temp_file(result: String) -> Result<String, Error> {
     let mut tempfile = temp_dir();
     tempfile.push(Uuid::new_v4().to_string());
     tempfile.set_extension("xml");
@@ -1,53 +1,66 @@
 use std::{
-    collections::{ BTreeMap, HashMap },
+    collections::{ BTreeMap, HashMap, HashSet },
     convert::TryInto,
     sync::{ Arc, RwLock },
 };
 use log::warn;
+use geo::algorithm::orient::{ Direction, Orient };
 use geo_types as gt;
 use geojson as gj;
 use osmpbfreader::objects::{ OsmId, OsmObj };
 use osm_xml;
 
 use crate::{
-    features::{Address, GeoTileProperties, GeoTilesDataStructure, TILE_SCALE},
+    cache,
+    csv_parser::CsvRow,
+    error::{ Error, ParseError },
+    features::{
+        Address, GeoTile, GeoTileProperties, GeoTilesDataStructure, Geometry, OsmIdCellsDataStructure, OsmIdGeoTilesDataStructure,
+        TrackType, WayNodes, WayNodesDataStructure, WaypointType, geotile_dedup, geotile_sort,
+    },
+    filter::{ self, Filter },
+    gpx_parser::{ Gpx, GpxPoint },
+    mbtiles_parser::MbtilesFeature,
     operations::{
-        line_string_operations::{draw_line_string, line_string_feature_to_geo_tile},
+        line_string_operations::{draw_line, draw_line_string, line_string_feature_to_geo_tile},
         point_operations::{draw_point, point_feature_to_geo_tile},
         polygon_operations::{draw_polygon, polygon_feature_to_geo_tile},
+        relation_operations::{way_nodes_to_line_string, WayNode},
     },
     openstreetmap,
     osmtogeojson,
-    pbf_parser::HasCoordinates,
+    pbf_parser::{ self, HasCoordinates, HasMultiPolygon },
+    projection::TileGrid,
+    settings::Settings,
 };
 
 pub mod line_string_operations;
 pub mod point_operations;
 pub mod polygon_operations;
+pub mod relation_operations;
 
-// Takes a lat/lon unit (f64) and converts it to a 2d grid coordinate unit using i32.
+// Takes a lat/lon unit (f64) and converts it to a tile scale unit using i32, tile_scale being the number
+// of units per degree (eg. features::TILE_SCALE for radiuses). Grid cells go through TileGrid::to_tile instead,
+// which also applies the grid's projection.
 // This is a lossy operation.
-pub fn to_tile_scale(unit: f64) -> i32 {
-    return (unit * TILE_SCALE).round() as i32
+pub fn to_tile_scale(unit: f64, tile_scale: f64) -> i32 {
+    (unit * tile_scale).round() as i32
 }
 
 // Takes a tile-scaled i32 unit and converts it back to a lat/lon scale unit (f64).
 // This is not technically a lossy operation, but the initial convertion to tile scale would have been,
 // therefor you can't expect to be able to convert back-and-forth without losing fidelity.
-pub fn from_tile_scale(unit : i32) -> f64 {
-    return (unit as f64) / TILE_SCALE;
+pub fn from_tile_scale(unit : i32, tile_scale: f64) -> f64 {
+    (unit as f64) / tile_scale
 }
 
 // Same as from_tile_scale(i32) except takes a u8.
-pub fn from_tile_scale_u8(unit : u8) -> f64 {
-    return (unit as f64) / TILE_SCALE;
+pub fn from_tile_scale_u8(unit : u8, tile_scale: f64) -> f64 {
+    (unit as f64) / tile_scale
 }
 
 pub fn property_to_option_string(props: &dyn GeoTileProperties, key: &str) -> Option<String> {
-    match props.fetch(key) {
-        Some(value) => Some(value.to_string()),
-        _ => None,
-    }
+    props.fetch(key).map(|value| value.to_string())
 }
 
 pub fn address_from_properties(props: &dyn GeoTileProperties) -> Option<Address> {
@@ -56,22 +69,10 @@ pub fn address_from_properties(props: &dyn GeoTileProperties) -> Option<Address>
         || props.has("addr:street")
         || props.has("addr:postcode")
     {
-        let house_number = match props.fetch("addr:housenumber") {
-            Some(value) => Some(String::from(value)),
-            _ => None,
-        };
-        let unit = match props.fetch("addr:unit") {
-            Some(value) => Some(String::from(value)),
-            _ => None,
-        };
-        let street = match props.fetch("addr:street") {
-            Some(value) => Some(String::from(value)),
-            _ => None,
-        };
-        let postal_code = match props.fetch("addr:postcode") {
-            Some(value) => Some(String::from(value)),
-            _ => None,
-        };
+        let house_number = props.fetch("addr:housenumber").map(String::from);
+        let unit = props.fetch("addr:unit").map(String::from);
+        let street = props.fetch("addr:street").map(String::from);
+        let postal_code = props.fetch("addr:postcode").map(String::from);
         Some(Address {
             house_number,
             unit,
@@ -83,198 +84,666 @@ pub fn address_from_properties(props: &dyn GeoTileProperties) -> Option<Address>
     }
 }
 
+// A file downloaded (or converted from downloaded data) for a load. Temporary files are removed once dropped,
+// whether the load succeeded or not, unless they were moved into the cache.
+#[derive(Debug)]
+pub struct DownloadedFile {
+    path: String,
+    temporary: bool,
+}
+
+impl DownloadedFile {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Drop for DownloadedFile {
+    fn drop(&mut self) {
+        if self.temporary {
+            // Already gone when moved into the cache, or when never written (eg. a failed conversion).
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
 pub fn get_geojson_file_by_lat_lon(
     lat: f64,
     lon: f64,
     radius: f64,
-) -> Result<String, Box<dyn std::error::Error>> {
+    settings: &Settings,
+) -> Result<DownloadedFile, Box<dyn std::error::Error>> {
     let left = lon - radius;
     let bottom = lat - radius;
     let right = lon + radius;
     let top = lat + radius;
-    let osm_file = openstreetmap::download_osm_data_by_bbox(left, bottom, right, top)?;
-    let geojson_file = format!("{}.geojson", osm_file);
-    osmtogeojson::convert_osm_to_geojson(osm_file, geojson_file.clone())?;
+    if let Some(cache) = &settings.cache {
+        if let Some(geojson_file) = cache::get_cached_geojson_file(cache, settings.offline, left, bottom, right, top) {
+            return Ok(DownloadedFile { path: geojson_file, temporary: false });
+        }
+    }
+    if settings.offline {
+        return Err(Box::new(Error::Offline(format!("area {},{},{},{} isn't cached", left, bottom, right, top))));
+    }
+    let osm_file = openstreetmap::download_osm_data_by_bbox(left, bottom, right, top, settings)?;
+    let geojson_file = convert_downloaded_osm_file(osm_file)?;
+    match &settings.cache {
+        Some(cache) => {
+            let path = cache::cache_geojson_file(cache, geojson_file.path(), left, bottom, right, top)?;
+            Ok(DownloadedFile { path, temporary: false })
+        }
+        None => Ok(geojson_file),
+    }
+}
+
+pub fn get_geojson_file_by_overpass_query(
+    query: &str,
+    settings: &Settings,
+) -> Result<DownloadedFile, Box<dyn std::error::Error>> {
+    let osm_file = openstreetmap::download_osm_data_by_overpass_query(query, settings)?;
+    convert_downloaded_osm_file(osm_file)
+}
+
+// The downloaded OSM file is only needed for the conversion.
+fn convert_downloaded_osm_file(osm_file: String) -> Result<DownloadedFile, Box<dyn std::error::Error>> {
+    let osm_file = DownloadedFile { path: osm_file, temporary: true };
+    let geojson_file = DownloadedFile { path: format!("{}.geojson", osm_file.path()), temporary: true };
+    osmtogeojson::convert_osm_to_geojson(osm_file.path().to_string(), geojson_file.path().to_string())?;
     Ok(geojson_file)
 }
 
-pub fn process_geojson(geojson: &gj::GeoJson) -> GeoTilesDataStructure {
+// Fails on the first malformed feature, see process_geojson_with_data_structure for the lenient mode
+// and the list of processed GeoTiles.
+pub fn process_geojson(geojson: &gj::GeoJson, grid: TileGrid) -> Result<GeoTilesDataStructure, ParseError> {
     let data_structure = GeoTilesDataStructure::new(RwLock::new(HashMap::new()));
-    process_geojson_with_data_structure(geojson, data_structure.clone());
-    data_structure
+    process_geojson_with_data_structure(geojson, data_structure.clone(), grid, false)?;
+    Ok(data_structure)
 }
 
-pub fn process_osm(osm_data: &osm_xml::OSM) -> GeoTilesDataStructure {
+pub fn process_osm(osm_data: &osm_xml::OSM, filter: Option<&Filter>, grid: TileGrid) -> Result<GeoTilesDataStructure, ParseError> {
     let data_structure = GeoTilesDataStructure::new(RwLock::new(HashMap::new()));
-    process_osm_with_data_structure(osm_data, data_structure.clone());
-    data_structure
+    process_osm_with_data_structure(osm_data, data_structure.clone(), None, filter, grid, false)?;
+    Ok(data_structure)
 }
 
-pub fn process_pbf(pbf_data: &BTreeMap<OsmId, OsmObj>) -> GeoTilesDataStructure {
+pub fn process_pbf(pbf_data: &BTreeMap<OsmId, OsmObj>, filter: Option<&Filter>, grid: TileGrid) -> Result<GeoTilesDataStructure, ParseError> {
     let data_structure = GeoTilesDataStructure::new(RwLock::new(HashMap::new()));
-    process_pbf_with_data_structure(pbf_data, data_structure.clone());
-    data_structure
+    process_pbf_with_data_structure(pbf_data, data_structure.clone(), None, filter, grid, false)?;
+    Ok(data_structure)
 }
 
-pub fn process_geojson_with_data_structure(geojson: &gj::GeoJson, data_structure: GeoTilesDataStructure) {
-    match *geojson {
-        gj::GeoJson::FeatureCollection(ref ctn) => {
-            for feature in &ctn.features {
-                // Only process features that have properties and a geometry.
-                if feature.properties.is_some() && feature.geometry.is_some() {
-                    process_feature(
-                        feature.properties.as_ref().unwrap(),
-                        &feature.geometry.as_ref().unwrap(),
-                        data_structure.clone(),
-                    )
-                } else {
-                    warn!("Found feature from features without properties or geometry");
-                }
+// Records the grid cells occupied by every GeoTile of the data structure (by osm_id).
+pub fn index_osm_id_cells(data_structure: &GeoTilesDataStructure) -> OsmIdCellsDataStructure {
+    let mut osm_id_cells: HashMap<String, HashSet<gt::Coordinate<i32>>> = HashMap::new();
+    for (coordinate, geo_tiles) in data_structure.read().unwrap().iter() {
+        for geo_tile in geo_tiles {
+            osm_id_cells.entry(geo_tile.osm_id().to_string()).or_default().insert(*coordinate);
+        }
+    }
+    Arc::new(RwLock::new(osm_id_cells))
+}
+
+// Records the GeoTiles of every OSM object (eg. every polygon of a multipolygon).
+pub fn index_osm_id_geo_tiles(geo_tiles: &[Arc<GeoTile>]) -> OsmIdGeoTilesDataStructure {
+    let mut osm_id_geo_tiles: HashMap<String, Vec<Arc<GeoTile>>> = HashMap::new();
+    for geo_tile in geo_tiles {
+        osm_id_geo_tiles.entry(geo_tile.osm_id().to_string()).or_default().push(geo_tile.clone());
+    }
+    Arc::new(RwLock::new(osm_id_geo_tiles))
+}
+
+// Ids of OpenStreetMap objects (node/123, way/456, relation/789), the same object found in several sources
+// is only loaded once. Other ids (eg. track/0 for the first track of a GPX file) are only unique within a file.
+fn is_osm_object_id(osm_id: &str) -> bool {
+    ["node/", "way/", "relation/"].iter().any(|prefix| osm_id.starts_with(prefix))
+}
+
+// OSM objects already registered in osm_id_geo_tiles are skipped before being drawn again, see merge_data_structures.
+fn is_loaded(osm_id: Option<&str>, osm_id_geo_tiles: Option<&OsmIdGeoTilesDataStructure>) -> bool {
+    match (osm_id, osm_id_geo_tiles) {
+        (Some(osm_id), Some(osm_id_geo_tiles)) => is_osm_object_id(osm_id) && osm_id_geo_tiles.read().unwrap().contains_key(osm_id),
+        _ => false,
+    }
+}
+
+// Moves the GeoTiles of a freshly processed data structure into another, keeping track of their cells.
+// geo_tiles are the processed GeoTiles (once each), the ones that don't cover any cell included.
+// OSM objects already registered in osm_id_geo_tiles (eg. loaded with an overlapping area) keep their existing
+// GeoTiles, the GeoTiles of other features are registered next to the ones sharing their id.
+// Returns the registered GeoTiles.
+pub fn merge_data_structures(
+    from: GeoTilesDataStructure,
+    geo_tiles: &[Arc<GeoTile>],
+    into: GeoTilesDataStructure,
+    osm_id_cells: OsmIdCellsDataStructure,
+    osm_id_geo_tiles: OsmIdGeoTilesDataStructure,
+) -> Vec<Arc<GeoTile>> {
+    let mut from = from.write().unwrap();
+    let mut locked_data_structure = into.write().unwrap();
+    let mut locked_osm_id_cells = osm_id_cells.write().unwrap();
+    let mut locked_osm_id_geo_tiles = osm_id_geo_tiles.write().unwrap();
+    let known_osm_ids: HashSet<String> = geo_tiles
+        .iter()
+        .map(|geo_tile| geo_tile.osm_id())
+        .filter(|osm_id| is_osm_object_id(osm_id) && locked_osm_id_geo_tiles.contains_key(*osm_id))
+        .map(String::from)
+        .collect();
+    let mut registered_geo_tiles = Vec::new();
+    for geo_tile in geo_tiles.iter().filter(|geo_tile| !known_osm_ids.contains(geo_tile.osm_id())) {
+        locked_osm_id_geo_tiles.entry(geo_tile.osm_id().to_string()).or_default().push(geo_tile.clone());
+        registered_geo_tiles.push(geo_tile.clone());
+    }
+    for (coordinate, mut geo_tiles) in from.drain() {
+        geo_tiles.retain(|geo_tile| !known_osm_ids.contains(geo_tile.osm_id()));
+        if geo_tiles.is_empty() {
+            continue;
+        }
+        for geo_tile in geo_tiles.iter() {
+            locked_osm_id_cells.entry(geo_tile.osm_id().to_string()).or_default().insert(coordinate);
+        }
+        let vec = locked_data_structure.entry(coordinate).or_default();
+        vec.extend(geo_tiles);
+        vec.sort_by(geotile_sort);
+        vec.dedup_by(geotile_dedup);
+    }
+    registered_geo_tiles
+}
+
+// Removes every GeoTile drawn for the given osm_id.
+// Note that GeoTiles of other objects that were deduplicated away on those cells won't reappear.
+pub fn remove_osm_id(osm_id: &str, data_structure: GeoTilesDataStructure, osm_id_cells: OsmIdCellsDataStructure) {
+    let cells = match osm_id_cells.write().unwrap().remove(osm_id) {
+        Some(cells) => cells,
+        None => return,
+    };
+    let mut locked_data_structure = data_structure.write().unwrap();
+    for coordinate in cells {
+        if let Some(geo_tiles) = locked_data_structure.get_mut(&coordinate) {
+            geo_tiles.retain(|geo_tile| geo_tile.osm_id() != osm_id);
+            if geo_tiles.is_empty() {
+                locked_data_structure.remove(&coordinate);
             }
         }
-        gj::GeoJson::Feature(ref feature) => {
-            // Only process features that have properties and a geometry.
-            if feature.properties.is_some() && feature.geometry.is_some() {
-                process_feature(
-                    feature.properties.as_ref().unwrap(),
-                    &feature.geometry.as_ref().unwrap(),
-                    data_structure,
-                )
-            } else {
-                warn!("Found feature without properties or geometry");
+    }
+}
+
+// Registers the nodes of the ways among freshly processed GeoTiles (eg. not filtered out), ways
+// already registered keep their nodes like they keep their GeoTiles, see merge_data_structures.
+pub fn register_way_nodes(found_way_nodes: WayNodes, geo_tiles: &[Arc<GeoTile>], way_nodes: &WayNodesDataStructure) {
+    let processed_osm_ids: HashSet<&str> = geo_tiles.iter().map(|geo_tile| geo_tile.osm_id()).collect();
+    let mut locked_way_nodes = way_nodes.write().unwrap();
+    for (osm_id, nodes) in found_way_nodes.into_iter().filter(|(osm_id, _)| processed_osm_ids.contains(osm_id.as_str())) {
+        locked_way_nodes.entry(osm_id).or_insert(nodes);
+    }
+}
+
+// Moves the nodes of the registered ways to their new location, returns the moved ways with their updated nodes.
+pub fn move_way_nodes(way_nodes: &WayNodesDataStructure, moved_nodes: &HashMap<i64, gt::Coordinate<f64>>) -> Vec<(String, Vec<WayNode>)> {
+    let mut moved_ways = Vec::new();
+    for (osm_id, nodes) in way_nodes.write().unwrap().iter_mut() {
+        let mut moved = false;
+        for (id, coordinate) in nodes.iter_mut() {
+            if let Some(new_coordinate) = moved_nodes.get(id) {
+                moved |= new_coordinate != coordinate;
+                *coordinate = *new_coordinate;
             }
         }
+        if moved {
+            moved_ways.push((osm_id.clone(), nodes.clone()));
+        }
+    }
+    moved_ways
+}
+
+// The geometry of a way with new node locations, polygons keep their holes (eg. from an old-style multipolygon).
+pub fn way_geometry(geometry: &Geometry, nodes: &[WayNode]) -> Option<Geometry> {
+    match geometry {
+        Geometry::LineString(_) => Some(Geometry::LineString(way_nodes_to_line_string(nodes))),
+        Geometry::Polygon(polygon) => {
+            let polygon = gt::Polygon::new(way_nodes_to_line_string(nodes), polygon.interiors().to_vec());
+            Some(Geometry::Polygon(polygon.orient(Direction::Default)))
+        }
+        Geometry::Point(_) => None,
+    }
+}
+
+// Draws already classified GeoTiles (eg. read from a snapshot, or to change the grid) by their geometry.
+pub fn draw_geo_tiles(geo_tiles: &[Arc<GeoTile>], grid: TileGrid) -> GeoTilesDataStructure {
+    let data_structure = GeoTilesDataStructure::new(RwLock::new(HashMap::new()));
+    for geo_tile in geo_tiles {
+        draw_geo_tile(geo_tile.clone(), data_structure.clone(), grid);
+    }
+    data_structure
+}
+
+fn draw_geo_tile(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStructure, grid: TileGrid) {
+    match geo_tile.geometry().clone() {
+        Geometry::Point(point) => draw_point(&point, geo_tile, data_structure, grid),
+        // Tracks aren't classified line strings, see process_gpx_with_data_structure.
+        Geometry::LineString(line_string) if matches!(*geo_tile, GeoTile::Track { .. }) => {
+            draw_track(&line_string, geo_tile, data_structure, grid)
+        }
+        Geometry::LineString(_) => draw_line_string(geo_tile, data_structure, grid),
+        Geometry::Polygon(poly) => draw_polygon(&poly, geo_tile, data_structure, grid),
+    }
+}
+
+// Features are listed in geo_tiles when processed, whether or not their GeoTile covers any cell of the grid
+// (eg. polygons smaller than a cell at coarse tile scales), the spatial queries still find them.
+fn draw_processed_geo_tile(geo_tile: GeoTile, data_structure: GeoTilesDataStructure, grid: TileGrid, geo_tiles: &mut Vec<Arc<GeoTile>>) {
+    let geo_tile = Arc::new(geo_tile);
+    geo_tiles.push(geo_tile.clone());
+    draw_geo_tile(geo_tile, data_structure, grid);
+}
+
+pub fn draw_geometry(properties: &dyn GeoTileProperties, geometry: Geometry, data_structure: GeoTilesDataStructure, grid: TileGrid, geo_tiles: &mut Vec<Arc<GeoTile>>) {
+    let geo_tile = match geometry {
+        Geometry::Point(point) => point_feature_to_geo_tile(properties, point),
+        Geometry::LineString(line_string) => line_string_feature_to_geo_tile(properties, line_string),
+        Geometry::Polygon(poly) => polygon_feature_to_geo_tile(properties, poly),
+    };
+    draw_processed_geo_tile(geo_tile, data_structure, grid, geo_tiles);
+}
+
+// The process_*_with_data_structure functions return the processed GeoTiles (once each, see draw_processed_geo_tile)
+// with the skipped features.
+pub type Processed = (Vec<Arc<GeoTile>>, Vec<ParseError>);
+
+// When lenient, malformed features are skipped and returned instead of failing the whole load
+// (the features processed before a failure are drawn either way).
+pub fn process_geojson_with_data_structure(
+    geojson: &gj::GeoJson,
+    data_structure: GeoTilesDataStructure,
+    grid: TileGrid,
+    lenient: bool,
+) -> Result<Processed, ParseError> {
+    let features = match *geojson {
+        gj::GeoJson::FeatureCollection(ref ctn) => ctn.features.iter().collect(),
+        gj::GeoJson::Feature(ref feature) => vec![feature],
         gj::GeoJson::Geometry(_) => {
             // For now, ignore hanging geometry types.
             //match_geometry(geometry, terrain_type, terrain_manager)
-            warn!("Found top-level geometry")
+            warn!("Found top-level geometry");
+            Vec::new()
+        }
+    };
+    let mut geo_tiles = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, feature) in features.into_iter().enumerate() {
+        if let Err(error) = process_indexed_feature(index, feature, data_structure.clone(), grid, &mut geo_tiles) {
+            skip_or_fail(error, lenient, &mut skipped)?;
+        }
+    }
+    Ok((geo_tiles, skipped))
+}
+
+// Same as process_geojson_with_data_structure for features read one at a time
+// (see geojson_parser::read_geojson_features), parse errors are handled like malformed features.
+// OSM objects already in osm_id_geo_tiles (eg. downloaded again with an overlapping area) aren't drawn again.
+pub fn process_geojson_features_with_data_structure<I>(
+    features: I,
+    data_structure: GeoTilesDataStructure,
+    osm_id_geo_tiles: Option<&OsmIdGeoTilesDataStructure>,
+    grid: TileGrid,
+    lenient: bool,
+) -> Result<Processed, ParseError>
+where
+    I: IntoIterator<Item = Result<gj::Feature, ParseError>>,
+{
+    let mut geo_tiles = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, feature) in features.into_iter().enumerate() {
+        let result = feature.and_then(|feature| {
+            if is_loaded(feature_osm_id(&feature), osm_id_geo_tiles) {
+                return Ok(());
+            }
+            process_indexed_feature(index, &feature, data_structure.clone(), grid, &mut geo_tiles)
+        });
+        if let Err(error) = result {
+            skip_or_fail(error, lenient, &mut skipped)?;
         }
     }
+    Ok((geo_tiles, skipped))
 }
 
-pub fn process_osm_with_data_structure(osm_data: &osm_xml::OSM, data_structure: GeoTilesDataStructure) {
-    // Nodes
-    for (_, node) in osm_data.nodes.iter() {
-        let point: gt::Point<f64> = (node.lat, node.lon).try_into().unwrap();
-        let geo_tile = Arc::new(point_feature_to_geo_tile(&node.tags, point));
-        draw_point(&point, geo_tile, data_structure.clone());
-    }
-    // Ways
-    for (_, way) in osm_data.ways.iter() {
-        let mut coordinates: Vec<(f64, f64)> = Vec::new();
-        for node in way.nodes.iter() {
-            match osm_data.resolve_reference(&node) {
-                osm_xml::Reference::Node(n) => coordinates.push((n.lat, n.lon)),
-                osm_xml::Reference::Unresolved  |
-                osm_xml::Reference::Way(_)      |
-                osm_xml::Reference::Relation(_) => {
-                    warn!("Found a non-node as part of way {}'s node list: {:?}", way.id, node);
+fn feature_osm_id(feature: &gj::Feature) -> Option<&str> {
+    feature.properties.as_ref().and_then(|properties| properties.get("id")?.as_str())
+}
+
+// Rows read from a CSV/TSV file (see csv_parser::read_csv_rows) go through the same GeoTile rules
+// as OSM data, parse errors are handled like malformed features. Rows of loaded OSM objects are skipped.
+pub fn process_csv_rows_with_data_structure<I>(
+    rows: I,
+    data_structure: GeoTilesDataStructure,
+    osm_id_geo_tiles: Option<&OsmIdGeoTilesDataStructure>,
+    grid: TileGrid,
+    lenient: bool,
+) -> Result<Processed, ParseError>
+where
+    I: IntoIterator<Item = Result<CsvRow, ParseError>>,
+{
+    let mut geo_tiles = Vec::new();
+    let mut skipped = Vec::new();
+    for row in rows {
+        match row {
+            Ok((properties, _)) if is_loaded(properties.fetch("id"), osm_id_geo_tiles) => {}
+            Ok((properties, geometry)) => draw_geometry(&properties, geometry, data_structure.clone(), grid, &mut geo_tiles),
+            Err(error) => skip_or_fail(error, lenient, &mut skipped)?,
+        }
+    }
+    Ok((geo_tiles, skipped))
+}
+
+// Vector tile features (see mbtiles_parser::read_mbtiles_features) are drawn like CSV rows,
+// tiles that can't be decoded are handled like malformed features.
+pub fn process_mbtiles_features_with_data_structure<I>(
+    features: I,
+    data_structure: GeoTilesDataStructure,
+    osm_id_geo_tiles: Option<&OsmIdGeoTilesDataStructure>,
+    grid: TileGrid,
+    lenient: bool,
+) -> Result<Processed, ParseError>
+where
+    I: IntoIterator<Item = Result<MbtilesFeature, ParseError>>,
+{
+    let mut geo_tiles = Vec::new();
+    let mut skipped = Vec::new();
+    for feature in features {
+        match feature {
+            Ok((properties, _)) if is_loaded(properties.fetch("id"), osm_id_geo_tiles) => {}
+            Ok((properties, geometries)) => {
+                for geometry in geometries {
+                    draw_geometry(&properties, geometry, data_structure.clone(), grid, &mut geo_tiles);
                 }
             }
+            Err(error) => skip_or_fail(error, lenient, &mut skipped)?,
         }
-        if way.is_polygon() { // Polygon
-            let poly: gt::Polygon<f64> = gt::Polygon::new(coordinates.into(), vec![]);
-            let geo_tile = Arc::new(polygon_feature_to_geo_tile(&way.tags, poly.clone()));
-            draw_polygon(&poly, geo_tile, data_structure.clone());
-        } else { // LineString
-            let line_string: gt::LineString<f64> = coordinates.into();
-            let geo_tile = Arc::new(line_string_feature_to_geo_tile(&way.tags, line_string));
-            draw_line_string(geo_tile, data_structure.clone());
+    }
+    Ok((geo_tiles, skipped))
+}
+
+fn process_indexed_feature(
+    index: usize,
+    feature: &gj::Feature,
+    data_structure: GeoTilesDataStructure,
+    grid: TileGrid,
+    geo_tiles: &mut Vec<Arc<GeoTile>>,
+) -> Result<(), ParseError> {
+    // Only process features that have properties and a geometry.
+    let (properties, geometry) = match (&feature.properties, &feature.geometry) {
+        (Some(properties), Some(geometry)) => (properties, geometry),
+        _ => {
+            warn!("Found feature without properties or geometry");
+            return Ok(());
         }
+    };
+    process_feature(properties, geometry, data_structure, grid, geo_tiles).map_err(|message| {
+        let osm_id = properties.get("id").and_then(|id| id.as_str()).map(String::from);
+        ParseError { file: None, feature_index: Some(index), osm_id, message }
+    })
+}
+
+fn skip_or_fail(error: ParseError, lenient: bool, skipped: &mut Vec<ParseError>) -> Result<(), ParseError> {
+    if !lenient {
+        return Err(error);
     }
-    // Relations
-    // TODO: INCOMPLETE - not sure how to handle this scenario yet.
+    warn!("Skipping malformed feature: {}", error);
+    skipped.push(error);
+    Ok(())
 }
 
-pub fn process_pbf_with_data_structure(pbf_data: &BTreeMap<OsmId, OsmObj>, data_structure: GeoTilesDataStructure) {
+// OSM objects already registered in osm_id_geo_tiles are converted but not drawn again.
+pub fn process_osm_with_data_structure(
+    osm_data: &osm_xml::OSM,
+    data_structure: GeoTilesDataStructure,
+    osm_id_geo_tiles: Option<&OsmIdGeoTilesDataStructure>,
+    filter: Option<&Filter>,
+    grid: TileGrid,
+    lenient: bool,
+) -> Result<Processed, ParseError> {
+    // Goes through the same osmtogeojson rules as downloaded data (areas, multipolygons, routes).
+    // Only the objects matching the filter (and their dependencies, filtered out once converted) are converted.
+    let filtered_osm_data = filter.map(|filter| osmtogeojson::filter_osm_data(osm_data, filter));
+    let mut geojson = osmtogeojson::osm_to_geojson(filtered_osm_data.as_ref().unwrap_or(osm_data));
+    if let gj::GeoJson::FeatureCollection(ref mut ctn) = geojson {
+        ctn.features.retain(|feature| {
+            let matches = feature.properties.as_ref().is_some_and(|properties| filter::matches(filter, properties));
+            matches && !is_loaded(feature_osm_id(feature), osm_id_geo_tiles)
+        });
+    }
+    process_geojson_with_data_structure(&geojson, data_structure, grid, lenient)
+}
+
+// GPX waypoints are drawn as points and every track segment (or route) as a line, their ids
+// (waypoint/0, track/0, route/0) are the positions in the file.
+// Returns the processed GeoTiles, see process_geojson_with_data_structure.
+pub fn process_gpx_with_data_structure(gpx: &Gpx, data_structure: GeoTilesDataStructure, grid: TileGrid) -> Vec<Arc<GeoTile>> {
+    let mut geo_tiles = Vec::new();
+    for (index, waypoint) in gpx.waypoints.iter().enumerate() {
+        let point = gt::Point::new(waypoint.longitude, waypoint.latitude);
+        let geo_tile = GeoTile::Waypoint {
+            waypoint_type: WaypointType::Waypoint,
+            geometry: Geometry::Point(point),
+            osm_id: format!("waypoint/{}", index),
+            address: None,
+            name: waypoint.name.clone(),
+            description: waypoint.description.clone(),
+            time: waypoint.time.clone(),
+            elevation: waypoint.elevation.map(|elevation| elevation.to_string()),
+            symbol: waypoint.symbol.clone(),
+        };
+        draw_processed_geo_tile(geo_tile, data_structure.clone(), grid, &mut geo_tiles);
+    }
+    let tracks = gpx.tracks.iter().enumerate().map(|(index, track)| (TrackType::Track, format!("track/{}", index), track));
+    let routes = gpx.routes.iter().enumerate().map(|(index, route)| (TrackType::Route, format!("route/{}", index), route));
+    for (track_type, osm_id, path) in tracks.chain(routes) {
+        for segment in path.segments.iter().filter(|segment| !segment.is_empty()) {
+            let line_string: gt::LineString<f64> = segment.iter().map(|point| (point.longitude, point.latitude)).collect::<Vec<(f64, f64)>>().into();
+            let elevations = segment.iter().filter_map(|point| point.elevation);
+            let min_elevation = elevations.clone().fold(None, |min: Option<f64>, elevation| Some(min.map_or(elevation, |min| min.min(elevation))));
+            let max_elevation = elevations.fold(None, |max: Option<f64>, elevation| Some(max.map_or(elevation, |max| max.max(elevation))));
+            let times = || segment.iter().filter_map(|point: &GpxPoint| point.time.clone());
+            let geo_tile = GeoTile::Track {
+                track_type,
+                geometry: Geometry::LineString(line_string),
+                osm_id: osm_id.clone(),
+                address: None,
+                name: path.name.clone(),
+                description: path.description.clone(),
+                start_time: times().next(),
+                end_time: times().next_back(),
+                min_elevation: min_elevation.map(|elevation| elevation.to_string()),
+                max_elevation: max_elevation.map(|elevation| elevation.to_string()),
+            };
+            draw_processed_geo_tile(geo_tile, data_structure.clone(), grid, &mut geo_tiles);
+        }
+    }
+    geo_tiles
+}
+
+fn draw_track(line_string: &gt::LineString<f64>, geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStructure, grid: TileGrid) {
+    if line_string.0.len() == 1 {
+        draw_point(&line_string.0[0].into(), geo_tile.clone(), data_structure.clone(), grid);
+    }
+    for line in line_string.lines() {
+        draw_line(&line.start.into(), &line.end.into(), 1, geo_tile.clone(), data_structure.clone(), grid);
+    }
+}
+
+// Ways with too few of their nodes in the data are malformed (nothing is drawn for them).
+// OSM objects already registered in osm_id_geo_tiles are skipped.
+pub fn process_pbf_with_data_structure(
+    pbf_data: &BTreeMap<OsmId, OsmObj>,
+    data_structure: GeoTilesDataStructure,
+    osm_id_geo_tiles: Option<&OsmIdGeoTilesDataStructure>,
+    filter: Option<&Filter>,
+    grid: TileGrid,
+    lenient: bool,
+) -> Result<Processed, ParseError> {
+    let multipolygon_way_ids = pbf_parser::get_multipolygon_member_way_ids(pbf_data);
+    let mut geo_tiles = Vec::new();
+    let mut skipped = Vec::new();
     for obj in pbf_data.values() {
+        // Dependencies of the wanted objects (untagged way nodes, etc.) are not drawn themselves.
+        if !filter::matches(filter, obj.tags()) && !obj.is_relation() {
+            continue;
+        }
         let mut tags = obj.tags().clone();
-        tags.insert("id".to_string(), obj.id().inner_id().to_string());
+        tags.insert("id".to_string(), pbf_parser::osm_id_to_string(obj.id()));
+        // Old-style multipolygons are checked once their id (the one of their outer way) is known.
+        if !obj.is_relation() && is_loaded(tags.fetch("id"), osm_id_geo_tiles) {
+            continue;
+        }
         match obj {
             OsmObj::Node(obj) => {
-                let point: gt::Point<f64> = (obj.lat(), obj.lon()).try_into().unwrap();
-                let geo_tile = Arc::new(point_feature_to_geo_tile(&tags, point));
-                draw_point(&point, geo_tile, data_structure.clone());
+                let point: gt::Point<f64> = (obj.lon(), obj.lat()).into();
+                let geo_tile = point_feature_to_geo_tile(&tags, point);
+                draw_processed_geo_tile(geo_tile, data_structure.clone(), grid, &mut geo_tiles);
             }
             OsmObj::Way(obj) => {
-                let coordinates = obj.get_coordinates(&pbf_data);
+                // Already drawn as part of a multipolygon relation.
+                if multipolygon_way_ids.contains(&obj.id) {
+                    continue;
+                }
+                let coordinates = obj.get_coordinates(pbf_data);
+                let min_positions = if obj.is_open() { 2 } else { 3 };
+                if coordinates.len() < min_positions {
+                    let message = format!("Expected at least {} positions, got {}", min_positions, coordinates.len());
+                    let error = ParseError { file: None, feature_index: None, osm_id: Some(pbf_parser::osm_id_to_string(obj.id.into())), message };
+                    skip_or_fail(error, lenient, &mut skipped)?;
+                    continue;
+                }
                 if obj.is_open() { // LineString
                     let line_string: gt::LineString<f64> = coordinates.into();
-                    let geo_tile = Arc::new(line_string_feature_to_geo_tile(&tags, line_string));
-                    draw_line_string(geo_tile, data_structure.clone());
+                    let geo_tile = line_string_feature_to_geo_tile(&tags, line_string);
+                    draw_processed_geo_tile(geo_tile, data_structure.clone(), grid, &mut geo_tiles);
                 } else { // Polygon
                     let poly: gt::Polygon<f64> = gt::Polygon::new(coordinates.into(), vec![]);
-                    let geo_tile = Arc::new(polygon_feature_to_geo_tile(&tags, poly.clone()));
-                    draw_polygon(&poly, geo_tile, data_structure.clone());
+                    let geo_tile = polygon_feature_to_geo_tile(&tags, poly);
+                    draw_processed_geo_tile(geo_tile, data_structure.clone(), grid, &mut geo_tiles);
                 }
             }
-            OsmObj::Relation(_obj) => {
-                //let coordinates = obj.get_coordinates(&pbf_data, &mut vec![]);
-                // TODO: INCOMPLETE - not sure how to handle this scenario yet.
+            OsmObj::Relation(obj) => {
+                // TODO: Other relation types (routes, sites, etc.) are not drawn yet.
+                if !pbf_parser::is_multipolygon(obj) {
+                    continue;
+                }
+                let tags = match pbf_parser::get_simple_multipolygon_outer_way(obj, pbf_data) {
+                    Some(way) => {
+                        let mut way_tags = way.tags.clone();
+                        way_tags.insert("id".to_string(), pbf_parser::osm_id_to_string(way.id.into()));
+                        way_tags
+                    }
+                    None => tags,
+                };
+                if !filter::matches(filter, &tags) || is_loaded(tags.fetch("id"), osm_id_geo_tiles) {
+                    continue;
+                }
+                let multi_polygon = obj.get_multi_polygon(pbf_data);
+                // Eg. member ways missing from a badly cut extract or rings that don't close.
+                if multi_polygon.0.is_empty() {
+                    let message = "No closed outer ring could be assembled from the members".to_string();
+                    let error = ParseError { file: None, feature_index: None, osm_id: Some(pbf_parser::osm_id_to_string(obj.id.into())), message };
+                    skip_or_fail(error, lenient, &mut skipped)?;
+                    continue;
+                }
+                for poly in multi_polygon {
+                    let geo_tile = polygon_feature_to_geo_tile(&tags, poly);
+                    draw_processed_geo_tile(geo_tile, data_structure.clone(), grid, &mut geo_tiles);
+                }
             }
         }
     }
+    Ok((geo_tiles, skipped))
 }
 
+// Malformed geometries are reported (and nothing is drawn for them) instead of panicking.
 fn process_feature(
     properties: &dyn GeoTileProperties,
     geometry: &gj::Geometry,
     data_structure: GeoTilesDataStructure,
-) {
+    grid: TileGrid,
+    geo_tiles: &mut Vec<Arc<GeoTile>>,
+) -> Result<(), String> {
+    check_geometry_value(&geometry.value)?;
     match geometry.value {
         gj::Value::Polygon(_) => {
-            let poly: gt::Polygon<f64> =
-                TryInto::<gt::Polygon<f64>>::try_into(geometry.value.clone()).unwrap();
-            let geo_tile = Arc::new(polygon_feature_to_geo_tile(properties, poly.clone()));
-            draw_polygon(&poly, geo_tile, data_structure);
+            let poly: gt::Polygon<f64> = convert_geometry_value(&geometry.value)?;
+            draw_processed_geo_tile(polygon_feature_to_geo_tile(properties, poly), data_structure, grid, geo_tiles);
         }
         gj::Value::MultiPolygon(_) => {
-            let multi_polygon: gt::MultiPolygon<f64> =
-                TryInto::<gt::MultiPolygon<f64>>::try_into(geometry.value.clone()).unwrap();
-            for polygon in multi_polygon {
-                let poly: gt::Polygon<f64> =
-                    TryInto::<gt::Polygon<f64>>::try_into(polygon).unwrap();
-                let geo_tile = Arc::new(polygon_feature_to_geo_tile(properties, poly.clone()));
-                draw_polygon(&poly, geo_tile, data_structure.clone());
+            let multi_polygon: gt::MultiPolygon<f64> = convert_geometry_value(&geometry.value)?;
+            for poly in multi_polygon {
+                draw_processed_geo_tile(polygon_feature_to_geo_tile(properties, poly), data_structure.clone(), grid, geo_tiles);
             }
         }
         gj::Value::GeometryCollection(ref gc) => {
             for geom in gc {
-                process_feature(properties, geom, data_structure.clone())
+                process_feature(properties, geom, data_structure.clone(), grid, geo_tiles)?;
             }
         }
         gj::Value::LineString(_) => {
-            let line_string: gt::LineString<f64> =
-                TryInto::<gt::LineString<f64>>::try_into(geometry.value.clone()).unwrap();
-            let geo_tile = Arc::new(line_string_feature_to_geo_tile(properties, line_string));
-            draw_line_string(geo_tile, data_structure);
+            let line_string: gt::LineString<f64> = convert_geometry_value(&geometry.value)?;
+            draw_processed_geo_tile(line_string_feature_to_geo_tile(properties, line_string), data_structure, grid, geo_tiles);
         }
         gj::Value::MultiLineString(_) => {
-            let multi_line_string: gt::MultiLineString<f64> =
-                TryInto::<gt::MultiLineString<f64>>::try_into(geometry.value.clone()).unwrap();
+            let multi_line_string: gt::MultiLineString<f64> = convert_geometry_value(&geometry.value)?;
             for line_string in multi_line_string {
-                let line_string: gt::LineString<f64> =
-                    TryInto::<gt::LineString<f64>>::try_into(line_string).unwrap();
-                let geo_tile = Arc::new(line_string_feature_to_geo_tile(properties, line_string));
-                draw_line_string(geo_tile, data_structure.clone());
+                draw_processed_geo_tile(line_string_feature_to_geo_tile(properties, line_string), data_structure.clone(), grid, geo_tiles);
             }
         }
         gj::Value::Point(_) => {
-            let point: gt::Point<f64> =
-                TryInto::<gt::Point<f64>>::try_into(geometry.value.clone()).unwrap();
-            let geo_tile = Arc::new(point_feature_to_geo_tile(properties, point));
-            draw_point(&point, geo_tile, data_structure);
+            let point: gt::Point<f64> = convert_geometry_value(&geometry.value)?;
+            draw_processed_geo_tile(point_feature_to_geo_tile(properties, point), data_structure, grid, geo_tiles);
         }
         gj::Value::MultiPoint(_) => {
-            let multi_point: gt::MultiPoint<f64> =
-                TryInto::<gt::MultiPoint<f64>>::try_into(geometry.value.clone()).unwrap();
+            let multi_point: gt::MultiPoint<f64> = convert_geometry_value(&geometry.value)?;
             for point in multi_point {
-                let point: gt::Point<f64> = TryInto::<gt::Point<f64>>::try_into(point).unwrap();
-                let geo_tile = Arc::new(point_feature_to_geo_tile(properties, point));
-                draw_point(&point, geo_tile, data_structure.clone());
+                draw_processed_geo_tile(point_feature_to_geo_tile(properties, point), data_structure.clone(), grid, geo_tiles);
             }
         }
     }
+    Ok(())
+}
+
+fn convert_geometry_value<T>(value: &gj::Value) -> Result<T, String>
+where
+    gj::Value: TryInto<T, Error = gj::Error>,
+{
+    value.clone().try_into().map_err(|error| error.to_string())
+}
+
+// The geojson conversions index into the positions, so they are checked beforehand.
+// Lines need at least two positions and polygon rings at least three to be drawn.
+fn check_geometry_value(value: &gj::Value) -> Result<(), String> {
+    fn check_position(position: &gj::Position) -> Result<(), String> {
+        if position.len() < 2 || position.iter().any(|coordinate| !coordinate.is_finite()) {
+            return Err(format!("Invalid position: {:?}", position));
+        }
+        Ok(())
+    }
+    fn check_line(line: &[gj::Position], min_positions: usize) -> Result<(), String> {
+        if line.len() < min_positions {
+            return Err(format!("Expected at least {} positions, got {}", min_positions, line.len()));
+        }
+        line.iter().try_for_each(check_position)
+    }
+    fn check_polygon(rings: &[Vec<gj::Position>]) -> Result<(), String> {
+        if rings.is_empty() {
+            return Err("Polygon without rings".to_string());
+        }
+        rings.iter().try_for_each(|ring| check_line(ring, 3))
+    }
+    match value {
+        gj::Value::Point(position) => check_position(position),
+        gj::Value::MultiPoint(positions) => positions.iter().try_for_each(check_position),
+        gj::Value::LineString(line) => check_line(line, 2),
+        gj::Value::MultiLineString(lines) => lines.iter().try_for_each(|line| check_line(line, 2)),
+        gj::Value::Polygon(rings) => check_polygon(rings),
+        gj::Value::MultiPolygon(polygons) => polygons.iter().try_for_each(|rings| check_polygon(rings)),
+        // Checked one by one when processed.
+        gj::Value::GeometryCollection(_) => Ok(()),
+    }
 }
 
 #[cfg(test)]
@@ -284,7 +753,7 @@ mod tests {
 
     #[test]
     fn test_parse_and_process_geojson_file() {
-        let geojson = geojson_parser::parse_geojson_file("resources/ottawa.xml.geojson");
-        process_geojson(&geojson);
+        let geojson = geojson_parser::parse_geojson_file("resources/ottawa.xml.geojson").unwrap();
+        process_geojson(&geojson, TileGrid::default()).unwrap();
     }
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 use crate::{
     operations,
+    projection::TileGrid,
     features::{
         aerialway_feature::{draw_aerialway_line_string, get_aerialway_geo_tile},
         aeroway_feature::{draw_aeroway_line_string, get_aeroway_geo_tile},
@@ -24,7 +25,7 @@ use crate::{
         telecom_feature::{draw_telecom_line_string, get_telecom_geo_tile},
         water_feature::{draw_water_line_string, get_water_geo_tile},
         waterway_feature::{draw_waterway_line_string, get_waterway_geo_tile},
-        GeoTile, UnclassifiedType, GeoTileProperties, GeoTilesDataStructure, Geometry, TILE_SCALE, geotile_sort, geotile_dedup
+        GeoTile, UnclassifiedType, GeoTileProperties, GeoTilesDataStructure, Geometry, geotile_sort, geotile_dedup
     }
 };
 
@@ -32,7 +33,7 @@ use geo_types as gt;
 use log::warn;
 use std::sync::Arc;
 
-pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStructure) {
+pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStructure, grid: TileGrid) {
     match (*geo_tile).clone() {
         GeoTile::Aerialway {
             aerialway_type,
@@ -44,7 +45,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("aerialway should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("aerialway should not be dealing with a polygon"),
             };
-            draw_aerialway_line_string(geo_tile, data_structure, aerialway_type, line_string)
+            draw_aerialway_line_string(geo_tile, data_structure, aerialway_type, line_string, grid)
         }
         GeoTile::Aeroway {
             aeroway_type,
@@ -56,7 +57,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("aeroway should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("aeroway should not be dealing with a polygon"),
             };
-            draw_aeroway_line_string(geo_tile, data_structure, aeroway_type, line_string)
+            draw_aeroway_line_string(geo_tile, data_structure, aeroway_type, line_string, grid)
         }
         GeoTile::Amenity {
             amenity_type,
@@ -68,7 +69,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("amenity should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("amenity should not be dealing with a polygon"),
             };
-            draw_amenity_line_string(geo_tile, data_structure, amenity_type, line_string)
+            draw_amenity_line_string(geo_tile, data_structure, amenity_type, line_string, grid)
         }
         GeoTile::Barrier {
             barrier_type,
@@ -80,7 +81,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("barrier should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("barrier should not be dealing with a polygon"),
             };
-            draw_barrier_line_string(geo_tile, data_structure, barrier_type, line_string)
+            draw_barrier_line_string(geo_tile, data_structure, barrier_type, line_string, grid)
         }
         GeoTile::Building { .. } => {
             warn!("buildings should not be dealing with a line string");
@@ -95,7 +96,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("geological should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("geological should not be dealing with a polygon"),
             };
-            draw_geological_line_string(geo_tile, data_structure, geological_type, line_string)
+            draw_geological_line_string(geo_tile, data_structure, geological_type, line_string, grid)
         }
         GeoTile::Healthcare {
             healthcare_type,
@@ -107,7 +108,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("healthcare should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("healthcare should not be dealing with a polygon"),
             };
-            draw_healthcare_line_string(geo_tile, data_structure, healthcare_type, line_string)
+            draw_healthcare_line_string(geo_tile, data_structure, healthcare_type, line_string, grid)
         }
         GeoTile::Highway {
             highway_type,
@@ -119,7 +120,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("highways should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("highways should not be dealing with a polygon"),
             };
-            draw_highway_line_string(geo_tile, data_structure, highway_type, line_string)
+            draw_highway_line_string(geo_tile, data_structure, highway_type, line_string, grid)
         }
         GeoTile::Historic {
             historic_type,
@@ -131,7 +132,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("historic should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("historic should not be dealing with a polygon"),
             };
-            draw_historic_line_string(geo_tile, data_structure, historic_type, line_string)
+            draw_historic_line_string(geo_tile, data_structure, historic_type, line_string, grid)
         }
         GeoTile::Landuse {
             geometry,
@@ -143,7 +144,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("landuse should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("landuse should not be dealing with a polygon"),
             };
-            draw_landuse_line_string(geo_tile, data_structure, landuse_type, line_string)
+            draw_landuse_line_string(geo_tile, data_structure, landuse_type, line_string, grid)
         }
         GeoTile::Leisure {
             geometry,
@@ -155,7 +156,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("leisure should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("leisure should not be dealing with a polygon"),
             };
-            draw_leisure_line_string(geo_tile, data_structure, leisure_type, line_string)
+            draw_leisure_line_string(geo_tile, data_structure, leisure_type, line_string, grid)
         }
         GeoTile::ManMade {
             geometry,
@@ -167,7 +168,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("man made should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("man made should not be dealing with a polygon"),
             };
-            draw_man_made_line_string(geo_tile, data_structure, man_made_type, line_string)
+            draw_man_made_line_string(geo_tile, data_structure, man_made_type, line_string, grid)
         }
         GeoTile::Military {
             geometry,
@@ -179,7 +180,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("military should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("military should not be dealing with a polygon"),
             };
-            draw_military_line_string(geo_tile, data_structure, military_type, line_string)
+            draw_military_line_string(geo_tile, data_structure, military_type, line_string, grid)
         }
         GeoTile::Natural {
             geometry,
@@ -191,7 +192,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("natural should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("natural should not be dealing with a polygon"),
             };
-            draw_natural_line_string(geo_tile, data_structure, natural_type, line_string)
+            draw_natural_line_string(geo_tile, data_structure, natural_type, line_string, grid)
         }
         GeoTile::Office {
             geometry,
@@ -203,7 +204,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("office should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("office should not be dealing with a polygon"),
             };
-            draw_office_line_string(geo_tile, data_structure, office_type, line_string)
+            draw_office_line_string(geo_tile, data_structure, office_type, line_string, grid)
         }
         GeoTile::Power {
             geometry,
@@ -215,7 +216,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("power should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("power should not be dealing with a polygon"),
             };
-            draw_power_line_string(geo_tile, data_structure, power_type, line_string)
+            draw_power_line_string(geo_tile, data_structure, power_type, line_string, grid)
         }
         GeoTile::PublicTransport {
             geometry,
@@ -227,7 +228,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("public transport should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("public transport should not be dealing with a polygon"),
             };
-            draw_public_transport_line_string(geo_tile, data_structure, public_transport_type, line_string)
+            draw_public_transport_line_string(geo_tile, data_structure, public_transport_type, line_string, grid)
         }
         GeoTile::Railway {
             geometry,
@@ -239,7 +240,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("railway should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("railway should not be dealing with a polygon"),
             };
-            draw_railway_line_string(geo_tile, data_structure, railway_type, line_string)
+            draw_railway_line_string(geo_tile, data_structure, railway_type, line_string, grid)
         }
         GeoTile::Route {
             geometry,
@@ -251,7 +252,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("route should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("route should not be dealing with a polygon"),
             };
-            draw_route_line_string(geo_tile, data_structure, route_type, line_string)
+            draw_route_line_string(geo_tile, data_structure, route_type, line_string, grid)
         }
         GeoTile::Shop {
             geometry,
@@ -263,7 +264,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("shop should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("shop should not be dealing with a polygon"),
             };
-            draw_shop_line_string(geo_tile, data_structure, shop_type, line_string)
+            draw_shop_line_string(geo_tile, data_structure, shop_type, line_string, grid)
         }
         GeoTile::Sport {
             geometry,
@@ -275,7 +276,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("sport should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("sport should not be dealing with a polygon"),
             };
-            draw_sport_line_string(geo_tile, data_structure, sport_type, line_string)
+            draw_sport_line_string(geo_tile, data_structure, sport_type, line_string, grid)
         }
         GeoTile::Telecom {
             geometry,
@@ -287,7 +288,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("telecom should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("telecom should not be dealing with a polygon"),
             };
-            draw_telecom_line_string(geo_tile, data_structure, telecom_type, line_string)
+            draw_telecom_line_string(geo_tile, data_structure, telecom_type, line_string, grid)
         }
         GeoTile::Water {
             geometry,
@@ -299,7 +300,7 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("water should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("water should not be dealing with a polygon"),
             };
-            draw_water_line_string(geo_tile, data_structure, water_type, line_string)
+            draw_water_line_string(geo_tile, data_structure, water_type, line_string, grid)
         }
         GeoTile::Waterway {
             geometry,
@@ -311,7 +312,17 @@ pub fn draw_line_string(geo_tile: Arc<GeoTile>, data_structure: GeoTilesDataStru
                 Geometry::Point(_) => panic!("waterway should not be dealing with a point"),
                 Geometry::Polygon(_) => panic!("waterway should not be dealing with a polygon"),
             };
-            draw_waterway_line_string(geo_tile, data_structure, waterway_type, line_string)
+            draw_waterway_line_string(geo_tile, data_structure, waterway_type, line_string, grid)
+        }
+        // Drawn so that they are kept (and found by the address index) like any other feature.
+        GeoTile::Unclassified {
+            unclassified_type: UnclassifiedType::AddressInterpolation,
+            geometry: Geometry::LineString(line_string),
+            ..
+        } => {
+            for line in line_string.lines() {
+                draw_line(&line.start.into(), &line.end.into(), 1, geo_tile.clone(), data_structure.clone(), grid);
+            }
         }
         GeoTile::Unclassified { .. } => {
             warn!("Trying to draw a line string for an unclassified feature: {:?}", geo_tile)
@@ -379,6 +390,16 @@ pub fn line_string_feature_to_geo_tile(
     } else if properties.has("service") && properties.fetch("service").unwrap() == "driveway" {
         // Driveways are treated as service roads.
         get_highway_geo_tile(properties, line_string, true)
+    } else if properties.has("addr:interpolation") {
+        // House numbers between the address nodes at both ends, see address_index.
+        let osm_id = properties.fetch("id").unwrap_or_default().to_string();
+        GeoTile::Unclassified {
+            unclassified_type: UnclassifiedType::AddressInterpolation,
+            address: operations::address_from_properties(properties),
+            geometry: line_string,
+            osm_id,
+            interpolation: operations::property_to_option_string(properties, "addr:interpolation"),
+        }
     } else {
         warn!(
             "Unclassified line string feature geo tile found: {}",
@@ -390,35 +411,48 @@ pub fn line_string_feature_to_geo_tile(
             address: None,
             geometry: line_string,
             osm_id,
+            interpolation: None,
         }
     }
 }
 
-pub fn line_string_to_i32(line_string: &gt::LineString<f64>) -> gt::LineString<i32> {
+pub fn line_string_to_i32(line_string: &gt::LineString<f64>, grid: TileGrid) -> gt::LineString<i32> {
     let mut points: Vec<gt::Coordinate<i32>> = Vec::new();
     for point in line_string.points_iter() {
-        points.push(gt::Coordinate {
-            x: operations::to_tile_scale(point.x()),
-            y: operations::to_tile_scale(point.y()),
-        });
+        points.push(grid.to_tile(point.x(), point.y()));
     }
     points.into()
 }
 
+// Thickness is in cells of the default grid, see TileGrid::line_thickness.
 pub fn draw_line(
     start: &gt::Point<f64>,
     end: &gt::Point<f64>,
     thickness: u8,
     geo_tile: Arc<GeoTile>,
     data_structure: GeoTilesDataStructure,
+    grid: TileGrid,
+) {
+    let start: gt::Point<f64> = grid.to_grid(start.x(), start.y()).into();
+    let end: gt::Point<f64> = grid.to_grid(end.x(), end.y()).into();
+    draw_grid_line(&start, &end, grid.line_thickness(thickness), geo_tile, data_structure);
+}
+
+// Same as draw_line with start and end in (continuous) grid coordinates, see TileGrid::to_grid.
+fn draw_grid_line(
+    start: &gt::Point<f64>,
+    end: &gt::Point<f64>,
+    thickness: u8,
+    geo_tile: Arc<GeoTile>,
+    data_structure: GeoTilesDataStructure,
 ) {
     if thickness < 1 {
         return;
     }
-    let end_x = end.x() * TILE_SCALE;
-    let end_y = end.y() * TILE_SCALE;
-    let start_x = start.x() * TILE_SCALE;
-    let start_y = start.y() * TILE_SCALE;
+    let end_x = end.x();
+    let end_y = end.y();
+    let start_x = start.x();
+    let start_y = start.y();
     if thickness > 1 {
         // Find which direction the line goes to reach end, then adjust thickness horizontally or vertically.
         if end_x / end_y >= 1.0 {
@@ -457,7 +491,7 @@ pub fn draw_line(
         };
         let vec = locked_data_structure
             .entry(coord)
-            .or_insert(Vec::new());
+            .or_default();
         vec.push(geo_tile.clone());
         // TODO: May need to revisit this for performance reasons. Maybe only sort and dedup once all loading is complete?
         vec.sort_by(geotile_sort);
@@ -499,9 +533,9 @@ fn draw_line_north_of(
     geo_tile: Arc<GeoTile>,
     data_structure: GeoTilesDataStructure,
 ) {
-    draw_line(
-        &gt::Point::new(start.x(), start.y() - operations::from_tile_scale_u8(distance)),
-        &gt::Point::new(end.x(), end.y() - operations::from_tile_scale_u8(distance)),
+    draw_grid_line(
+        &gt::Point::new(start.x(), start.y() - distance as f64),
+        &gt::Point::new(end.x(), end.y() - distance as f64),
         1,
         geo_tile,
         data_structure,
@@ -515,9 +549,9 @@ fn draw_line_south_of(
     geo_tile: Arc<GeoTile>,
     data_structure: GeoTilesDataStructure,
 ) {
-    draw_line(
-        &gt::Point::new(start.x(), start.y() + operations::from_tile_scale_u8(distance)),
-        &gt::Point::new(end.x(), end.y() + operations::from_tile_scale_u8(distance)),
+    draw_grid_line(
+        &gt::Point::new(start.x(), start.y() + distance as f64),
+        &gt::Point::new(end.x(), end.y() + distance as f64),
         1,
         geo_tile,
         data_structure,
@@ -531,9 +565,9 @@ fn draw_line_east_of(
     geo_tile: Arc<GeoTile>,
     data_structure: GeoTilesDataStructure,
 ) {
-    draw_line(
-        &gt::Point::new(start.x() + operations::from_tile_scale_u8(distance), start.y()),
-        &gt::Point::new(end.x() + operations::from_tile_scale_u8(distance), end.y()),
+    draw_grid_line(
+        &gt::Point::new(start.x() + distance as f64, start.y()),
+        &gt::Point::new(end.x() + distance as f64, end.y()),
         1,
         geo_tile,
         data_structure,
@@ -547,9 +581,9 @@ fn draw_line_west_of(
     geo_tile: Arc<GeoTile>,
     data_structure: GeoTilesDataStructure,
 ) {
-    draw_line(
-        &gt::Point::new(start.x() - operations::from_tile_scale_u8(distance), start.y()),
-        &gt::Point::new(end.x() - operations::from_tile_scale_u8(distance), end.y()),
+    draw_grid_line(
+        &gt::Point::new(start.x() - distance as f64, start.y()),
+        &gt::Point::new(end.x() - distance as f64, end.y()),
         1,
         geo_tile,
         data_structure,
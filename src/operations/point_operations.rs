@@ -1,5 +1,5 @@
 use crate::{
-    operations,
+    projection::TileGrid,
     features::{
         barrier_feature::get_barrier_geo_tile, building_feature::get_building_geo_tile,
         highway_feature::get_highway_geo_tile, natural_feature::get_natural_geo_tile,
@@ -25,12 +25,13 @@ pub fn draw_point(
     point: &gt::Point<f64>,
     geo_tile: Arc<GeoTile>,
     data_structure: GeoTilesDataStructure,
+    grid: TileGrid,
 ) {
-    let coord = point_to_coordinates(point);
+    let coord = point_to_coordinates(point, grid);
     let mut locked_data_structure = data_structure.write().unwrap();
     let vec = locked_data_structure
         .entry(coord)
-        .or_insert(Vec::new());
+        .or_default();
     vec.push(geo_tile);
     // TODO: May need to revisit this for performance reasons. Maybe only sort and dedup once all loading is complete?
     vec.sort_by(geotile_sort);
@@ -106,20 +107,15 @@ pub fn point_feature_to_geo_tile(properties: &dyn GeoTileProperties, point: gt::
             address: None,
             geometry: point,
             osm_id,
+            interpolation: None,
         }
     }
 }
 
-pub fn point_to_i32(point: &gt::Point<f64>) -> gt::Point<i32> {
-    gt::Point::new(
-        operations::to_tile_scale(point.x()),
-        operations::to_tile_scale(point.y()),
-    )
+pub fn point_to_i32(point: &gt::Point<f64>, grid: TileGrid) -> gt::Point<i32> {
+    point_to_coordinates(point, grid).into()
 }
 
-fn point_to_coordinates(point: &gt::Point<f64>) -> gt::Coordinate<i32> {
-    gt::Coordinate {
-        x: operations::to_tile_scale(point.x()),
-        y: operations::to_tile_scale(point.y()),
-    }
+fn point_to_coordinates(point: &gt::Point<f64>, grid: TileGrid) -> gt::Coordinate<i32> {
+    grid.to_tile(point.x(), point.y())
 }
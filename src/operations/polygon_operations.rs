@@ -15,58 +15,42 @@ use crate::{
         water_feature::get_water_geo_tile, waterway_feature::get_waterway_geo_tile,
         GeoTile, UnclassifiedType, GeoTileProperties, GeoTilesDataStructure, Geometry, geotile_sort, geotile_dedup
     },
-    operations::{
-        self,
-        line_string_operations::line_string_to_i32,
-    }
+    operations::line_string_operations::line_string_to_i32,
+    projection::TileGrid,
 };
-use geo::algorithm::bounding_rect::BoundingRect;
+use geo::algorithm::{ bounding_rect::BoundingRect, map_coords::MapCoords };
 use geo_types as gt;
 use log::warn;
 use std::sync::Arc;
 
 // Inspired and adapted from https://www.alienryderflex.com/polygon_fill/
-// TODO: Need to create proper unit tests with custom Polygons.
-//       This will confirm whether or not we're missing the surrounding layer of
-//       polygons (may need to apply the full linestring exterior of the polygon).
-// Also need to think about the interior linestrings and how we'll handle those...
+// Interior rings contribute their own crossings to every scanline, so the even-odd fill
+// leaves holes (courtyards, islands, etc.) empty.
 pub fn draw_polygon(
     poly: &gt::Polygon<f64>,
     geo_tile: Arc<GeoTile>,
     data_structure: GeoTilesDataStructure,
+    grid: TileGrid,
 ) {
-    // Establish coordinate system first.
-    let bounding_rect = poly.bounding_rect().unwrap();
-    let min_x = operations::to_tile_scale(bounding_rect.min().x) - 1;
-    let max_x = operations::to_tile_scale(bounding_rect.max().x) + 1;
-    let min_y = operations::to_tile_scale(bounding_rect.min().y) - 1;
-    let max_y = operations::to_tile_scale(bounding_rect.max().y) + 1;
+    // Establish coordinate system first, the polygon is filled in (continuous) grid coordinates.
+    let poly = poly.map_coords(|&(x, y)| grid.to_grid(x, y));
+    let bounding_rect = match poly.bounding_rect() {
+        Some(bounding_rect) => bounding_rect,
+        None => return, // Empty polygon.
+    };
+    let min_x = bounding_rect.min().x.round() as i32 - 1;
+    let max_x = bounding_rect.max().x.round() as i32 + 1;
+    let min_y = bounding_rect.min().y.round() as i32 - 1;
+    let max_y = bounding_rect.max().y.round() as i32 + 1;
 
-    let mut previous_poly_corner: Option<gt::Point<f64>> = None; // The trailing corner that was last checked.
-    let mut first_poly_corner: gt::Point<f64> = gt::Point::new(0_f64, 0_f64);
     // Iterate through horizontal lines in the polygon.
     let mut locked_data_structure = data_structure.write().unwrap();
     for y in min_y..max_y + 1 {
-        let yf64 = operations::from_tile_scale(y);
+        let yf64 = y as f64;
         let mut x_intersections: Vec<i32> = Vec::new();
-        for poly_corner in poly.exterior().points_iter() {
-            if let Some(previous_corner) = previous_poly_corner {
-                if (poly_corner.y() < yf64 && previous_corner.y() >= yf64) || (previous_corner.y() < yf64 && poly_corner.y() >= yf64) {
-                    // The horizontal line is between the two polygon corners (linestring passes through).
-                    let x_intersection: i32 = operations::to_tile_scale(poly_corner.x() + (yf64 - poly_corner.y()) / (previous_corner.y() - poly_corner.y()) * (previous_corner.x() - poly_corner.x()));
-                    x_intersections.push(x_intersection);
-                }
-            } else { // First iteration, keep track of the first corner.
-                first_poly_corner = poly_corner;
-            }
-            previous_poly_corner = Some(poly_corner.clone());
-        }
-        // Need to do one last check from the last poly corner to the first poly corner.
-        let previous_corner: gt::Point<f64> = previous_poly_corner.unwrap();
-        if (first_poly_corner.y() < yf64 && previous_corner.y() >= yf64) || (previous_corner.y() < yf64 && first_poly_corner.y() >= yf64) {
-            // The horizontal line is between the two polygon corners (linestring passes through).
-            let x_intersection: i32 = operations::to_tile_scale(first_poly_corner.x() + (yf64 - first_poly_corner.y()) / (previous_corner.y() - first_poly_corner.y()) * (previous_corner.x() - first_poly_corner.x()));
-            x_intersections.push(x_intersection);
+        push_x_intersections(poly.exterior(), yf64, &mut x_intersections);
+        for interior in poly.interiors() {
+            push_x_intersections(interior, yf64, &mut x_intersections);
         }
         // Sort our intersections from left to right.
         if x_intersections.len() < 2 { continue ; }
@@ -83,7 +67,7 @@ pub fn draw_polygon(
                 for x in corner1..corner2 { // Do we need to use (corner2 + 1) here?
                     let vec = locked_data_structure
                         .entry(gt::Coordinate { x, y })
-                        .or_insert(Vec::new());
+                        .or_default();
                     vec.push(geo_tile.clone());
                     // TODO: May need to revisit this for performance reasons. Maybe only sort and dedup once all loading is complete?
                     vec.sort_by(geotile_sort);
@@ -94,6 +78,24 @@ pub fn draw_polygon(
     }
 }
 
+// Pushes the x cell coordinate of every ring edge crossed by the horizontal line at y (ring and y in grid coordinates).
+fn push_x_intersections(ring: &gt::LineString<f64>, y: f64, x_intersections: &mut Vec<i32>) {
+    let corners: Vec<gt::Point<f64>> = ring.points_iter().collect();
+    let (first_corner, last_corner) = match (corners.first(), corners.last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return,
+    };
+    // Include the edge from the last corner back to the first in case the ring isn't closed.
+    let edges = corners.windows(2).map(|pair| (pair[0], pair[1])).chain(std::iter::once((last_corner, first_corner)));
+    for (previous_corner, poly_corner) in edges {
+        if (poly_corner.y() < y && previous_corner.y() >= y) || (previous_corner.y() < y && poly_corner.y() >= y) {
+            // The horizontal line is between the two polygon corners (linestring passes through).
+            let x_intersection: i32 = (poly_corner.x() + (y - poly_corner.y()) / (previous_corner.y() - poly_corner.y()) * (previous_corner.x() - poly_corner.x())).round() as i32;
+            x_intersections.push(x_intersection);
+        }
+    }
+}
+
 pub fn polygon_feature_to_geo_tile(
     properties: &dyn GeoTileProperties,
     polygon: gt::Polygon<f64>,
@@ -172,15 +174,72 @@ pub fn polygon_feature_to_geo_tile(
             address: None,
             geometry: polygon,
             osm_id,
+            interpolation: None,
         }
     }
 }
 
-pub fn polygon_to_i32(polygon: &gt::Polygon<f64>) -> gt::Polygon<i32> {
-    let exterior_points = line_string_to_i32(polygon.exterior());
+pub fn polygon_to_i32(polygon: &gt::Polygon<f64>, grid: TileGrid) -> gt::Polygon<i32> {
+    let exterior_points = line_string_to_i32(polygon.exterior(), grid);
     let mut interiors_points = Vec::new();
     for interior in polygon.interiors() {
-        interiors_points.push(line_string_to_i32(interior));
+        interiors_points.push(line_string_to_i32(interior, grid));
     }
     gt::Polygon::new(exterior_points, interiors_points)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ features::TILE_SCALE, operations };
+    use std::{collections::HashMap, sync::RwLock};
+
+    // Polygon coordinates are given in tile units to keep the tests readable.
+    fn ring(points: &[(i32, i32)]) -> gt::LineString<f64> {
+        points.iter()
+            .map(|&(x, y)| (operations::from_tile_scale(x, TILE_SCALE), operations::from_tile_scale(y, TILE_SCALE)))
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    fn draw(poly: gt::Polygon<f64>) -> GeoTilesDataStructure {
+        let data_structure: GeoTilesDataStructure = Arc::new(RwLock::new(HashMap::new()));
+        let properties = serde_json::json!({ "building": "yes", "id": "way/1" });
+        let geo_tile = Arc::new(polygon_feature_to_geo_tile(properties.as_object().unwrap(), poly.clone()));
+        draw_polygon(&poly, geo_tile, data_structure.clone(), TileGrid::default());
+        data_structure
+    }
+
+    fn is_drawn(data_structure: &GeoTilesDataStructure, x: i32, y: i32) -> bool {
+        data_structure.read().unwrap().contains_key(&gt::Coordinate { x, y })
+    }
+
+    #[test]
+    fn test_draw_polygon() {
+        let square = ring(&[(0, 0), (10, 0), (10, 10), (0, 10), (0, 0)]);
+        let data_structure = draw(gt::Polygon::new(square, vec![]));
+        for &(x, y) in [(1, 1), (4, 5), (8, 8)].iter() {
+            assert!(is_drawn(&data_structure, x, y));
+        }
+        for &(x, y) in [(-2, 5), (12, 5), (5, -2), (5, 12)].iter() {
+            assert!(!is_drawn(&data_structure, x, y));
+        }
+    }
+
+    #[test]
+    fn test_draw_polygon_with_holes() {
+        let square = ring(&[(0, 0), (20, 0), (20, 20), (0, 20), (0, 0)]);
+        let holes = vec![
+            ring(&[(3, 3), (7, 3), (7, 7), (3, 7), (3, 3)]),
+            // Clockwise and left open, as can happen with hand-built polygons.
+            ring(&[(12, 12), (12, 17), (17, 17), (17, 12)]),
+        ];
+        let data_structure = draw(gt::Polygon::new(square, holes));
+        for &(x, y) in [(5, 5), (4, 6), (14, 14), (16, 13)].iter() {
+            assert!(!is_drawn(&data_structure, x, y));
+        }
+        for &(x, y) in [(1, 5), (9, 5), (5, 1), (5, 9), (10, 10), (14, 10), (18, 14)].iter() {
+            assert!(is_drawn(&data_structure, x, y));
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use geo::algorithm::orient::{Direction, Orient};
+use geo_types as gt;
+
+// A member way node: its OSM id (used to stitch ways together) and its lon/lat coordinate.
+pub type WayNode = (i64, gt::Coordinate<f64>);
+
+// Stitches ways sharing end nodes into as few node lists as possible, reversing ways where needed.
+// Adapted from iD's relation.js (same as osmtogeojson). Dangling ways and unclosed rings are left as-is.
+pub fn join_ways(mut ways: Vec<Vec<WayNode>>) -> Vec<Vec<WayNode>> {
+    let fit_together = |a: Option<&WayNode>, b: Option<&WayNode>| match (a, b) {
+        (Some(a), Some(b)) => a.0 == b.0,
+        _ => false,
+    };
+    let mut joined = Vec::new();
+    while let Some(mut current) = ways.pop() {
+        while !ways.is_empty() && !fit_together(current.first(), current.last()) {
+            let mut found = None;
+            for (index, way) in ways.iter().enumerate() {
+                if fit_together(current.last(), way.first()) {
+                    found = Some((index, true, false));
+                } else if fit_together(current.last(), way.last()) {
+                    found = Some((index, true, true));
+                } else if fit_together(current.first(), way.last()) {
+                    found = Some((index, false, false));
+                } else if fit_together(current.first(), way.first()) {
+                    found = Some((index, false, true));
+                }
+                if found.is_some() {
+                    break;
+                }
+            }
+            let (index, append, reverse) = match found {
+                Some(found) => found,
+                None => break, // Invalid geometry (dangling way, unclosed ring).
+            };
+            let mut way = ways.remove(index);
+            if reverse {
+                way.reverse();
+            }
+            if append {
+                current.extend(way.into_iter().skip(1));
+            } else {
+                way.pop();
+                way.append(&mut current);
+                current = way;
+            }
+        }
+        joined.push(current);
+    }
+    joined
+}
+
+// Joins the outer and inner member ways of a multipolygon relation into rings, then gives every
+// inner ring to the first outer ring containing one of its nodes (holes in empty space are dropped).
+// Rings with less than four nodes are ignored and the resulting polygons follow RFC 7946 winding.
+pub fn assemble_multipolygon(outer_ways: Vec<Vec<WayNode>>, inner_ways: Vec<Vec<WayNode>>) -> gt::MultiPolygon<f64> {
+    let mut clusters: Vec<Vec<Vec<WayNode>>> = join_ways(outer_ways)
+        .into_iter()
+        .filter(|ring| ring.len() >= 4)
+        .map(|ring| vec![ring])
+        .collect();
+    for inner in join_ways(inner_ways).into_iter().filter(|ring| ring.len() >= 4) {
+        let outer = clusters
+            .iter_mut()
+            .find(|cluster| inner.iter().any(|node| coordinate_in_ring(&node.1, &cluster[0])));
+        if let Some(cluster) = outer {
+            cluster.push(inner);
+        }
+    }
+    let polygons = clusters
+        .into_iter()
+        .map(|mut cluster| {
+            let exterior = way_nodes_to_line_string(&cluster.remove(0));
+            let interiors = cluster.iter().map(|ring| way_nodes_to_line_string(ring)).collect();
+            gt::Polygon::new(exterior, interiors).orient(Direction::Default)
+        })
+        .collect();
+    gt::MultiPolygon(polygons)
+}
+
+pub fn way_nodes_to_line_string(way_nodes: &[WayNode]) -> gt::LineString<f64> {
+    way_nodes.iter().map(|node| node.1).collect::<Vec<_>>().into()
+}
+
+// Ray casting point-in-polygon test (https://wrf.ecse.rpi.edu/Research/Short_Notes/pnpoly.html).
+fn coordinate_in_ring(coordinate: &gt::Coordinate<f64>, ring: &[WayNode]) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for i in 0..ring.len() {
+        let (ci, cj) = (ring[i].1, ring[j].1);
+        if (ci.y > coordinate.y) != (cj.y > coordinate.y)
+            && coordinate.x < (cj.x - ci.x) * (coordinate.y - ci.y) / (cj.y - ci.y) + ci.x
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn way(nodes: &[(i64, f64, f64)]) -> Vec<WayNode> {
+        nodes.iter().map(|&(id, x, y)| (id, gt::Coordinate { x, y })).collect()
+    }
+
+    #[test]
+    fn test_assemble_multipolygon() {
+        // Outer ring split over two ways, the second one reversed, with a hole inside it.
+        let outer_ways = vec![
+            way(&[(1, 0.0, 0.0), (2, 10.0, 0.0), (3, 10.0, 10.0)]),
+            way(&[(1, 0.0, 0.0), (4, 0.0, 10.0), (3, 10.0, 10.0)]),
+        ];
+        let inner_ways = vec![way(&[(5, 2.0, 2.0), (6, 2.0, 4.0), (7, 4.0, 4.0), (5, 2.0, 2.0)])];
+        let multi_polygon = assemble_multipolygon(outer_ways, inner_ways);
+        assert_eq!(multi_polygon.0.len(), 1);
+        let polygon = &multi_polygon.0[0];
+        assert_eq!(polygon.exterior().0.len(), 5);
+        assert_eq!(polygon.interiors().len(), 1);
+        // A hole outside of every outer ring is dropped.
+        let outer_ways = vec![way(&[(1, 0.0, 0.0), (2, 1.0, 0.0), (3, 1.0, 1.0), (1, 0.0, 0.0)])];
+        let inner_ways = vec![way(&[(5, 2.0, 2.0), (6, 2.0, 4.0), (7, 4.0, 4.0), (5, 2.0, 2.0)])];
+        assert!(assemble_multipolygon(outer_ways, inner_ways).0[0].interiors().is_empty());
+    }
+}
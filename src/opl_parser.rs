@@ -0,0 +1,147 @@
+use std::io::BufRead;
+use osm_xml;
+
+use crate::{ compression, error::ParseError };
+
+// OPL is the line based text format of osmium (https://osmcode.org/opl-file-format/), eg.
+// n1 v1 Tamenity=bench x-75.0 y45.0
+// w10 v1 Thighway=path Nn1,n2
+// r20 v1 Ttype=multipolygon Mw10@outer,w11@inner
+pub fn parse_opl_file(filename: String) -> Result<osm_xml::OSM, ParseError> {
+    let file = compression::open_file(&filename).map_err(|error| ParseError::new(error.to_string()).in_file(&filename))?;
+    parse_opl(file).map_err(|error| error.in_file(&filename))
+}
+
+pub fn parse_opl<R: BufRead>(source: R) -> Result<osm_xml::OSM, ParseError> {
+    let mut osm_data = osm_xml::OSM { bounds: None, nodes: Default::default(), ways: Default::default(), relations: Default::default() };
+    for (index, line) in source.lines().enumerate() {
+        let line = line.map_err(|error| ParseError::new(error.to_string()))?;
+        parse_line(&line, &mut osm_data).map_err(|message| ParseError::new(format!("line {}: {}", index + 1, message)))?;
+    }
+    Ok(osm_data)
+}
+
+fn parse_line(line: &str, osm_data: &mut osm_xml::OSM) -> Result<(), String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(());
+    }
+    let mut fields = line.split(' ').filter(|field| !field.is_empty());
+    let object = fields.next().unwrap();
+    let id: osm_xml::Id = object.get(1..).and_then(|id| id.parse().ok()).ok_or_else(|| format!("invalid object id {}", object))?;
+    let mut tags = Vec::new();
+    let mut lon = None;
+    let mut lat = None;
+    let mut nodes = Vec::new();
+    let mut members = Vec::new();
+    for field in fields {
+        let mut chars = field.chars();
+        let key = chars.next().unwrap();
+        let value = chars.as_str();
+        match key {
+            // Deleted objects (in change files) have nothing to draw.
+            'd' if value == "D" => return Ok(()),
+            'T' => tags = parse_tags(value)?,
+            'x' if !value.is_empty() => lon = Some(parse_coordinate(value)?),
+            'y' if !value.is_empty() => lat = Some(parse_coordinate(value)?),
+            'N' => nodes = parse_list(value, |item| Ok(osm_xml::UnresolvedReference::Node(parse_reference(item, 'n')?)))?,
+            'M' => members = parse_list(value, parse_member)?,
+            // Version, changeset, timestamp, user, etc.
+            _ => {}
+        }
+    }
+    match object.chars().next() {
+        Some('n') => match (lat, lon) {
+            (Some(lat), Some(lon)) => {
+                osm_data.nodes.insert(id, osm_xml::Node { id, lat, lon, tags });
+            }
+            _ => return Err(format!("node {} without coordinates", id)),
+        },
+        Some('w') => {
+            osm_data.ways.insert(id, osm_xml::Way { id, tags, nodes });
+        }
+        Some('r') => {
+            osm_data.relations.insert(id, osm_xml::Relation { id, members, tags });
+        }
+        // Changesets are not part of the map data.
+        Some('c') => {}
+        _ => return Err(format!("unknown object type {}", object)),
+    }
+    Ok(())
+}
+
+fn parse_list<T, F: Fn(&str) -> Result<T, String>>(value: &str, parse_item: F) -> Result<Vec<T>, String> {
+    value.split(',').filter(|item| !item.is_empty()).map(parse_item).collect()
+}
+
+fn parse_tags(value: &str) -> Result<Vec<osm_xml::Tag>, String> {
+    parse_list(value, |tag| {
+        let mut parts = tag.splitn(2, '=');
+        let key = unescape(parts.next().unwrap())?;
+        let val = unescape(parts.next().ok_or_else(|| format!("tag without a value: {}", tag))?)?;
+        Ok(osm_xml::Tag { key, val })
+    })
+}
+
+fn parse_coordinate(value: &str) -> Result<f64, String> {
+    value.parse::<f64>().ok().filter(|coordinate| coordinate.is_finite()).ok_or_else(|| format!("invalid coordinate {}", value))
+}
+
+fn parse_reference(item: &str, object_type: char) -> Result<osm_xml::Id, String> {
+    item.strip_prefix(object_type)
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| format!("invalid reference {}", item))
+}
+
+fn parse_member(item: &str) -> Result<osm_xml::Member, String> {
+    let (reference, role) = item.split_once('@').unwrap_or((item, ""));
+    let role = unescape(role)?;
+    match reference.chars().next() {
+        Some('n') => Ok(osm_xml::Member::Node(osm_xml::UnresolvedReference::Node(parse_reference(reference, 'n')?), role)),
+        Some('w') => Ok(osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(parse_reference(reference, 'w')?), role)),
+        Some('r') => Ok(osm_xml::Member::Relation(osm_xml::UnresolvedReference::Relation(parse_reference(reference, 'r')?), role)),
+        _ => Err(format!("invalid member {}", item)),
+    }
+}
+
+// Special characters are written as their hex unicode code point between percent signs (eg. %20% for a space).
+fn unescape(value: &str) -> Result<String, String> {
+    let mut unescaped = String::new();
+    let mut parts = value.split('%');
+    unescaped.push_str(parts.next().unwrap());
+    while let Some(code_point) = parts.next() {
+        let character = u32::from_str_radix(code_point, 16).ok().and_then(std::char::from_u32);
+        match (character, parts.next()) {
+            (Some(character), Some(rest)) => {
+                unescaped.push(character);
+                unescaped.push_str(rest);
+            }
+            _ => return Err(format!("invalid escape sequence in {}", value)),
+        }
+    }
+    Ok(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_opl() {
+        let opl = "# Test fixture
+n1 v1 dV Tamenity=bench,name=Park%20%bench x-75.0 y45.0
+n2 v1 dV T x-75.001 y45.0
+n3 v2 dD c5 t2020-01-01T00:00:00Z i1 utest T x y
+w10 v1 dV Thighway=path Nn1,n2
+r20 v1 dV Ttype=multipolygon Mw10@outer,n1@
+";
+        let osm_data = parse_opl(opl.as_bytes()).unwrap();
+        assert_eq!(osm_data.nodes.len(), 2);
+        assert_eq!(osm_data.nodes[&1].tags[1].val, "Park bench");
+        assert_eq!(osm_data.nodes[&2].lon, -75.001);
+        assert_eq!(osm_data.ways[&10].nodes, vec![osm_xml::UnresolvedReference::Node(1), osm_xml::UnresolvedReference::Node(2)]);
+        assert_eq!(osm_data.relations[&20].members[0], osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(10), "outer".to_string()));
+        let error = parse_opl("n1 v1 x-75.0\n".as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "<input>: line 1: node 1 without coordinates");
+    }
+}
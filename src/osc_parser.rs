@@ -0,0 +1,162 @@
+use std::io::{ Cursor, Read };
+use osm_xml;
+use xml::{
+    reader::{ EventReader, XmlEvent },
+    writer::{ EmitterConfig, EventWriter, XmlEvent as WriterXmlEvent },
+};
+
+use crate::{ compression, error::ParseError };
+
+// The create, modify and delete blocks of an osmChange (.osc) file. Deleted objects are only listed by
+// osm_id (node/123, way/456, relation/789) since their coordinates are often left out.
+pub struct OsmChange {
+    pub create: osm_xml::OSM,
+    pub modify: osm_xml::OSM,
+    pub delete: Vec<String>,
+}
+
+pub fn parse_osm_change_file(filename: String) -> Result<OsmChange, ParseError> {
+    let file = compression::open_file(&filename).map_err(|error| to_parse_error(error).in_file(&filename))?;
+    parse_osm_change(file).map_err(|error| error.in_file(&filename))
+}
+
+fn to_parse_error<E: ToString>(error: E) -> ParseError {
+    ParseError::new(error.to_string())
+}
+
+// Every create and modify block is copied into its own <osm> document (blocks of the same kind can appear
+// several times) which is then parsed by osm_xml. osm_xml skips the nodes without coordinates, the elements
+// of the delete blocks are read straight from the XML instead.
+pub fn parse_osm_change<R: Read>(source: R) -> Result<OsmChange, ParseError> {
+    let new_writer = || -> Result<EventWriter<Vec<u8>>, ParseError> {
+        let mut writer = EventWriter::new_with_config(Vec::new(), EmitterConfig::new().write_document_declaration(false));
+        writer.write(WriterXmlEvent::start_element("osm")).map_err(to_parse_error)?;
+        Ok(writer)
+    };
+    let mut writers = [new_writer()?, new_writer()?];
+    let mut delete = Vec::new();
+    let mut current_block: Option<usize> = None;
+    let mut in_delete_block = false;
+    let mut depth = 0;
+    for event in EventReader::new(source) {
+        let event = event.map_err(to_parse_error)?;
+        match event {
+            XmlEvent::StartElement { ref name, ref attributes, .. } => {
+                depth += 1;
+                if depth == 2 {
+                    current_block = match name.local_name.as_str() {
+                        "create" => Some(0),
+                        "modify" => Some(1),
+                        _ => None,
+                    };
+                    in_delete_block = name.local_name == "delete";
+                    continue;
+                }
+                if depth == 3 && in_delete_block && matches!(name.local_name.as_str(), "node" | "way" | "relation") {
+                    let element_type = name.local_name.as_str();
+                    let id = attributes
+                        .iter()
+                        .find(|attribute| attribute.name.local_name == "id")
+                        .and_then(|attribute| attribute.value.parse::<i64>().ok())
+                        .ok_or_else(|| ParseError::new(format!("Deleted {} without a valid id", element_type)))?;
+                    delete.push(format!("{}/{}", element_type, id));
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                depth -= 1;
+                if depth == 1 {
+                    current_block = None;
+                    in_delete_block = false;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        if let (Some(block), Some(writer_event)) = (current_block, event.as_writer_event()) {
+            writers[block].write(writer_event).map_err(to_parse_error)?;
+        }
+    }
+    let mut blocks = Vec::new();
+    for mut writer in writers {
+        writer.write(WriterXmlEvent::end_element()).map_err(to_parse_error)?;
+        blocks.push(osm_xml::OSM::parse(Cursor::new(writer.into_inner())).map_err(to_parse_error)?);
+    }
+    let modify = blocks.pop().unwrap();
+    let create = blocks.pop().unwrap();
+    Ok(OsmChange { create, modify, delete })
+}
+
+// Same format as the ids produced by osmtogeojson (node/123, way/456, relation/789).
+pub fn osm_ids(osm_data: &osm_xml::OSM) -> Vec<String> {
+    let nodes = osm_data.nodes.keys().map(|id| format!("node/{}", id));
+    let ways = osm_data.ways.keys().map(|id| format!("way/{}", id));
+    let relations = osm_data.relations.keys().map(|id| format!("relation/{}", id));
+    nodes.chain(ways).chain(relations).collect()
+}
+
+// Diffs only contain the changed objects, so a way whose nodes (or a relation whose member ways)
+// aren't part of the block can't be drawn from it. Those are removed from the block and returned
+// as (osm_id, tags) so that their previous geometry can be reused.
+pub fn take_unresolved_objects(osm_data: &mut osm_xml::OSM) -> Vec<(String, Vec<osm_xml::Tag>)> {
+    let mut unresolved = Vec::new();
+    let nodes = &osm_data.nodes;
+    let way_ids: Vec<osm_xml::Id> = osm_data.ways
+        .values()
+        .filter(|way| way.nodes.iter().any(|reference| match reference {
+            osm_xml::UnresolvedReference::Node(id) => !nodes.contains_key(id),
+            _ => true,
+        }))
+        .map(|way| way.id)
+        .collect();
+    for id in way_ids {
+        let way = osm_data.ways.remove(&id).unwrap();
+        unresolved.push((format!("way/{}", id), way.tags));
+    }
+    let ways = &osm_data.ways;
+    let relation_ids: Vec<osm_xml::Id> = osm_data.relations
+        .values()
+        .filter(|relation| relation.members.iter().any(|member| match member {
+            osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(id), _) => !ways.contains_key(id),
+            _ => false,
+        }))
+        .map(|relation| relation.id)
+        .collect();
+    for id in relation_ids {
+        let relation = osm_data.relations.remove(&id).unwrap();
+        unresolved.push((format!("relation/{}", id), relation.tags));
+    }
+    unresolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_osm_change() {
+        let osc = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <osmChange version="0.6" generator="test">
+              <create>
+                <node id="1" version="1" lat="45.0" lon="-75.0"><tag k="amenity" v="bench"/></node>
+              </create>
+              <modify>
+                <node id="2" version="2" lat="45.1" lon="-75.1"/>
+                <way id="10" version="3"><nd ref="2"/><nd ref="3"/><tag k="highway" v="path"/></way>
+              </modify>
+              <create>
+                <node id="4" version="1" lat="45.2" lon="-75.2"/>
+              </create>
+              <delete>
+                <relation id="20" version="4"/>
+                <node id="5" version="3"/>
+              </delete>
+            </osmChange>"#;
+        let osm_change = parse_osm_change(osc.as_bytes()).unwrap();
+        assert_eq!(osm_change.create.nodes.len(), 2);
+        assert_eq!(osm_change.create.nodes[&1].tags[0].val, "bench");
+        assert_eq!(osm_change.modify.nodes.len(), 1);
+        assert_eq!(osm_change.modify.ways[&10].nodes.len(), 2);
+        // Deleted nodes usually come without coordinates.
+        assert_eq!(osm_change.delete, vec!["relation/20", "node/5"]);
+    }
+}
@@ -1,8 +1,8 @@
-use std::fs::File;
 use osm_xml;
 
+use crate::{ compression, error::ParseError };
 
-pub fn parse_osm_file(filename: String) -> Result<osm_xml::OSM, osm_xml::error::Error> {
-    let file = File::open(filename).unwrap();
-    osm_xml::OSM::parse(file)
-}
\ No newline at end of file
+pub fn parse_osm_file(filename: String) -> Result<osm_xml::OSM, ParseError> {
+    let file = compression::open_file(&filename).map_err(|error| ParseError::new(error.to_string()).in_file(&filename))?;
+    osm_xml::OSM::parse(file).map_err(|error| ParseError::new(error.to_string()).in_file(&filename))
+}
@@ -1,42 +1,509 @@
-use std::fs::File;
-use std::io::Write;
-use std::process::Command;
-use std::{error::Error, fmt};
+/*
+ * Native port of the osmtogeojson (https://github.com/tyrasd/osmtogeojson) conversion rules.
+ * Produces the same flat-properties FeatureCollection the Node tool would (minus the meta
+ * attributes, which osm_xml does not expose) so it can go straight into process_geojson.
+*/
 
-#[derive(Debug)]
-struct ConvertError { message: String }
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::Write,
+};
+use geojson as gj;
+use geo_types as gt;
+use osm_xml as osm;
+use serde_json::{Map, Value as JsonValue};
 
-impl Error for ConvertError {}
+use crate::{
+    features::WayNodes,
+    filter::{ Filter, UNINTERESTING_TAGS },
+    operations::relation_operations::{assemble_multipolygon, join_ways, way_nodes_to_line_string, WayNode},
+    osm_parser,
+};
 
-impl fmt::Display for ConvertError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.message)
-    }
+enum PolygonRule {
+    All,
+    Whitelist(&'static [&'static str]),
+    Blacklist(&'static [&'static str]),
 }
 
+// Closed ways are only areas if one of their tags matches these rules.
+// See https://github.com/tyrasd/osm-polygon-features
+const POLYGON_FEATURES: [(&str, PolygonRule); 27] = [
+    ("building", PolygonRule::All),
+    ("highway", PolygonRule::Whitelist(&["services", "rest_area", "escape", "elevator"])),
+    ("natural", PolygonRule::Blacklist(&["coastline", "cliff", "ridge", "arete", "tree_row"])),
+    ("landuse", PolygonRule::All),
+    ("waterway", PolygonRule::Whitelist(&["riverbank", "dock", "boatyard", "dam"])),
+    ("amenity", PolygonRule::All),
+    ("leisure", PolygonRule::All),
+    ("barrier", PolygonRule::Whitelist(&["city_wall", "ditch", "hedge", "retaining_wall", "wall", "spikes"])),
+    ("railway", PolygonRule::Whitelist(&["station", "turntable", "roundhouse", "platform"])),
+    ("area", PolygonRule::All),
+    ("boundary", PolygonRule::All),
+    ("man_made", PolygonRule::Blacklist(&["cutline", "embankment", "pipeline"])),
+    ("power", PolygonRule::Whitelist(&["plant", "substation", "generator", "transformer"])),
+    ("place", PolygonRule::All),
+    ("shop", PolygonRule::All),
+    ("aeroway", PolygonRule::Blacklist(&["taxiway"])),
+    ("tourism", PolygonRule::All),
+    ("historic", PolygonRule::All),
+    ("public_transport", PolygonRule::All),
+    ("office", PolygonRule::All),
+    ("building:part", PolygonRule::All),
+    ("military", PolygonRule::All),
+    ("ruins", PolygonRule::All),
+    ("area:highway", PolygonRule::All),
+    ("craft", PolygonRule::All),
+    ("golf", PolygonRule::All),
+    ("indoor", PolygonRule::All),
+];
+
 pub fn convert_osm_to_geojson(
     osm_file: String,
     geojson_file: String,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let mut file;
-    if let Result::Ok(output) = Command::new("osmtogeojson").arg(&osm_file).output() {
-        file = match File::create(geojson_file) {
-            Result::Ok(f) => f,
-            Result::Err(err) => return Err(Box::new(err)),
+    let osm_data = osm_parser::parse_osm_file(osm_file)?;
+    let geojson = osm_to_geojson(&osm_data);
+    let mut file = File::create(geojson_file)?;
+    write!(file, "{}", geojson)?;
+    Ok(())
+}
+
+pub fn osm_to_geojson(osm_data: &osm::OSM) -> gj::GeoJson {
+    // Features are emitted in the same order as osmtogeojson: relations, then areas, then lines, then points.
+    let mut relations: Vec<&osm::Relation> = osm_data.relations.values().collect();
+    relations.sort_by_key(|relation| relation.id);
+    let mut ways: Vec<&osm::Way> = osm_data.ways.values().collect();
+    ways.sort_by_key(|way| way.id);
+    let mut nodes: Vec<&osm::Node> = osm_data.nodes.values().collect();
+    nodes.sort_by_key(|node| node.id);
+
+    let mut relation_features = Vec::new();
+    let mut relation_node_ids = HashSet::new();
+    let mut skippable_way_ids = HashSet::new();
+    for relation in relations {
+        for member in relation.members.iter() {
+            if let osm::Member::Node(osm::UnresolvedReference::Node(id), _) = member {
+                relation_node_ids.insert(*id);
+            }
+        }
+        let feature = match fetch_tag(&relation.tags, "type") {
+            Some("route") | Some("waterway") => route_feature(osm_data, relation),
+            Some("multipolygon") | Some("boundary") => multipolygon_feature(osm_data, relation, &mut skippable_way_ids),
+            _ => None,
         };
-        match write!(file, "{}", String::from_utf8_lossy(&output.stdout)) {
-            Result::Ok(_) => return Ok(()),
-            Result::Err(err) => return Err(Box::new(err)),
+        if let Some(feature) = feature {
+            relation_features.push(feature);
+        }
+    }
+
+    let mut polygon_features = Vec::new();
+    let mut line_features = Vec::new();
+    let mut way_node_ids = HashSet::new();
+    for way in ways.iter() {
+        for node in way.nodes.iter() {
+            if let osm::UnresolvedReference::Node(id) = node {
+                way_node_ids.insert(*id);
+            }
+        }
+    }
+    for way in ways {
+        // Already rendered as (part of) a multipolygon.
+        if skippable_way_ids.contains(&way.id) {
+            continue;
+        }
+        let way_nodes = resolve_way_nodes(osm_data, way);
+        if way_nodes.len() <= 1 {
+            continue;
+        }
+        let mut coordinates = nodes_to_positions(&way_nodes);
+        let first = resolve_node(osm_data, way.nodes.first());
+        let last = resolve_node(osm_data, way.nodes.last());
+        let closed = match (first, last) {
+            (Some(first), Some(last)) => first.id == last.id,
+            _ => false,
         };
-    } else if let Result::Ok(output) = Command::new("osmtogeojson.cmd").arg(&osm_file).output() { // Windows
-        file = match File::create(geojson_file) {
-            Result::Ok(f) => f,
-            Result::Err(err) => return Err(Box::new(err)),
+        if closed && is_polygon_feature(&way.tags) {
+            rewind_ring(&mut coordinates, true);
+            polygon_features.push(osm_feature("way", way.id, &way.tags, gj::Value::Polygon(vec![coordinates])));
+        } else {
+            line_features.push(osm_feature("way", way.id, &way.tags, gj::Value::LineString(coordinates)));
+        }
+    }
+
+    // Nodes that only exist to shape a way are dropped unless they carry interesting tags.
+    let mut point_features = Vec::new();
+    for node in nodes {
+        if !way_node_ids.contains(&node.id)
+            || relation_node_ids.contains(&node.id)
+            || has_interesting_tags(&node.tags, |_| false)
+        {
+            point_features.push(osm_feature("node", node.id, &node.tags, gj::Value::Point(vec![node.lon, node.lat])));
+        }
+    }
+
+    let mut features = relation_features;
+    features.append(&mut polygon_features);
+    features.append(&mut line_features);
+    features.append(&mut point_features);
+    gj::GeoJson::FeatureCollection(gj::FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    })
+}
+
+// Keeps the objects matching the filter (old-style multipolygons when their outer way does, see
+// multipolygon_feature) along with what converting them takes: the members of the kept relations and the
+// nodes of the kept ways. Those dependencies are converted too, filter the resulting features as well.
+pub fn filter_osm_data(osm_data: &osm::OSM, filter: &Filter) -> osm::OSM {
+    let mut filtered = osm::OSM { bounds: osm_data.bounds, nodes: Default::default(), ways: Default::default(), relations: Default::default() };
+    for relation in osm_data.relations.values() {
+        let wanted = filter.matches(&relation.tags) || simple_multipolygon_outer_way(osm_data, relation).is_some_and(|way| filter.matches(&way.tags));
+        if !wanted {
+            continue;
+        }
+        for member in relation.members.iter() {
+            match member {
+                osm::Member::Node(reference, _) | osm::Member::Way(reference, _) => match osm_data.resolve_reference(reference) {
+                    osm::Reference::Node(node) => {
+                        filtered.nodes.insert(node.id, node.clone());
+                    }
+                    osm::Reference::Way(way) => {
+                        filtered.ways.insert(way.id, way.clone());
+                    }
+                    _ => {}
+                },
+                // Nested relations aren't converted.
+                osm::Member::Relation(_, _) => {}
+            }
+        }
+        filtered.relations.insert(relation.id, relation.clone());
+    }
+    for way in osm_data.ways.values().filter(|way| filter.matches(&way.tags)) {
+        filtered.ways.insert(way.id, way.clone());
+    }
+    for node in osm_data.nodes.values().filter(|node| filter.matches(&node.tags)) {
+        filtered.nodes.insert(node.id, node.clone());
+    }
+    let way_nodes: Vec<&osm::Node> = filtered.ways.values().flat_map(|way| resolve_way_nodes(osm_data, way)).collect();
+    for node in way_nodes {
+        filtered.nodes.insert(node.id, node.clone());
+    }
+    filtered
+}
+
+// Old-style multipolygons are untagged relations whose single outer way holds the tags.
+fn simple_multipolygon_outer_way<'a>(osm_data: &'a osm::OSM, relation: &osm::Relation) -> Option<&'a osm::Way> {
+    if !matches!(fetch_tag(&relation.tags, "type"), Some("multipolygon") | Some("boundary"))
+        || has_interesting_tags(&relation.tags, |tag| tag.key == "type")
+    {
+        return None;
+    }
+    let mut outers = relation.members.iter().filter_map(|member| match member {
+        osm::Member::Way(reference, role) if role == "outer" => Some(reference),
+        _ => None,
+    });
+    let outer = outers.next()?;
+    if outers.next().is_some() {
+        return None;
+    }
+    match osm_data.resolve_reference(outer) {
+        osm::Reference::Way(way) => Some(way),
+        _ => None,
+    }
+}
+
+pub fn is_polygon_feature(tags: &[osm::Tag]) -> bool {
+    if fetch_tag(tags, "area") == Some("no") {
+        return false;
+    }
+    tags.iter().any(|tag| {
+        if tag.val == "no" {
+            return false;
+        }
+        POLYGON_FEATURES.iter().any(|(key, rule)| {
+            *key == tag.key && match rule {
+                PolygonRule::All => true,
+                PolygonRule::Whitelist(values) => values.contains(&tag.val.as_str()),
+                PolygonRule::Blacklist(values) => !values.contains(&tag.val.as_str()),
+            }
+        })
+    })
+}
+
+pub fn has_interesting_tags<F: Fn(&osm::Tag) -> bool>(tags: &[osm::Tag], ignored: F) -> bool {
+    tags.iter().any(|tag| !UNINTERESTING_TAGS.contains(&tag.key.as_str()) && !ignored(tag))
+}
+
+// The nodes of every way (by osm_id), the ones missing from the data are left out as when drawing the way.
+pub fn way_nodes(osm_data: &osm::OSM) -> WayNodes {
+    osm_data.ways
+        .values()
+        .map(|way| {
+            let nodes = resolve_way_nodes(osm_data, way)
+                .iter()
+                .map(|node| (node.id, gt::Coordinate { x: node.lon, y: node.lat }))
+                .collect();
+            (format!("way/{}", way.id), nodes)
+        })
+        .collect()
+}
+
+fn fetch_tag<'a>(tags: &'a [osm::Tag], key: &str) -> Option<&'a str> {
+    tags.iter().find(|tag| tag.key == key).map(|tag| tag.val.as_str())
+}
+
+fn resolve_node<'a>(osm_data: &'a osm::OSM, reference: Option<&osm::UnresolvedReference>) -> Option<&'a osm::Node> {
+    match osm_data.resolve_reference(reference?) {
+        osm::Reference::Node(node) => Some(node),
+        _ => None,
+    }
+}
+
+// Missing nodes (ways cut by the edge of the downloaded area) are skipped.
+fn resolve_way_nodes<'a>(osm_data: &'a osm::OSM, way: &osm::Way) -> Vec<&'a osm::Node> {
+    way.nodes
+        .iter()
+        .filter_map(|reference| resolve_node(osm_data, Some(reference)))
+        .collect()
+}
+
+fn resolve_way_members(osm_data: &osm::OSM, relation: &osm::Relation, role: Option<&str>) -> Vec<Vec<WayNode>> {
+    relation.members
+        .iter()
+        .filter_map(|member| match member {
+            osm::Member::Way(reference, member_role) if role.map_or(true, |role| role == member_role) => {
+                match osm_data.resolve_reference(reference) {
+                    osm::Reference::Way(way) => Some(
+                        resolve_way_nodes(osm_data, way)
+                            .iter()
+                            .map(|node| (node.id, gt::Coordinate { x: node.lon, y: node.lat }))
+                            .collect(),
+                    ),
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn nodes_to_positions(nodes: &[&osm::Node]) -> Vec<gj::Position> {
+    nodes.iter().map(|node| vec![node.lon, node.lat]).collect()
+}
+
+fn route_feature(osm_data: &osm::OSM, relation: &osm::Relation) -> Option<gj::Feature> {
+    let mut line_strings: Vec<gt::LineString<f64>> = join_ways(resolve_way_members(osm_data, relation, None))
+        .iter()
+        .filter(|way_nodes| !way_nodes.is_empty())
+        .map(|way_nodes| way_nodes_to_line_string(way_nodes))
+        .collect();
+    let value = match line_strings.len() {
+        0 => return None,
+        1 => gj::Value::from(&line_strings.remove(0)),
+        _ => gj::Value::from(&gt::MultiLineString(line_strings)),
+    };
+    Some(osm_feature("relation", relation.id, &relation.tags, value))
+}
+
+fn multipolygon_feature(
+    osm_data: &osm::OSM,
+    relation: &osm::Relation,
+    skippable_way_ids: &mut HashSet<osm::Id>,
+) -> Option<gj::Feature> {
+    let mut outer_way_ids = Vec::new();
+    for member in relation.members.iter() {
+        if let osm::Member::Way(reference, role) = member {
+            let way = match osm_data.resolve_reference(reference) {
+                osm::Reference::Way(way) => way,
+                _ => {
+                    if role == "outer" {
+                        outer_way_ids.push(None);
+                    }
+                    continue;
+                }
+            };
+            // Member ways without tags of their own are drawn by the multipolygon instead.
+            if role == "outer" {
+                outer_way_ids.push(Some(way.id));
+                if !has_interesting_tags(&way.tags, |tag| relation.tags.contains(tag)) {
+                    skippable_way_ids.insert(way.id);
+                }
+            } else if role == "inner" && !has_interesting_tags(&way.tags, |_| false) {
+                skippable_way_ids.insert(way.id);
+            }
+        }
+    }
+    if outer_way_ids.is_empty() {
+        return None;
+    }
+    // Old-style multipolygons keep their tags on the single outer way.
+    let simple = outer_way_ids.len() == 1 && !has_interesting_tags(&relation.tags, |tag| tag.key == "type");
+    let (osm_type, id, tags) = if simple {
+        let way = osm_data.ways.get(&outer_way_ids[0]?)?;
+        skippable_way_ids.insert(way.id);
+        ("way", way.id, &way.tags)
+    } else {
+        ("relation", relation.id, &relation.tags)
+    };
+    let mut multi_polygon = assemble_multipolygon(
+        resolve_way_members(osm_data, relation, Some("outer")),
+        resolve_way_members(osm_data, relation, Some("inner")),
+    );
+    let value = match multi_polygon.0.len() {
+        0 => return None,
+        1 => gj::Value::from(&multi_polygon.0.remove(0)),
+        _ => gj::Value::from(&multi_polygon),
+    };
+    Some(osm_feature(osm_type, id, tags, value))
+}
+
+// RFC 7946 winding order: exterior rings are counterclockwise, holes are clockwise.
+fn rewind_ring(ring: &mut gj::LineStringType, exterior: bool) {
+    let clockwise = ring
+        .windows(2)
+        .map(|pair| (pair[1][0] - pair[0][0]) * (pair[1][1] + pair[0][1]))
+        .sum::<f64>() > 0.0;
+    if clockwise == exterior {
+        ring.reverse();
+    }
+}
+
+fn osm_feature(osm_type: &str, id: osm::Id, tags: &[osm::Tag], value: gj::Value) -> gj::Feature {
+    let osm_id = format!("{}/{}", osm_type, id);
+    let mut properties = Map::new();
+    for tag in tags.iter() {
+        properties.insert(tag.key.clone(), JsonValue::String(tag.val.clone()));
+    }
+    properties.insert("id".to_string(), JsonValue::String(osm_id.clone()));
+    gj::Feature {
+        bbox: None,
+        geometry: Some(gj::Geometry::new(value)),
+        id: Some(gj::feature::Id::String(osm_id)),
+        properties: Some(properties),
+        foreign_members: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OSM_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <node id="1" lat="45.0" lon="-75.0"/>
+  <node id="2" lat="45.0" lon="-74.9"/>
+  <node id="3" lat="45.1" lon="-74.9"/>
+  <node id="4" lat="45.1" lon="-75.0"><tag k="created_by" v="JOSM"/></node>
+  <node id="5" lat="45.02" lon="-74.98"/>
+  <node id="6" lat="45.02" lon="-74.92"/>
+  <node id="7" lat="45.08" lon="-74.92"/>
+  <node id="8" lat="45.08" lon="-74.98"/>
+  <node id="9" lat="45.2" lon="-75.0"><tag k="amenity" v="bench"/></node>
+  <node id="10" lat="45.3" lon="-75.0"/>
+  <node id="11" lat="45.3" lon="-74.9"/>
+  <way id="100">
+    <nd ref="1"/><nd ref="2"/><nd ref="3"/>
+  </way>
+  <way id="101">
+    <nd ref="3"/><nd ref="4"/><nd ref="1"/>
+  </way>
+  <way id="102">
+    <nd ref="5"/><nd ref="6"/><nd ref="7"/><nd ref="8"/><nd ref="5"/>
+  </way>
+  <way id="103">
+    <nd ref="10"/><nd ref="9"/><nd ref="11"/>
+    <tag k="highway" v="residential"/>
+  </way>
+  <way id="104">
+    <nd ref="10"/><nd ref="9"/><nd ref="11"/><nd ref="10"/>
+    <tag k="highway" v="residential"/>
+  </way>
+  <relation id="1000">
+    <member type="way" ref="100" role="outer"/>
+    <member type="way" ref="101" role="outer"/>
+    <member type="way" ref="102" role="inner"/>
+    <tag k="type" v="multipolygon"/>
+    <tag k="landuse" v="forest"/>
+  </relation>
+  <relation id="1001">
+    <member type="way" ref="103" role=""/>
+    <tag k="type" v="route"/>
+    <tag k="route" v="bus"/>
+  </relation>
+</osm>"#;
+
+    fn feature_ids(geojson: &gj::GeoJson) -> Vec<String> {
+        match geojson {
+            gj::GeoJson::FeatureCollection(collection) => collection.features
+                .iter()
+                .map(|feature| feature.properties.as_ref().unwrap()["id"].as_str().unwrap().to_string())
+                .collect(),
+            _ => panic!("expected a feature collection"),
+        }
+    }
+
+    #[test]
+    fn test_osm_to_geojson() {
+        let osm_data = osm::OSM::parse(OSM_XML.as_bytes()).unwrap();
+        let geojson = osm_to_geojson(&osm_data);
+        // Multipolygon member ways are skipped and way-only nodes without interesting tags are dropped.
+        assert_eq!(feature_ids(&geojson), vec!["relation/1000", "relation/1001", "way/103", "way/104", "node/9"]);
+        let features = match geojson {
+            gj::GeoJson::FeatureCollection(collection) => collection.features,
+            _ => unreachable!(),
         };
-        match write!(file, "{}", String::from_utf8_lossy(&output.stdout)) {
-            Result::Ok(_) => return Ok(()),
-            Result::Err(err) => return Err(Box::new(err)),
+        match &features[0].geometry.as_ref().unwrap().value {
+            gj::Value::Polygon(rings) => {
+                assert_eq!(rings.len(), 2);
+                assert_eq!(rings[0].len(), 5);
+                assert_eq!(rings[0].first(), rings[0].last());
+            }
+            value => panic!("expected the multipolygon to be a polygon with a hole, got {:?}", value),
+        }
+        assert!(matches!(features[1].geometry.as_ref().unwrap().value, gj::Value::LineString(_)));
+        // Closed highways are not areas.
+        assert!(matches!(features[3].geometry.as_ref().unwrap().value, gj::Value::LineString(_)));
+        assert_eq!(features[4].geometry.as_ref().unwrap().value, gj::Value::Point(vec![-75.0, 45.2]));
+    }
+
+    // resources/ottawa.xml.geojson was written by the Node osmtogeojson from the OSM XML export resources/ottawa.xml,
+    // converting the same export must give the same features, minus the meta attributes osm_xml doesn't expose.
+    #[test]
+    #[ignore = "needs the resources/ottawa.xml export, which isn't part of the repository"]
+    fn test_ottawa_parity() {
+        const META_KEYS: [&str; 5] = ["timestamp", "version", "changeset", "user", "uid"];
+        fn properties(feature: &gj::Feature) -> Map<String, JsonValue> {
+            let mut properties = feature.properties.clone().unwrap();
+            for key in META_KEYS.iter() {
+                properties.remove(*key);
+            }
+            properties
+        }
+        let features = |geojson: gj::GeoJson| match geojson {
+            gj::GeoJson::FeatureCollection(collection) => collection.features,
+            _ => panic!("expected a feature collection"),
         };
+        let expected = features(crate::geojson_parser::parse_geojson_file("resources/ottawa.xml.geojson").unwrap());
+        let actual = features(osm_to_geojson(&crate::osm_parser::parse_osm_file("resources/ottawa.xml".to_string()).unwrap()));
+        let ids = |features: &[gj::Feature]| features.iter().map(|feature| feature.properties.as_ref().unwrap()["id"].clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&actual), ids(&expected));
+        for (actual, expected) in actual.iter().zip(expected.iter()) {
+            assert_eq!(actual.geometry, expected.geometry, "{}", expected.properties.as_ref().unwrap()["id"]);
+            assert_eq!(properties(actual), properties(expected), "{}", expected.properties.as_ref().unwrap()["id"]);
+        }
+    }
+
+    #[test]
+    fn test_is_polygon_feature() {
+        let tag = |key: &str, val: &str| osm::Tag { key: key.to_string(), val: val.to_string() };
+        assert!(is_polygon_feature(&[tag("building", "yes")]));
+        assert!(!is_polygon_feature(&[tag("building", "yes"), tag("area", "no")]));
+        assert!(!is_polygon_feature(&[tag("highway", "residential")]));
+        assert!(is_polygon_feature(&[tag("highway", "rest_area")]));
+        assert!(!is_polygon_feature(&[tag("natural", "coastline")]));
+        assert!(!is_polygon_feature(&[tag("building", "no")]));
     }
-    Err(Box::new(ConvertError { message: "Failed to convert osm file to geojson file - ensure you have the osmtogeojson tool installed (npm install -g osmtogeojson)".to_string() }))
 }
@@ -1,19 +1,29 @@
 use std::{
-    collections::BTreeMap,
+    collections::{ BTreeMap, HashMap, HashSet },
     fs::File,
 };
 
 use geo::prelude::ConvexHull;
-use geo_types::MultiPoint;
+use geo_types::{ self as gt, MultiPoint };
 use osmpbfreader::{
-    objects::{ OsmId, OsmObj, Relation, RelationId, Way },
+    objects::{ NodeId, OsmId, OsmObj, Relation, RelationId, Tags, Way, WayId },
     OsmPbfReader,
 };
 
+use crate::{
+    features::WayNodes,
+    filter::{ self, Filter, UNINTERESTING_TAGS },
+    operations::relation_operations::{ assemble_multipolygon, WayNode },
+};
+
 pub trait HasCoordinates {
     fn get_coordinates(&self, objs: &BTreeMap<OsmId, OsmObj>) -> Vec<(f64, f64)>;
 }
 
+pub trait HasMultiPolygon {
+    fn get_multi_polygon(&self, objs: &BTreeMap<OsmId, OsmObj>) -> gt::MultiPolygon<f64>;
+}
+
 pub trait HasCycleCoordinates {
     fn get_coordinates(
         &self,
@@ -70,8 +80,242 @@ impl HasCycleCoordinates for Relation {
     }
 }
 
-pub fn parse_pbf_file(filename: String) -> Result<BTreeMap<OsmId, OsmObj>, osmpbfreader::Error> {
-    let file = File::open(filename.as_str()).unwrap();
+impl HasMultiPolygon for Relation {
+    fn get_multi_polygon(&self, objs: &BTreeMap<OsmId, OsmObj>) -> gt::MultiPolygon<f64> {
+        let member_ways = |role: &str| -> Vec<Vec<WayNode>> {
+            self.refs
+                .iter()
+                .filter(|osm_ref| osm_ref.role == role)
+                .filter_map(|osm_ref| objs.get(&osm_ref.member)?.way())
+                .map(|way| {
+                    way.nodes
+                        .iter()
+                        .filter_map(|&id| {
+                            let node = objs.get(&id.into())?.node()?;
+                            Some((id.0, gt::Coordinate { x: node.lon(), y: node.lat() }))
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+        assemble_multipolygon(member_ways("outer"), member_ways("inner"))
+    }
+}
+
+// Same format as the ids produced by osmtogeojson (node/123, way/456, relation/789).
+pub fn osm_id_to_string(id: OsmId) -> String {
+    match id {
+        OsmId::Node(id) => format!("node/{}", id.0),
+        OsmId::Way(id) => format!("way/{}", id.0),
+        OsmId::Relation(id) => format!("relation/{}", id.0),
+    }
+}
+
+// The nodes of every way (by osm_id), the ones missing from the data are left out as when drawing the way.
+pub fn way_nodes(objs: &BTreeMap<OsmId, OsmObj>) -> WayNodes {
+    objs.values()
+        .filter_map(|obj| obj.way())
+        .map(|way| {
+            let nodes = way.nodes
+                .iter()
+                .filter_map(|&id| {
+                    let node = objs.get(&id.into())?.node()?;
+                    Some((id.0, gt::Coordinate { x: node.lon(), y: node.lat() }))
+                })
+                .collect();
+            (format!("way/{}", way.id.0), nodes)
+        })
+        .collect()
+}
+
+pub fn is_multipolygon(relation: &Relation) -> bool {
+    matches!(relation.tags.get("type").map(String::as_str), Some("multipolygon") | Some("boundary"))
+}
+
+fn has_interesting_tags<F: Fn(&str, &str) -> bool>(tags: &Tags, ignored: F) -> bool {
+    tags.iter().any(|(key, val)| !UNINTERESTING_TAGS.contains(&key.as_str()) && !ignored(key, val))
+}
+
+// Old-style multipolygons are untagged relations whose single outer way holds the tags.
+pub fn get_simple_multipolygon_outer_way<'a>(relation: &Relation, objs: &'a BTreeMap<OsmId, OsmObj>) -> Option<&'a Way> {
+    objs.get(&get_simple_multipolygon_outer_way_id(relation)?.into())?.way()
+}
+
+fn get_simple_multipolygon_outer_way_id(relation: &Relation) -> Option<WayId> {
+    let mut outers = relation.refs.iter().filter(|osm_ref| osm_ref.role == "outer");
+    let outer = outers.next()?;
+    if outers.next().is_some() || has_interesting_tags(&relation.tags, |key, _| key == "type") {
+        return None;
+    }
+    outer.member.way()
+}
+
+// Multipolygon member ways without tags of their own are drawn by their relation instead.
+pub fn get_multipolygon_member_way_ids(objs: &BTreeMap<OsmId, OsmObj>) -> HashSet<WayId> {
+    let mut way_ids = HashSet::new();
+    let relations = objs.values().filter_map(|obj| obj.relation()).filter(|relation| is_multipolygon(relation));
+    for relation in relations {
+        if let Some(way) = get_simple_multipolygon_outer_way(relation, objs) {
+            way_ids.insert(way.id);
+        }
+        for osm_ref in relation.refs.iter() {
+            let way = match objs.get(&osm_ref.member).and_then(|obj| obj.way()) {
+                Some(way) => way,
+                None => continue,
+            };
+            let skippable = match osm_ref.role.as_str() {
+                "outer" => !has_interesting_tags(&way.tags, |key, val| relation.tags.get(key).is_some_and(|v| v == val)),
+                "inner" => !has_interesting_tags(&way.tags, |_, _| false),
+                _ => false,
+            };
+            if skippable {
+                way_ids.insert(way.id);
+            }
+        }
+    }
+    way_ids
+}
+
+// Objects matching the filter, and the old-style multipolygons whose outer way does (see
+// get_wanted_simple_multipolygon_ids).
+fn is_wanted(obj: &OsmObj, filter: Option<&Filter>, simple_multipolygon_ids: &HashSet<RelationId>) -> bool {
+    filter::matches(filter, obj.tags()) || obj.relation().is_some_and(|relation| simple_multipolygon_ids.contains(&relation.id))
+}
+
+// Old-style multipolygons have no tags to match the filter against, they are wanted when their outer way
+// matches it. Takes a pass over the relations and one over the ways (none without a filter, everything is wanted).
+fn get_wanted_simple_multipolygon_ids(pbf: &mut OsmPbfReader<File>, filter: Option<&Filter>) -> Result<HashSet<RelationId>, osmpbfreader::Error> {
+    let mut wanted_ids = HashSet::new();
+    if filter.is_none() {
+        return Ok(wanted_ids);
+    }
+    let mut outer_way_relation_ids: HashMap<WayId, Vec<RelationId>> = HashMap::new();
+    for obj in pbf.iter() {
+        if let OsmObj::Relation(relation) = obj? {
+            if let Some(way_id) = get_simple_multipolygon_outer_way_id(&relation).filter(|_| is_multipolygon(&relation)) {
+                outer_way_relation_ids.entry(way_id).or_default().push(relation.id);
+            }
+        }
+    }
+    pbf.rewind()?;
+    if !outer_way_relation_ids.is_empty() {
+        for obj in pbf.iter() {
+            if let OsmObj::Way(way) = obj? {
+                if let Some(relation_ids) = outer_way_relation_ids.get(&way.id).filter(|_| filter::matches(filter, &way.tags)) {
+                    wanted_ids.extend(relation_ids);
+                }
+            }
+        }
+        pbf.rewind()?;
+    }
+    Ok(wanted_ids)
+}
+
+// Only the objects matching the filter (and their dependencies) are parsed.
+pub fn parse_pbf_file(filename: String, filter: Option<&Filter>) -> Result<BTreeMap<OsmId, OsmObj>, osmpbfreader::Error> {
+    let file = File::open(filename.as_str())?;
+    let mut pbf = OsmPbfReader::new(file);
+    let simple_multipolygon_ids = get_wanted_simple_multipolygon_ids(&mut pbf, filter)?;
+    pbf.get_objs_and_deps(|obj| is_wanted(obj, filter, &simple_multipolygon_ids))
+}
+
+// Streams the file in passes so that only the objects touching the bounding box are kept in memory
+// (after finding the wanted old-style multipolygons when filtering, see get_wanted_simple_multipolygon_ids):
+// 1. Nodes inside the bounding box (unwanted ones are dropped after step 3 unless referenced).
+// 2. Ways referencing one of those nodes (and matching the filter).
+// 3. Relations referencing one of the kept nodes or ways (and matching the filter).
+// 4. Member ways of the multipolygons (and boundaries) among those relations that were not kept yet, needed
+//    to close their rings. Other relations (eg. routes) only keep their members touching the bounding box.
+// 5. Nodes outside the bounding box that are referenced by the kept ways.
+pub fn parse_pbf_file_by_bbox(
+    filename: String,
+    left: f64,
+    bottom: f64,
+    right: f64,
+    top: f64,
+    filter: Option<&Filter>,
+) -> Result<BTreeMap<OsmId, OsmObj>, osmpbfreader::Error> {
+    let file = File::open(filename.as_str())?;
     let mut pbf = OsmPbfReader::new(file);
-    pbf.get_objs_and_deps(|_| true)
+    let simple_multipolygon_ids = get_wanted_simple_multipolygon_ids(&mut pbf, filter)?;
+    let mut objs: BTreeMap<OsmId, OsmObj> = BTreeMap::new();
+    for obj in pbf.iter() {
+        if let OsmObj::Node(node) = obj? {
+            let (lat, lon) = (node.lat(), node.lon());
+            if lon >= left && lon <= right && lat >= bottom && lat <= top {
+                objs.insert(node.id.into(), OsmObj::Node(node));
+            }
+        }
+    }
+    pbf.rewind()?;
+    for obj in pbf.iter() {
+        let obj = obj?;
+        if !is_wanted(&obj, filter, &simple_multipolygon_ids) {
+            continue;
+        }
+        if let OsmObj::Way(way) = obj {
+            if way.nodes.iter().any(|&id| objs.contains_key(&id.into())) {
+                objs.insert(way.id.into(), OsmObj::Way(way));
+            }
+        }
+    }
+    pbf.rewind()?;
+    let mut missing_way_ids: HashSet<WayId> = HashSet::new();
+    for obj in pbf.iter() {
+        let obj = obj?;
+        if !is_wanted(&obj, filter, &simple_multipolygon_ids) {
+            continue;
+        }
+        if let OsmObj::Relation(relation) = obj {
+            if relation.refs.iter().any(|osm_ref| objs.contains_key(&osm_ref.member)) {
+                if is_multipolygon(&relation) {
+                    for osm_ref in relation.refs.iter() {
+                        if let OsmId::Way(id) = osm_ref.member {
+                            if !objs.contains_key(&osm_ref.member) {
+                                missing_way_ids.insert(id);
+                            }
+                        }
+                    }
+                }
+                objs.insert(relation.id.into(), OsmObj::Relation(relation));
+            }
+        }
+    }
+    if filter.is_some() {
+        // Nodes were all kept to find the ways above, drop the unwanted ones nothing references.
+        let mut referenced: HashSet<OsmId> = HashSet::new();
+        for obj in objs.values() {
+            match obj {
+                OsmObj::Way(way) => referenced.extend(way.nodes.iter().map(|&id| OsmId::from(id))),
+                OsmObj::Relation(relation) => referenced.extend(relation.refs.iter().map(|osm_ref| osm_ref.member)),
+                OsmObj::Node(_) => {}
+            }
+        }
+        objs.retain(|id, obj| !obj.is_node() || referenced.contains(id) || is_wanted(obj, filter, &simple_multipolygon_ids));
+    }
+    if !missing_way_ids.is_empty() {
+        pbf.rewind()?;
+        for obj in pbf.iter() {
+            if let OsmObj::Way(way) = obj? {
+                if missing_way_ids.contains(&way.id) {
+                    objs.insert(way.id.into(), OsmObj::Way(way));
+                }
+            }
+        }
+    }
+    let mut missing_node_ids: HashSet<NodeId> = HashSet::new();
+    for way in objs.values().filter_map(|obj| obj.way()) {
+        missing_node_ids.extend(way.nodes.iter().filter(|&&id| !objs.contains_key(&id.into())));
+    }
+    if !missing_node_ids.is_empty() {
+        pbf.rewind()?;
+        for obj in pbf.iter() {
+            if let OsmObj::Node(node) = obj? {
+                if missing_node_ids.contains(&node.id) {
+                    objs.insert(node.id.into(), OsmObj::Node(node));
+                }
+            }
+        }
+    }
+    Ok(objs)
 }
\ No newline at end of file
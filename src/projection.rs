@@ -0,0 +1,160 @@
+use std::f64::consts::FRAC_PI_4;
+use geo_types as gt;
+use serde::{Deserialize, Serialize};
+
+use crate::{ error::Error, features::TILE_SCALE };
+
+// Web Mercator can't represent the poles, latitudes are clamped to the usual square world.
+const WEB_MERCATOR_MAX_LATITUDE: f64 = 85.051_128_78;
+
+// How lon/lat locations are laid out on the grid. Projected coordinates are expressed in degrees of arc
+// along the projection's standard line (the equator, or the central meridian), so one grid cell stays
+// 1/scale degree long there (roughly a meter at the default scale) whatever the projection.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Projection {
+    // Raw lat/lon cells, which get narrower (in meters) than they are tall away from the equator.
+    #[default]
+    Degrees,
+    // Spherical Web Mercator (EPSG:3857), cells are square but shrink (in meters) away from the equator.
+    WebMercator,
+    // Spherical transverse Mercator centered on a meridian, cells are square and keep their size (in meters)
+    // close to it. Latitudes are unchanged along the central meridian.
+    TransverseMercator { longitude: f64 },
+}
+
+impl Projection {
+    // Returns the projected (x, y) of a location.
+    pub fn project(&self, longitude: f64, latitude: f64) -> (f64, f64) {
+        match *self {
+            Projection::Degrees => (longitude, latitude),
+            Projection::WebMercator => {
+                let latitude = latitude.clamp(-WEB_MERCATOR_MAX_LATITUDE, WEB_MERCATOR_MAX_LATITUDE).to_radians();
+                (longitude, (FRAC_PI_4 + latitude / 2.0).tan().ln().to_degrees())
+            }
+            Projection::TransverseMercator { longitude: central_longitude } => {
+                let delta_longitude = (longitude - central_longitude).to_radians();
+                let latitude = latitude.to_radians();
+                let b = latitude.cos() * delta_longitude.sin();
+                let x = b.atanh().to_degrees() + central_longitude;
+                let y = latitude.tan().atan2(delta_longitude.cos()).to_degrees();
+                (x, y)
+            }
+        }
+    }
+
+    // Returns the (longitude, latitude) of projected coordinates.
+    pub fn unproject(&self, x: f64, y: f64) -> (f64, f64) {
+        match *self {
+            Projection::Degrees => (x, y),
+            Projection::WebMercator => (x, (2.0 * y.to_radians().exp().atan() - 2.0 * FRAC_PI_4).to_degrees()),
+            Projection::TransverseMercator { longitude: central_longitude } => {
+                let x = (x - central_longitude).to_radians();
+                let y = y.to_radians();
+                let latitude = (y.sin() / x.cosh()).asin().to_degrees();
+                let longitude = x.sinh().atan2(y.cos()).to_degrees() + central_longitude;
+                (longitude, latitude)
+            }
+        }
+    }
+}
+
+// The grid GeoTiles are drawn on: scale cells per (projected) degree.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TileGrid {
+    pub scale: f64,
+    pub projection: Projection,
+}
+
+impl Default for TileGrid {
+    fn default() -> TileGrid {
+        TileGrid { scale: TILE_SCALE, projection: Projection::Degrees }
+    }
+}
+
+impl TileGrid {
+    pub fn new(scale: f64, projection: Projection) -> TileGrid {
+        TileGrid { scale, projection }
+    }
+
+    // Features can only be drawn on grids with a positive scale, small enough for every cell (up to 180 projected
+    // degrees away from the origin) to fit in an i32.
+    pub fn validate(&self) -> Result<(), Error> {
+        if !(self.scale.is_finite() && self.scale > 0.0 && 180.0 * self.scale <= i32::MAX as f64) {
+            return Err(Error::InvalidTileScale(self.scale));
+        }
+        Ok(())
+    }
+
+    // Lines are drawn thickness cells wide at the default scale (cells being roughly a meter), as wide on this grid
+    // while staying at least a cell wide on coarser ones.
+    pub fn line_thickness(&self, thickness: u8) -> u8 {
+        if thickness == 0 {
+            return 0;
+        }
+        (thickness as f64 * self.scale / TILE_SCALE).round().clamp(1.0, u8::MAX as f64) as u8
+    }
+
+    // Continuous grid coordinates of a location, its cell being at the rounded values.
+    pub fn to_grid(&self, longitude: f64, latitude: f64) -> (f64, f64) {
+        let (x, y) = self.projection.project(longitude, latitude);
+        (x * self.scale, y * self.scale)
+    }
+
+    // Returns the (longitude, latitude) of continuous grid coordinates.
+    pub fn from_grid(&self, x: f64, y: f64) -> (f64, f64) {
+        self.projection.unproject(x / self.scale, y / self.scale)
+    }
+
+    // The cell of a location. This is a lossy operation.
+    pub fn to_tile(&self, longitude: f64, latitude: f64) -> gt::Coordinate<i32> {
+        let (x, y) = self.to_grid(longitude, latitude);
+        gt::Coordinate { x: x.round() as i32, y: y.round() as i32 }
+    }
+
+    // Returns the (longitude, latitude) of the center of a cell.
+    pub fn from_tile(&self, coordinate: gt::Coordinate<i32>) -> (f64, f64) {
+        self.from_grid(coordinate.x as f64, coordinate.y as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_projections() {
+        let projections = [Projection::Degrees, Projection::WebMercator, Projection::TransverseMercator { longitude: -75.0 }];
+        for projection in projections.iter() {
+            let (x, y) = projection.project(-75.69, 45.42);
+            let (longitude, latitude) = projection.unproject(x, y);
+            assert!((longitude + 75.69).abs() < 1e-9 && (latitude - 45.42).abs() < 1e-9, "{:?}", projection);
+        }
+        // Around Ottawa, a degree of longitude is only ~0.7 times as long as a degree of latitude.
+        let cell_ratio = |projection: Projection| {
+            let grid = TileGrid::new(TILE_SCALE, projection);
+            let (x, y) = grid.to_grid(-75.69, 45.42);
+            let (east_x, _) = grid.to_grid(-75.68, 45.42);
+            let (_, north_y) = grid.to_grid(-75.69, 45.43);
+            (east_x - x) / (north_y - y)
+        };
+        assert!((cell_ratio(Projection::Degrees) - 1.0).abs() < 1e-6);
+        assert!((cell_ratio(Projection::WebMercator) - 45.42f64.to_radians().cos()).abs() < 1e-3);
+        assert!((cell_ratio(Projection::TransverseMercator { longitude: -75.7 }) - 45.42f64.to_radians().cos()).abs() < 1e-3);
+        // Unchanged along the central meridian.
+        let grid = TileGrid::new(TILE_SCALE, Projection::TransverseMercator { longitude: -75.0 });
+        assert_eq!(grid.to_tile(-75.0, 45.0), gt::Coordinate { x: -7_500_000, y: 4_500_000 });
+        assert_eq!(TileGrid::default().to_tile(-75.69006, 45.42114), gt::Coordinate { x: -7_569_006, y: 4_542_114 });
+    }
+
+    #[test]
+    fn test_line_thickness() {
+        assert_eq!(TileGrid::default().line_thickness(7), 7);
+        assert_eq!(TileGrid::new(TILE_SCALE * 2.0, Projection::Degrees).line_thickness(7), 14);
+        assert_eq!(TileGrid::new(TILE_SCALE / 10.0, Projection::Degrees).line_thickness(7), 1);
+        assert_eq!(TileGrid::new(TILE_SCALE * 100.0, Projection::Degrees).line_thickness(7), 255);
+        assert!(TileGrid::new(0.0, Projection::Degrees).validate().is_err());
+        assert!(TileGrid::new(f64::NAN, Projection::Degrees).validate().is_err());
+        assert!(TileGrid::new(1e7, Projection::Degrees).validate().is_ok());
+        assert!(TileGrid::new(1e8, Projection::Degrees).validate().is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use std::{thread, time::Duration};
+use log::warn;
+
+use crate::error::Error;
+
+// How many times (and how patiently) the Overpass and Nominatim clients retry transient errors.
+// The delay doubles after every attempt (up to max_backoff) unless the service tells us how long to wait,
+// requests the service wants delayed by more than max_backoff fail with Error::RateLimited right away.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> RetryPolicy {
+        RetryPolicy { max_retries: 0, ..RetryPolicy::default() }
+    }
+
+    pub fn retry<T, F: FnMut() -> Result<T, Error>>(&self, mut request: F) -> Result<T, Error> {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            match request() {
+                Err(error) if error.is_transient() && attempt < self.max_retries => {
+                    let delay = match error {
+                        Error::RateLimited { retry_after: Some(retry_after) } if retry_after > self.max_backoff => return Err(error),
+                        Error::RateLimited { retry_after: Some(retry_after) } => retry_after,
+                        _ => backoff,
+                    };
+                    warn!("{}, retrying in {} seconds", error, delay.as_secs_f32());
+                    thread::sleep(delay);
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
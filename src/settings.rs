@@ -0,0 +1,92 @@
+use std::{io::Read, time::Duration};
+use reqwest::blocking::{Client, Response};
+
+use crate::{cache::CacheSettings, error::Error, retry::RetryPolicy};
+
+pub const OVERPASS_URL: &str = "https://overpass-api.de/api";
+pub const NOMINATIM_URL: &str = "https://nominatim.openstreetmap.org";
+
+// Where and how the Overpass and Nominatim services are reached.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    // Base url of the Overpass API, the map and interpreter endpoints are appended to it.
+    pub overpass_url: String,
+    // Base url of the Nominatim instance.
+    pub nominatim_url: String,
+    pub user_agent: String,
+    pub timeout: Option<Duration>,
+    // Proxy used for all requests (eg. http://localhost:3128).
+    pub proxy: Option<String>,
+    // Responses larger than this (in bytes) are rejected.
+    pub max_response_size: Option<u64>,
+    pub retry_policy: RetryPolicy,
+    // Where downloaded areas are kept, None (the default) disables caching.
+    pub cache: Option<CacheSettings>,
+    // Only serve areas from the cache, never reach the Overpass or Nominatim services.
+    pub offline: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            overpass_url: OVERPASS_URL.to_string(),
+            nominatim_url: NOMINATIM_URL.to_string(),
+            user_agent: "osm-geo-mapper".to_string(),
+            timeout: None,
+            proxy: None,
+            max_response_size: None,
+            retry_policy: RetryPolicy::default(),
+            cache: None,
+            offline: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn overpass_map_url(&self) -> String {
+        format!("{}/map", self.overpass_url.trim_end_matches('/'))
+    }
+
+    pub fn overpass_interpreter_url(&self) -> String {
+        format!("{}/interpreter", self.overpass_url.trim_end_matches('/'))
+    }
+
+    pub fn nominatim_search_url(&self) -> String {
+        format!("{}/search", self.nominatim_url.trim_end_matches('/'))
+    }
+
+    pub fn nominatim_reverse_url(&self) -> String {
+        format!("{}/reverse", self.nominatim_url.trim_end_matches('/'))
+    }
+
+    pub fn http_client(&self) -> Result<Client, Error> {
+        if self.offline {
+            return Err(Error::Offline("network access is disabled".to_string()));
+        }
+        let mut builder = Client::builder().user_agent(self.user_agent.as_str());
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy.as_str())?);
+        }
+        Ok(builder.build()?)
+    }
+
+    // Reads the response body while enforcing max_response_size.
+    pub fn read_response(&self, response: Response) -> Result<String, Error> {
+        let max_response_size = match self.max_response_size {
+            Some(max_response_size) => max_response_size,
+            None => return Ok(response.text()?),
+        };
+        if response.content_length().is_some_and(|length| length > max_response_size) {
+            return Err(Error::ResponseTooLarge(max_response_size));
+        }
+        let mut body = String::new();
+        response.take(max_response_size + 1).read_to_string(&mut body)?;
+        if body.len() as u64 > max_response_size {
+            return Err(Error::ResponseTooLarge(max_response_size));
+        }
+        Ok(body)
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    convert::TryInto,
+    fs::File,
+    io::{ self, BufReader, BufWriter, Read, Write },
+    path::Path,
+    sync::Arc,
+};
+use bincode::Options;
+use geo_types as gt;
+use serde::{ Deserialize, Serialize };
+
+use crate::{ error::{ Error, ParseError }, features::GeoTile, projection::TileGrid };
+
+// Snapshot files start with these bytes and the format version (little endian u32), followed by
+// the zstd compressed (and checksummed) bincode encoding of a Snapshot.
+const MAGIC: &[u8; 8] = b"OSMGEOSN";
+// Bumped whenever the layout of Snapshot or GeoTile changes, other versions are rejected.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// The state of a populated OSMGeoMapper, see OSMGeoMapper::save_snapshot and OSMGeoMapper::load_snapshot.
+// Features are stored once (not once per cell) and drawn again on the grid when loaded.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub grid: TileGrid,
+    pub coordinates: gt::Coordinate<i32>,
+    pub radius: u32,
+    pub features: Vec<Arc<GeoTile>>,
+}
+
+// Decoding never reads more than limit bytes, so a corrupted length can't cause a huge allocation.
+fn bincode_options(limit: u64) -> impl Options {
+    bincode::DefaultOptions::new().with_limit(limit).reject_trailing_bytes()
+}
+
+pub fn write_snapshot<P: AsRef<Path>>(path: P, snapshot: &Snapshot) -> Result<(), Error> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+    let mut encoder = zstd::stream::write::Encoder::new(writer, 0)?;
+    encoder.include_checksum(true)?;
+    bincode_options(u64::MAX)
+        .serialize_into(&mut encoder, snapshot)
+        .map_err(io::Error::other)?;
+    encoder.finish()?.flush()?;
+    Ok(())
+}
+
+// Fails with a ParseError unless the whole file is a valid snapshot of the current version, and with
+// Error::InvalidTileScale when its grid can't be used (see TileGrid::validate).
+pub fn read_snapshot<P: AsRef<Path>>(path: P) -> Result<Snapshot, Error> {
+    let file_name = path.as_ref().to_string_lossy().to_string();
+    let invalid = |message: String| Error::Parse(ParseError::new(message).in_file(&file_name));
+    let mut reader = BufReader::new(File::open(&path)?);
+    let mut header = [0; 12];
+    if reader.read_exact(&mut header).is_err() || &header[..8] != MAGIC {
+        return Err(invalid("Not a snapshot file".to_string()));
+    }
+    let version = u32::from_le_bytes(header[8..].try_into().unwrap());
+    if version != SNAPSHOT_VERSION {
+        return Err(invalid(format!("Unsupported snapshot version {} (expected {})", version, SNAPSHOT_VERSION)));
+    }
+    let encoded = zstd::stream::decode_all(reader).map_err(|error| invalid(format!("Corrupted snapshot: {}", error)))?;
+    let snapshot: Snapshot = bincode_options(encoded.len() as u64)
+        .deserialize(&encoded)
+        .map_err(|error| invalid(format!("Corrupted snapshot: {}", error)))?;
+    snapshot.grid.validate()?;
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::features::{ Address, BuildingType, Geometry };
+
+    #[test]
+    fn test_snapshot() {
+        let path = std::env::temp_dir().join(format!("osm-geo-mapper-test-snapshot-{}.bin", uuid::Uuid::new_v4()));
+        let building = GeoTile::Building {
+            osm_id: "way/1".to_owned(),
+            geometry: Geometry::Point(gt::Point::new(-75.69, 45.42)),
+            building_type: BuildingType::Apartments,
+            address: Some(Address { house_number: Some("1".to_owned()), unit: None, street: Some("Wellington".to_owned()), postal_code: None }),
+            access: None,
+            amenity: None,
+            capacity: None,
+            covered: None,
+            entrance: None,
+            height: None,
+            levels: Some("3".to_owned()),
+            name: Some("Building A".to_owned()),
+            office: None,
+            operator: None,
+            power: None,
+            public_transport: None,
+            shop: None,
+            sport: None,
+        };
+        let snapshot = Snapshot { grid: TileGrid::default(), coordinates: gt::Coordinate { x: -7_569_000, y: 4_542_000 }, radius: 200, features: vec![Arc::new(building)] };
+        write_snapshot(&path, &snapshot).unwrap();
+        let read = read_snapshot(&path).unwrap();
+        assert_eq!(read.grid, snapshot.grid);
+        assert_eq!((read.coordinates, read.radius), (snapshot.coordinates, 200));
+        assert_eq!(format!("{:?}", read.features), format!("{:?}", snapshot.features));
+        let contents = fs::read(&path).unwrap();
+        let is_invalid = |contents: &[u8]| {
+            fs::write(&path, contents).unwrap();
+            matches!(read_snapshot(&path), Err(Error::Parse(_)))
+        };
+        // Wrong magic, other version, truncated and corrupted payloads.
+        assert!(is_invalid(b"OSM"));
+        assert!(is_invalid(&[&b"NOTASNAP"[..], &contents[8..]].concat()));
+        assert!(is_invalid(&[&MAGIC[..], &2u32.to_le_bytes()[..], &contents[12..]].concat()));
+        assert!(is_invalid(&contents[..contents.len() * 2 / 3]));
+        let mut corrupted = contents.clone();
+        let middle = corrupted.len() / 2;
+        corrupted[middle] ^= 0xff;
+        assert!(is_invalid(&corrupted));
+        let unusable = Snapshot { grid: TileGrid { scale: 0.0, ..TileGrid::default() }, ..snapshot };
+        write_snapshot(&path, &unusable).unwrap();
+        assert!(matches!(read_snapshot(&path), Err(Error::InvalidTileScale(_))));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+use std::{ f64::consts::PI, sync::Arc };
+use geo::algorithm::{
+    bounding_rect::BoundingRect, euclidean_distance::EuclideanDistance, intersects::Intersects, map_coords::MapCoords,
+};
+use geo_types as gt;
+use rstar::{ RTree, RTreeObject, AABB };
+
+use crate::features::{ GeoTile, Geometry };
+
+// Mean earth radius, distances are computed on a sphere.
+const EARTH_RADIUS: f64 = 6_371_008.8;
+const METERS_PER_DEGREE: f64 = EARTH_RADIUS * PI / 180.0;
+
+// nearest starts looking this far (in meters) around the location, doubling the distance until it has enough features.
+const NEAREST_START_DISTANCE: f64 = 100.0;
+
+#[derive(Debug, Clone)]
+struct IndexedGeoTile {
+    envelope: AABB<[f64; 2]>,
+    geo_tile: Arc<GeoTile>,
+}
+
+// The same feature, see SpatialIndex::remove.
+impl PartialEq for IndexedGeoTile {
+    fn eq(&self, other: &IndexedGeoTile) -> bool {
+        Arc::ptr_eq(&self.geo_tile, &other.geo_tile)
+    }
+}
+
+impl RTreeObject for IndexedGeoTile {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+// Spatial queries over the original (vector) geometries of features, so results don't depend on the grid
+// resolution (features that don't cover any grid cell are found too). Coordinates are lon/lat degrees, distances are in meters.
+// OSMGeoMapper keeps one up to date with the loaded features, see OSMGeoMapper::features_in_bbox.
+#[derive(Debug, Clone, Default)]
+pub struct SpatialIndex {
+    tree: RTree<IndexedGeoTile>,
+}
+
+impl SpatialIndex {
+    // Each GeoTile should only be given once.
+    pub fn new(geo_tiles: &[Arc<GeoTile>]) -> SpatialIndex {
+        let mut index = SpatialIndex::default();
+        index.add(geo_tiles);
+        index
+    }
+
+    // Indexes more features (eg. freshly loaded ones), each GeoTile should only be added once.
+    pub fn add(&mut self, geo_tiles: &[Arc<GeoTile>]) {
+        let indexed_geo_tiles = geo_tiles.iter().filter_map(indexed_geo_tile);
+        if self.is_empty() {
+            // Much faster than inserting them one by one.
+            self.tree = RTree::bulk_load(indexed_geo_tiles.collect());
+        } else {
+            indexed_geo_tiles.for_each(|indexed| self.tree.insert(indexed));
+        }
+    }
+
+    // Forgets the given features (eg. removed from the mapper).
+    pub fn remove(&mut self, geo_tiles: &[Arc<GeoTile>]) {
+        for indexed in geo_tiles.iter().filter_map(indexed_geo_tile) {
+            self.tree.remove(&indexed);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.size() == 0
+    }
+
+    // Features whose geometry intersects the bounding box.
+    pub fn features_in_bbox(&self, left: f64, bottom: f64, right: f64, top: f64) -> Vec<Arc<GeoTile>> {
+        let envelope = AABB::from_corners([left, bottom], [right, top]);
+        let bbox = gt::Polygon::new(
+            vec![(left, bottom), (right, bottom), (right, top), (left, top), (left, bottom)].into(),
+            vec![],
+        );
+        self.tree
+            .locate_in_envelope_intersecting(&envelope)
+            .filter(|indexed| match indexed.geo_tile.geometry() {
+                // Already known to be in the bounding box.
+                Geometry::Point(_) => true,
+                Geometry::LineString(line_string) => line_string.intersects(&bbox),
+                Geometry::Polygon(polygon) => polygon.intersects(&bbox),
+            })
+            .map(|indexed| indexed.geo_tile.clone())
+            .collect()
+    }
+
+    // Features closer than the given distance (in meters) to the location, with their distance, closest first.
+    // Polygons containing the location are at distance 0. Nothing is found around non-finite locations.
+    pub fn features_within(&self, lat: f64, lon: f64, meters: f64) -> Vec<(Arc<GeoTile>, f64)> {
+        if !(lat.is_finite() && lon.is_finite()) {
+            return Vec::new();
+        }
+        let delta_lat = meters / METERS_PER_DEGREE;
+        let delta_lon = delta_lat / lat.to_radians().cos().max(1e-6);
+        let envelope = AABB::from_corners([lon - delta_lon, lat - delta_lat], [lon + delta_lon, lat + delta_lat]);
+        let mut features: Vec<(Arc<GeoTile>, f64)> = self.tree
+            .locate_in_envelope_intersecting(&envelope)
+            .map(|indexed| (indexed.geo_tile.clone(), distance(lat, lon, indexed.geo_tile.geometry())))
+            .filter(|(_, distance)| *distance <= meters)
+            .collect();
+        features.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        features
+    }
+
+    // The k features closest to the location (with their distance in meters) for which filter returns true, closest first.
+    pub fn nearest<F>(&self, lat: f64, lon: f64, k: usize, filter: F) -> Vec<(Arc<GeoTile>, f64)>
+    where
+        F: Fn(&GeoTile) -> bool,
+    {
+        if k == 0 || self.is_empty() || !(lat.is_finite() && lon.is_finite()) {
+            return Vec::new();
+        }
+        // Past this distance every indexed feature has been considered.
+        let envelope = self.tree.root().envelope();
+        let (lower, upper) = (envelope.lower(), envelope.upper());
+        let max_distance = [lower, upper, [lower[0], upper[1]], [upper[0], lower[1]]]
+            .iter()
+            .map(|corner| distance(lat, lon, &Geometry::Point(gt::Point::new(corner[0], corner[1]))))
+            .fold(0.0, f64::max);
+        let mut meters = NEAREST_START_DISTANCE;
+        loop {
+            let mut features = self.features_within(lat, lon, meters);
+            features.retain(|(geo_tile, _)| filter(geo_tile));
+            if features.len() >= k || meters > max_distance {
+                features.truncate(k);
+                return features;
+            }
+            meters *= 2.0;
+        }
+    }
+}
+
+fn indexed_geo_tile(geo_tile: &Arc<GeoTile>) -> Option<IndexedGeoTile> {
+    let envelope = geometry_envelope(geo_tile.geometry())?;
+    Some(IndexedGeoTile { envelope, geo_tile: geo_tile.clone() })
+}
+
+fn geometry_envelope(geometry: &Geometry) -> Option<AABB<[f64; 2]>> {
+    let rect = match geometry {
+        Geometry::Point(point) => return Some(AABB::from_point([point.x(), point.y()])),
+        Geometry::LineString(line_string) => line_string.bounding_rect()?,
+        Geometry::Polygon(polygon) => polygon.bounding_rect()?,
+    };
+    Some(AABB::from_corners([rect.min().x, rect.min().y], [rect.max().x, rect.max().y]))
+}
+
+// Distance in meters between a location and a geometry, through an equirectangular projection centered
+// on the location (accurate enough for the few kilometers around it).
+pub fn distance(lat: f64, lon: f64, geometry: &Geometry) -> f64 {
+    let cos_lat = lat.to_radians().cos();
+    let project = |&(x, y): &(f64, f64)| ((x - lon) * cos_lat * METERS_PER_DEGREE, (y - lat) * METERS_PER_DEGREE);
+    let origin = gt::Point::new(0.0, 0.0);
+    match geometry {
+        Geometry::Point(point) => origin.euclidean_distance(&point.map_coords(project)),
+        Geometry::LineString(line_string) => origin.euclidean_distance(&line_string.map_coords(project)),
+        Geometry::Polygon(polygon) => origin.euclidean_distance(&polygon.map_coords(project)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geojson as gj;
+    use std::{ collections::HashMap, sync::RwLock };
+    use crate::{ features::AmenityType, operations, projection::TileGrid };
+
+    fn index(geojson: &str) -> SpatialIndex {
+        let geojson: gj::GeoJson = geojson.parse().unwrap();
+        let data_structure = Arc::new(RwLock::new(HashMap::new()));
+        let (geo_tiles, _) = operations::process_geojson_with_data_structure(&geojson, data_structure, TileGrid::default(), false).unwrap();
+        SpatialIndex::new(&geo_tiles)
+    }
+
+    #[test]
+    fn test_spatial_queries() {
+        let index = index(r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {"id": "node/1", "amenity": "bench"}, "geometry": {"type": "Point", "coordinates": [-75.0, 45.0]}},
+            {"type": "Feature", "properties": {"id": "node/2", "amenity": "cafe"}, "geometry": {"type": "Point", "coordinates": [-75.0, 45.001]}},
+            {"type": "Feature", "properties": {"id": "way/3", "highway": "footway"}, "geometry": {"type": "LineString", "coordinates": [[-75.01, 44.9995], [-74.99, 44.9995]]}},
+            {"type": "Feature", "properties": {"id": "way/4", "building": "yes"}, "geometry": {"type": "Polygon", "coordinates": [[[-75.02, 45.0], [-75.01, 45.0], [-75.01, 45.01], [-75.02, 45.01], [-75.02, 45.0]]]}}
+        ]}"#);
+        assert_eq!(index.len(), 4);
+        let osm_ids = |geo_tiles: Vec<Arc<GeoTile>>| {
+            let mut osm_ids: Vec<String> = geo_tiles.iter().map(|geo_tile| geo_tile.osm_id().to_string()).collect();
+            osm_ids.sort();
+            osm_ids
+        };
+        // The footway crosses the bounding box without any of its points being in it.
+        assert_eq!(osm_ids(index.features_in_bbox(-75.001, 44.999, -74.999, 45.0005)), vec!["node/1", "way/3"]);
+        // ~55m to the footway, ~111m to the cafe.
+        let within = index.features_within(45.0, -75.0, 100.0);
+        assert_eq!(osm_ids(within.iter().map(|(geo_tile, _)| geo_tile.clone()).collect()), vec!["node/1", "way/3"]);
+        assert!(within[0].1 == 0.0 && (within[1].1 - 55.6).abs() < 0.1);
+        // Inside the building.
+        let within = index.features_within(45.005, -75.015, 1.0);
+        assert_eq!(within.len(), 1);
+        assert_eq!(within[0].0.osm_id(), "way/4");
+        let cafes = index.nearest(45.0, -75.0, 2, |geo_tile| {
+            matches!(geo_tile, GeoTile::Amenity { amenity_type: AmenityType::Cafe, .. })
+        });
+        assert_eq!(cafes.len(), 1);
+        assert_eq!(cafes[0].0.osm_id(), "node/2");
+        assert!((cafes[0].1 - 111.2).abs() < 0.1);
+        assert_eq!(index.nearest(45.0, -75.0, 3, |_| true).len(), 3);
+        assert!(index.features_within(f64::NAN, -75.0, 100.0).is_empty());
+        assert!(index.nearest(45.0, f64::NAN, 1, |_| true).is_empty());
+    }
+}
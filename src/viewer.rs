@@ -1,4 +1,4 @@
-use std::{error::Error, io::Stdout, fmt};
+use std::{error::Error, io::{self, Stdout, Write}, fmt};
 use tui::{
     backend::CrosstermBackend,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
@@ -8,10 +8,10 @@ use tui::{
 };
 
 use crate::{
-    geojson_parser,
     interface,
     nominatim,
-    operations,
+    features::TILE_SCALE,
+    projection::{ Projection, TileGrid },
     viewer::details::geo_tile_text_lines
 };
 
@@ -34,59 +34,112 @@ impl fmt::Display for MissingConfigurationError {
     }
 }
 
-pub fn cli_options_to_mapper(options: cli::CLIOptions) -> Result<interface::OSMGeoMapper, Box<dyn std::error::Error>> {
-    let radius: u32;
-    if let Some(r) = options.radius {
-        radius = r;
-    } else {
-        radius = 200;
+pub fn cli_options_to_mapper(mut options: cli::CLIOptions) -> Result<interface::OSMGeoMapper, Box<dyn std::error::Error>> {
+    let settings = options.settings();
+    let radius = options.radius.unwrap_or(200);
+    // Generic files are handled like the matching format specific option.
+    let mut csv_file = None;
+    let mut mbtiles_file = None;
+    if let Some(file) = options.file.take() {
+        match interface::FileFormat::from_path(&file) {
+            Some(interface::FileFormat::GeoJson) => options.geojson_file = Some(file),
+            Some(interface::FileFormat::Pbf) => options.pbf_file = Some(file),
+            Some(interface::FileFormat::Gpx) => options.gpx_file = Some(file),
+            Some(interface::FileFormat::Csv) => csv_file = Some(file),
+            Some(interface::FileFormat::MBTiles) => mbtiles_file = Some(file),
+            Some(interface::FileFormat::Osm) | Some(interface::FileFormat::O5m) | Some(interface::FileFormat::Opl) => options.osm_file = Some(file),
+            None => return Err(Box::new(MissingConfigurationError { message: format!("Unknown file format: {} (try --help)", file.display()) })),
+        }
     }
-    if options.geojson_file.is_some() {
-        let geojson_file = format!("{}", options.geojson_file.unwrap().to_str().unwrap());
-        interface::OSMGeoMapper::from_geojson_file_with_radius(
-            geojson_file,
-            radius,
-            Some(
-                interface::Location::Coordinates {
-                    latitude: options.latitude.unwrap(),
-                    longitude: options.longitude.unwrap()
-                }
-            )
-        )
-    } else if options.osm_file.is_some() {
-        let osm_file = format!("{}", options.osm_file.unwrap().to_str().unwrap());
-        interface::OSMGeoMapper::from_osm_file(osm_file, None)
-    } else if options.pbf_file.is_some() {
-        let pbf_file = format!("{}", options.pbf_file.unwrap().to_str().unwrap());
-        interface::OSMGeoMapper::from_pbf_file(pbf_file, None)
-    } else if options.latitude.is_some() && options.longitude.is_some() {
-        let lat = options.latitude.unwrap();
-        let lon = options.longitude.unwrap();
-        let geojson_file = operations::get_geojson_file_by_lat_lon(lat, lon, operations::from_tile_scale(radius as i32))?;
-        interface::OSMGeoMapper::from_geojson_file_with_radius(
-            geojson_file,
-            radius,
-            Some(
-                interface::Location::Coordinates {
-                    latitude: lat,
-                    longitude: lon
-                }
-            )
-        )
-    } else if options.address.is_some() {
-        let (latitude, longitude) = nominatim::get_address_lat_lon(options.address.unwrap())?;
-        interface::OSMGeoMapper::from_geojson_file_with_radius(
-            operations::get_geojson_file_by_lat_lon(latitude, longitude, operations::from_tile_scale(radius as i32))?,
-            radius,
-            Some(
-                interface::Location::Coordinates {
-                    latitude,
-                    longitude
-                }
-            )
-        )
+    let tile_scale = options.tile_scale.unwrap_or(TILE_SCALE);
+    TileGrid::new(tile_scale, Projection::Degrees).validate()?;
+    // The features are drawn on the requested grid while loading. Transverse Mercator is centered on the
+    // loaded area, when its longitude isn't known up front the features are drawn again once loaded.
+    let transverse_mercator = options.projection.as_deref() == Some("transverse-mercator");
+    let web_mercator = options.projection.as_deref() == Some("web-mercator");
+    let load_options = |longitude: Option<f64>| {
+        let projection = match longitude {
+            _ if web_mercator => Projection::WebMercator,
+            Some(longitude) if transverse_mercator => Projection::TransverseMercator { longitude },
+            _ => Projection::Degrees,
+        };
+        Some(interface::LoadOptions { grid: TileGrid::new(tile_scale, projection), ..Default::default() })
+    };
+    let mut longitude = options.longitude;
+    let mut mapper = if let Some(geojson_file) = options.geojson_file {
+        let geojson_file = geojson_file.to_str().unwrap().to_string();
+        let location = match (options.latitude, options.longitude) {
+            (Some(latitude), Some(longitude)) => Some(interface::Location::Coordinates { latitude, longitude }),
+            _ => None,
+        };
+        interface::OSMGeoMapper::from_geojson_file_with_radius(geojson_file, radius, location, load_options(longitude))?
+    } else if let Some(osm_file) = options.osm_file {
+        let format = interface::FileFormat::from_path(&osm_file);
+        let osm_file = osm_file.to_str().unwrap().to_string();
+        match format {
+            Some(interface::FileFormat::O5m) => interface::OSMGeoMapper::from_o5m_file(osm_file, None, options.filter, load_options(longitude))?,
+            Some(interface::FileFormat::Opl) => interface::OSMGeoMapper::from_opl_file(osm_file, None, options.filter, load_options(longitude))?,
+            _ => interface::OSMGeoMapper::from_osm_file(osm_file, None, options.filter, load_options(longitude))?,
+        }
+    } else if let Some(pbf_file) = options.pbf_file {
+        let pbf_file = pbf_file.to_str().unwrap().to_string();
+        // Only load the area around the given location when there is one (large extracts can't fit in memory).
+        let area = match (options.latitude, options.longitude) {
+            (Some(latitude), Some(longitude)) => Some(interface::Area::Radius { latitude, longitude, radius }),
+            _ => None,
+        };
+        interface::OSMGeoMapper::from_pbf_file(pbf_file, None, area, options.filter, load_options(longitude))?
+    } else if let Some(mbtiles_file) = mbtiles_file {
+        let area = match (options.latitude, options.longitude) {
+            (Some(latitude), Some(longitude)) => Some(interface::Area::Radius { latitude, longitude, radius }),
+            _ => None,
+        };
+        interface::OSMGeoMapper::from_mbtiles_file(mbtiles_file.to_str().unwrap().to_string(), options.tile_zoom, None, area, load_options(longitude))?
+    } else if let Some(csv_file) = csv_file {
+        interface::OSMGeoMapper::from_csv_file(csv_file.to_str().unwrap().to_string(), None, None, load_options(longitude))?
+    } else if let (Some(lat), Some(lon)) = (options.latitude, options.longitude) {
+        interface::OSMGeoMapper::from_lat_lon(lat, lon, options.radius, Some(settings.clone()), load_options(longitude))?
+    } else if let Some(address) = options.address {
+        let candidates = nominatim::get_address_candidates(address, 10, &settings)?;
+        let candidate = choose_geocoding_candidate(candidates)?;
+        longitude = Some(candidate.longitude);
+        interface::OSMGeoMapper::from_geocoding_candidate(&candidate, options.radius, Some(settings.clone()), load_options(longitude))?
+    } else if let Some(gpx_file) = options.gpx_file.take() {
+        interface::OSMGeoMapper::from_gpx_file(gpx_file.to_str().unwrap().to_string(), None, load_options(longitude))?
     } else {
-        return Err(Box::new(MissingConfigurationError { message: "Need to provide one of osm_file, pbf_file, geojson_file, latitude/longitude, or address (try --help)".to_string() }));
+        return Err(Box::new(MissingConfigurationError { message: "Need to provide one of file, osm_file, pbf_file, geojson_file, gpx, latitude/longitude, or address (try --help)".to_string() }));
+    };
+    if let Some(gpx_file) = options.gpx_file {
+        mapper.load_gpx_file(gpx_file.to_str().unwrap().to_string())?;
+    }
+    if transverse_mercator && longitude.is_none() {
+        mapper.set_projection(Projection::TransverseMercator { longitude: mapper.location().1 })?;
+    }
+    // Also used when loading more data from the viewer.
+    mapper.settings = settings;
+    Ok(mapper)
+}
+
+// Lets the user pick one of the places matching an ambiguous address, the first one is the default.
+fn choose_geocoding_candidate(mut candidates: Vec<nominatim::GeocodingCandidate>) -> Result<nominatim::GeocodingCandidate, Box<dyn std::error::Error>> {
+    if candidates.len() == 1 {
+        return Ok(candidates.remove(0));
+    }
+    println!("Several places match this address:");
+    for (index, candidate) in candidates.iter().enumerate() {
+        println!("  {}. {}", index + 1, candidate);
+    }
+    loop {
+        print!("Choose a place [1-{}] (default 1): ", candidates.len());
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 || line.trim().is_empty() {
+            return Ok(candidates.remove(0));
+        }
+        match line.trim().parse::<usize>() {
+            Ok(choice) if choice >= 1 && choice <= candidates.len() => return Ok(candidates.remove(choice - 1)),
+            _ => println!("Invalid choice: {}", line.trim()),
+        }
     }
 }
 
@@ -97,6 +150,7 @@ pub fn run_crossterm(
     let mut mapper = cli_options_to_mapper(options)?;
     let mut zoom = 1;
     let mut loading = false;
+    let mut address = None;
     terminal.clear()?;
     // Display loop.
     loop {
@@ -104,22 +158,19 @@ pub fn run_crossterm(
         let mut viewport = viewport::Viewport {
             data_structure: mapper.data_structure.clone(),
             coordinates: mapper.coordinates,
+            grid: mapper.grid(),
             zoom,
             loading,
+            address: address.clone(),
         };
         // Redraw entities in terminal.
-        terminal.draw(|mut f| {
-            draw(&mut f, &mut viewport);
+        terminal.draw(|f| {
+            draw(f, &mut viewport);
         })?;
         // Load more data if requested by user.
         if loading {
-            let geojson_file = operations::get_geojson_file_by_lat_lon(
-                operations::from_tile_scale(mapper.coordinates.y),
-                operations::from_tile_scale(mapper.coordinates.x),
-                operations::from_tile_scale(mapper.radius as i32)
-            )?;
-            let geojson = geojson_parser::parse_geojson_file(&geojson_file.to_string());
-            operations::process_geojson_with_data_structure(&geojson, mapper.data_structure.clone());
+            let (latitude, longitude) = mapper.location();
+            mapper.load_more_from_lat_lon(latitude, longitude, Some(mapper.radius))?;
             loading = false;
             continue; // Go back to drawing with new data.
         }
@@ -127,6 +178,9 @@ pub fn run_crossterm(
         loop {
             if let Some(input_action) = input::process_user_input(&mut viewport) {
                 // Store our new coordinates in the mapper again and update our zoom level.
+                if mapper.coordinates != viewport.coordinates {
+                    address = None;
+                }
                 mapper.coordinates = viewport.coordinates;
                 zoom = viewport.zoom;
                 // Valid user input received and processed.
@@ -139,6 +193,15 @@ pub fn run_crossterm(
                 else if input_action == actions::PlayerAction::LoadMoreData {
                     loading = true;
                 }
+                // Look up the address of the current location, failures are shown instead of the address.
+                else if input_action == actions::PlayerAction::ShowAddress {
+                    let (latitude, longitude) = mapper.location();
+                    let result = mapper.reverse_geocode(latitude, longitude);
+                    address = Some(match result {
+                        Ok(found) => found.to_string(),
+                        Err(error) => error.to_string(),
+                    });
+                }
                 // Back to draw loop.
                 break;
             }
@@ -169,7 +232,7 @@ fn draw_left_panel(f: &mut tui::Frame<CrosstermBackend<Stdout>>, viewport: &view
 
 fn draw_details_panel(f: &mut tui::Frame<CrosstermBackend<Stdout>>, viewport: &viewport::Viewport, area: Rect) {
     let block = Block::default().title("Details").borders(Borders::ALL);
-    let geo_tile_lines = geo_tile_text_lines(&viewport);
+    let geo_tile_lines = geo_tile_text_lines(viewport);
     let paragraph = Paragraph::new(geo_tile_lines)
         .block(block)
         .alignment(Alignment::Center)
@@ -180,18 +243,18 @@ fn draw_details_panel(f: &mut tui::Frame<CrosstermBackend<Stdout>>, viewport: &v
 
 fn draw_info_panel(f: &mut tui::Frame<CrosstermBackend<Stdout>>, viewport: &viewport::Viewport, area: Rect) {
     let block = Block::default().title("Info").borders(Borders::ALL);
-    let lines;
-    if viewport.loading {
-        lines = Text::from("Loading more data at current location...\n");
+    let lines = if viewport.loading {
+        Text::from("Loading more data at current location...\n")
     } else {
-        lines = Text::from(vec![
+        Text::from(vec![
             Spans::from("Movement: <Up>, <Down>, <Left>, <Right>\n"),
             Spans::from("10x Movement: <Shift> + Movement Key\n"),
             Spans::from("Zoom In/Out: Z\n"),
             Spans::from("Load More Data: <Enter>\n"),
+            Spans::from("Show Address: A\n"),
             Spans::from("Quit: Q\n")
-        ]);
-    }
+        ])
+    };
     let paragraph = Paragraph::new(lines)
         .block(block)
         .alignment(Alignment::Center)
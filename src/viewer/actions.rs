@@ -12,6 +12,7 @@ pub enum PlayerAction {
     MoveRight10,
     ZoomToggle,
     LoadMoreData,
+    ShowAddress,
     Quit,
 }
 
@@ -68,6 +69,10 @@ pub fn from_input(code: KeyCode, modifiers: KeyModifiers) -> Option<PlayerAction
     if let KeyCode::Enter = code {
         return Some(PlayerAction::LoadMoreData);
     }
+    // Handle request to look up the address of the current location.
+    if let KeyCode::Char('a') = code {
+        return Some(PlayerAction::ShowAddress);
+    }
     // Handle quitting.
     if let KeyCode::Esc = code {
         return Some(PlayerAction::Quit);
@@ -1,17 +1,27 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 use structopt::StructOpt;
 
+use crate::{cache::CacheSettings, filter::Filter, settings::Settings};
+
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "osm-geo-mapper",
     about = "\nWill display OpenStreeMap data (PBF/OSM/GeoJSON) lines/points/polygons in the terminal."
 )]
 pub struct CLIOptions {
+    #[structopt(
+        short = "i",
+        long = "file",
+        parse(from_os_str),
+        help = "Optionally provide a file in any supported format, detected from its extension (.osm, .o5m, .opl, .pbf, .geojson, .geojsonl, .gpx, .csv, .tsv, .mbtiles, possibly followed by .bz2, .gz or .zst)"
+    )]
+    pub file: Option<PathBuf>,
+
     #[structopt(
         short = "g",
         long = "geojson-file",
         parse(from_os_str),
-        help = "Optionally provide a GeoJSON file directly to be parsed and displayed in the terminal"
+        help = "Optionally provide a GeoJSON (or GeoJSONSeq) file directly to be parsed and displayed in the terminal - may be compressed with bzip2, gzip or zstd"
     )]
     pub geojson_file: Option<PathBuf>,
 
@@ -19,7 +29,7 @@ pub struct CLIOptions {
         short = "o",
         long = "osm-file",
         parse(from_os_str),
-        help = "Optionally provide a OSM file directly to be parsed and displayed in the terminal"
+        help = "Optionally provide a OSM file (XML, or o5m/OPL detected from the .o5m/.opl extension) directly to be parsed and displayed in the terminal - may be compressed with bzip2, gzip or zstd"
     )]
     pub osm_file: Option<PathBuf>,
 
@@ -31,17 +41,50 @@ pub struct CLIOptions {
     )]
     pub pbf_file: Option<PathBuf>,
 
+    #[structopt(
+        long = "gpx",
+        parse(from_os_str),
+        help = "Optionally provide a GPX file whose tracks, routes and waypoints are drawn on top of the OpenStreetMap data (displayed alone if no other data source is given)"
+    )]
+    pub gpx_file: Option<PathBuf>,
+
+    #[structopt(
+        short = "f",
+        long = "filter",
+        help = "Only load the matching objects from the OSM/PBF file - comma separated tag keys, key=value pairs and/or GeoTile kinds (eg. \"Building,highway,amenity=cafe\")"
+    )]
+    pub filter: Option<Filter>,
+
+    #[structopt(
+        long = "tile-zoom",
+        help = "Zoom level of the vector tiles read from an MBTiles file - defaults to the most detailed one"
+    )]
+    pub tile_zoom: Option<u8>,
+
+    #[structopt(
+        long = "tile-scale",
+        help = "Number of grid cells per lat/lon degree - defaults to 100000 (roughly a meter at the equator), lower values show larger areas at a coarser resolution"
+    )]
+    pub tile_scale: Option<f64>,
+
+    #[structopt(
+        long = "projection",
+        possible_values(&["degrees", "web-mercator", "transverse-mercator"]),
+        help = "How lat/lon locations are laid out on the grid - defaults to degrees, transverse-mercator keeps cells square in meters around the loaded area"
+    )]
+    pub projection: Option<String>,
+
     #[structopt(
         long = "latitude",
         requires("longitude"),
-        help = "The latitude that will be used when fetching OpenStreetMap data (ignored if address or OSM/PBF/GeoJSON file is provided)"
+        help = "The latitude that will be used when fetching OpenStreetMap data (ignored if address or OSM/GeoJSON file is provided, limits the area loaded from a PBF or MBTiles file)"
     )]
     pub latitude: Option<f64>,
 
     #[structopt(
         long = "longitude",
         requires("latitude"),
-        help = "The longitude that will be used when fetching OpenStreetMap data (ignored if address or OSM/PBF/GeoJSON file is provided)"
+        help = "The longitude that will be used when fetching OpenStreetMap data (ignored if address or OSM/GeoJSON file is provided, limits the area loaded from a PBF or MBTiles file)"
     )]
     pub longitude: Option<f64>,
 
@@ -55,7 +98,84 @@ pub struct CLIOptions {
     #[structopt(
         short = "r",
         long = "radius",
-        help = "The radius of the area of land to retrieve in 100,000th of a lat/lon degree (roughly a meter at the equator) - defaults to 200 (0.002 degrees or ~200m). Significantly impacts loading times. Ignored if PBF/GeoJSON file is provided"
+        help = "The radius of the area of land to retrieve in 100,000th of a lat/lon degree (roughly a meter at the equator) - defaults to 200 (0.002 degrees or ~200m), or to the size of the place chosen with --address. Significantly impacts loading times. Ignored if OSM/GeoJSON file is provided"
     )]
     pub radius: Option<u32>,
+
+    #[structopt(
+        long = "overpass-url",
+        help = "Base url of the Overpass API used to fetch OpenStreetMap data - defaults to https://overpass-api.de/api"
+    )]
+    pub overpass_url: Option<String>,
+
+    #[structopt(
+        long = "nominatim-url",
+        help = "Base url of the Nominatim instance used to look up addresses - defaults to https://nominatim.openstreetmap.org"
+    )]
+    pub nominatim_url: Option<String>,
+
+    #[structopt(
+        long = "user-agent",
+        help = "User agent sent to the Overpass and Nominatim services - defaults to osm-geo-mapper"
+    )]
+    pub user_agent: Option<String>,
+
+    #[structopt(
+        long = "timeout",
+        help = "Timeout (in seconds) of the requests made to the Overpass and Nominatim services"
+    )]
+    pub timeout: Option<u64>,
+
+    #[structopt(
+        long = "proxy",
+        help = "Proxy used for the requests made to the Overpass and Nominatim services (eg. http://localhost:3128)"
+    )]
+    pub proxy: Option<String>,
+
+    #[structopt(
+        long = "max-response-size",
+        help = "Maximum size (in bytes) of the responses from the Overpass and Nominatim services"
+    )]
+    pub max_response_size: Option<u64>,
+
+    #[structopt(
+        long = "cache",
+        help = "Cache downloaded areas on disk (up to 512 MB, for a week) and reuse them for areas they cover"
+    )]
+    pub cache: bool,
+
+    #[structopt(
+        long = "cache-dir",
+        parse(from_os_str),
+        help = "Directory where downloaded areas are cached, implies --cache - defaults to osm-geo-mapper in the user's cache directory (eg. ~/.cache)"
+    )]
+    pub cache_dir: Option<PathBuf>,
+
+    #[structopt(
+        long = "offline",
+        help = "Only use previously downloaded (cached) areas, never reach the Overpass or Nominatim services - implies --cache"
+    )]
+    pub offline: bool,
+}
+
+impl CLIOptions {
+    pub fn settings(&self) -> Settings {
+        let defaults = Settings::default();
+        Settings {
+            overpass_url: self.overpass_url.clone().unwrap_or(defaults.overpass_url),
+            nominatim_url: self.nominatim_url.clone().unwrap_or(defaults.nominatim_url),
+            user_agent: self.user_agent.clone().unwrap_or(defaults.user_agent),
+            timeout: self.timeout.map(Duration::from_secs),
+            proxy: self.proxy.clone(),
+            max_response_size: self.max_response_size,
+            retry_policy: defaults.retry_policy,
+            cache: if self.cache || self.cache_dir.is_some() || self.offline {
+                let cache = CacheSettings::default();
+                Some(CacheSettings { directory: self.cache_dir.clone().unwrap_or(cache.directory), ..cache })
+            } else {
+                None
+            },
+            offline: self.offline,
+        }
+    }
 }
@@ -2,28 +2,28 @@ use tui::text::Text;
 use geo_types as gt;
 
 use super::viewport::Viewport;
-use crate::operations;
+use crate::projection::TileGrid;
 
-pub fn coord_to_lat_long_string(coord: &gt::Coordinate<i32>) -> String {
-    format!(
-        "Lat: {}, Long: {}",
-        operations::from_tile_scale(coord.y),
-        operations::from_tile_scale(coord.x),
-    )
+pub fn coord_to_lat_long_string(coord: &gt::Coordinate<i32>, grid: TileGrid) -> String {
+    let (longitude, latitude) = grid.from_tile(*coord);
+    format!("Lat: {}, Long: {}", latitude, longitude)
 }
 
-pub fn geo_tile_text_lines(viewport: &Viewport) -> Text {
+pub fn geo_tile_text_lines(viewport: &Viewport) -> Text<'_> {
     let mut text = Text::from("");
     {
         let locked_data_structure = viewport.data_structure.read().unwrap();
         if let Some(geo_tiles) = locked_data_structure.get(&viewport.coordinates) {
-            for geo_tile in geo_tiles.into_iter() {
+            for geo_tile in geo_tiles.iter() {
                 text.extend(Text::from(format!("{}\n", geo_tile)));
             }
         } else {
             text.extend(Text::from("No details available\n".to_string()));
         }
     }
-    text.extend(Text::from(coord_to_lat_long_string(&viewport.coordinates)));
+    text.extend(Text::from(coord_to_lat_long_string(&viewport.coordinates, viewport.grid)));
+    if let Some(address) = &viewport.address {
+        text.extend(Text::from(format!("\nAddress: {}", address)));
+    }
     text
 }
@@ -1,5 +1,5 @@
-use crate::features::{BuildingType, GeoTile, HighwayType, LanduseType, LeisureType, ManMadeType, NaturalType};
-use tui::style::{Color, Style};
+use crate::features::{BuildingType, GeoTile, HighwayType, LanduseType, LeisureType, ManMadeType, NaturalType, TrackType};
+use tui::style::{Color, Modifier, Style};
 
 pub struct GeoTileTheme {
     pub character: char,
@@ -235,6 +235,17 @@ pub fn get_geo_tile_theme(geo_tile: &GeoTile) -> GeoTileTheme {
             character: 'T',
             style: Style::default().bg(Color::LightYellow),
         },
+        // GPX overlays, drawn in colors not used by OSM features so that they stand out.
+        GeoTile::Track { track_type, .. } => match track_type {
+            TrackType::Track => GeoTileTheme {
+                character: '*',
+                style: Style::default().fg(Color::LightRed).add_modifier(Modifier::BOLD),
+            },
+            TrackType::Route => GeoTileTheme {
+                character: '*',
+                style: Style::default().fg(Color::LightMagenta).add_modifier(Modifier::BOLD),
+            },
+        },
         GeoTile::Water { .. } => GeoTileTheme {
             character: ' ',
             style: Style::default().bg(Color::Blue),
@@ -243,6 +254,10 @@ pub fn get_geo_tile_theme(geo_tile: &GeoTile) -> GeoTileTheme {
             character: ' ',
             style: Style::default().bg(Color::Blue),
         },
+        GeoTile::Waypoint { .. } => GeoTileTheme {
+            character: 'W',
+            style: Style::default().bg(Color::Red).fg(Color::White).add_modifier(Modifier::BOLD),
+        },
         GeoTile::Unclassified { .. } => GeoTileTheme {
             character: 'U',
             style: Style::default(),
@@ -6,13 +6,17 @@ use tui::{
     widgets::Widget,
 };
 
-use crate::{features::GeoTilesDataStructure, viewer::theme::get_geo_tile_theme};
+use crate::{features::GeoTilesDataStructure, projection::TileGrid, viewer::theme::get_geo_tile_theme};
 
 pub struct Viewport {
     pub data_structure: GeoTilesDataStructure,
     pub coordinates: gt::Coordinate<i32>,
+    // Grid of the data structure, to display the coordinates.
+    pub grid: TileGrid,
     pub zoom: u8,
     pub loading: bool,
+    // Address of the current coordinates, once looked up.
+    pub address: Option<String>,
 }
 
 impl Viewport {
@@ -50,7 +54,7 @@ impl Widget for &mut Viewport {
                 let mut character = ' ';
                 let mut style = Style::default();
                 if let Some(geo_tiles) = locked_data_structure.get(&current_coordinates) {
-                    if geo_tiles.len() > 0 {
+                    if !geo_tiles.is_empty() {
                         let geo_tile = geo_tiles[0].clone();
                         let theme = get_geo_tile_theme(&geo_tile);
                         character = theme.character;
@@ -68,7 +72,7 @@ impl Widget for &mut Viewport {
         //           However, it would eventually start working normally again and offset the
         //           cursor by one above. Need to figure out why this is happening.
         //           May be related to the data received by OpenStreetMap.
-        buf.get_mut(left + (width / 2) as u16, top + (height / 2) as u16)
+        buf.get_mut(left + width / 2, top + height / 2)
            .set_char('+')
            .set_style(Style::default().fg(Color::Red));
     }
@@ -0,0 +1,197 @@
+use std::convert::TryInto;
+use geo_types as gt;
+
+use crate::{ error::ParseError, features::Geometry };
+
+const WKB_POINT: u32 = 1;
+const WKB_LINE_STRING: u32 = 2;
+const WKB_POLYGON: u32 = 3;
+// Flags of the PostGIS extended WKB (EWKB) geometry type.
+const EWKB_Z: u32 = 0x8000_0000;
+const EWKB_M: u32 = 0x4000_0000;
+const EWKB_SRID: u32 = 0x2000_0000;
+
+// Well-known binary (https://www.ogc.org/standards/sfa) of the geometries GeoTiles can hold.
+impl Geometry {
+    // Little endian 2D WKB.
+    pub fn to_wkb(&self) -> Vec<u8> {
+        let mut wkb = vec![1];
+        match self {
+            Geometry::Point(point) => {
+                wkb.extend(&WKB_POINT.to_le_bytes());
+                write_coordinate(&mut wkb, &point.0);
+            }
+            Geometry::LineString(line_string) => {
+                wkb.extend(&WKB_LINE_STRING.to_le_bytes());
+                write_coordinates(&mut wkb, &line_string.0);
+            }
+            Geometry::Polygon(polygon) => {
+                wkb.extend(&WKB_POLYGON.to_le_bytes());
+                wkb.extend(&(1 + polygon.interiors().len() as u32).to_le_bytes());
+                for ring in std::iter::once(polygon.exterior()).chain(polygon.interiors()) {
+                    write_coordinates(&mut wkb, &ring.0);
+                }
+            }
+        }
+        wkb
+    }
+
+    // Hex encoded WKB, the way PostGIS and most CSV exports write it.
+    pub fn to_wkb_hex(&self) -> String {
+        self.to_wkb().iter().map(|byte| format!("{:02X}", byte)).collect()
+    }
+
+    // Accepts both byte orders, ISO (1001, 2001, etc.) and EWKB (with SRID) Z/M variants, the extra
+    // ordinates are dropped. Multi geometries are not supported.
+    pub fn from_wkb(wkb: &[u8]) -> Result<Geometry, ParseError> {
+        let mut reader = WkbReader { wkb, position: 0, little_endian: true };
+        reader.little_endian = match reader.bytes(1)?[0] {
+            0 => false,
+            1 => true,
+            byte_order => return Err(reader.error(&format!("invalid byte order {}", byte_order))),
+        };
+        let geometry_type = reader.u32()?;
+        if geometry_type & EWKB_SRID != 0 {
+            reader.u32()?;
+        }
+        let mut dimensions = 2;
+        if geometry_type & EWKB_Z != 0 {
+            dimensions += 1;
+        }
+        if geometry_type & EWKB_M != 0 {
+            dimensions += 1;
+        }
+        let iso_type = geometry_type & 0x0FFF_FFFF;
+        dimensions += match iso_type / 1000 {
+            1 | 2 => 1,
+            3 => 2,
+            _ => 0,
+        };
+        let geometry = match iso_type % 1000 {
+            WKB_POINT => Geometry::Point(reader.coordinate(dimensions)?.into()),
+            WKB_LINE_STRING => {
+                let coordinates = reader.coordinates(dimensions)?;
+                if coordinates.len() < 2 {
+                    return Err(reader.error("a line string needs at least 2 positions"));
+                }
+                Geometry::LineString(coordinates.into())
+            }
+            WKB_POLYGON => {
+                let ring_count = reader.u32()?;
+                let mut rings = Vec::new();
+                for _ in 0..ring_count {
+                    let ring = reader.coordinates(dimensions)?;
+                    if ring.len() < 3 {
+                        return Err(reader.error("a polygon ring needs at least 3 positions"));
+                    }
+                    rings.push(gt::LineString::from(ring));
+                }
+                if rings.is_empty() {
+                    return Err(reader.error("a polygon needs at least one ring"));
+                }
+                let exterior = rings.remove(0);
+                Geometry::Polygon(gt::Polygon::new(exterior, rings))
+            }
+            _ => return Err(ParseError::new(format!("Unsupported WKB geometry type: {}", geometry_type))),
+        };
+        if reader.position != wkb.len() {
+            return Err(reader.error("unexpected trailing bytes"));
+        }
+        Ok(geometry)
+    }
+
+    pub fn from_wkb_hex(wkb_hex: &str) -> Result<Geometry, ParseError> {
+        let wkb_hex = wkb_hex.trim();
+        if wkb_hex.len() % 2 != 0 || !wkb_hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(ParseError::new("Invalid hex encoded WKB".to_string()));
+        }
+        let wkb: Vec<u8> = (0..wkb_hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&wkb_hex[index..index + 2], 16).unwrap())
+            .collect();
+        Geometry::from_wkb(&wkb)
+    }
+}
+
+fn write_coordinate(wkb: &mut Vec<u8>, coordinate: &gt::Coordinate<f64>) {
+    wkb.extend(&coordinate.x.to_le_bytes());
+    wkb.extend(&coordinate.y.to_le_bytes());
+}
+
+fn write_coordinates(wkb: &mut Vec<u8>, coordinates: &[gt::Coordinate<f64>]) {
+    wkb.extend(&(coordinates.len() as u32).to_le_bytes());
+    for coordinate in coordinates {
+        write_coordinate(wkb, coordinate);
+    }
+}
+
+struct WkbReader<'a> {
+    wkb: &'a [u8],
+    position: usize,
+    little_endian: bool,
+}
+
+impl<'a> WkbReader<'a> {
+    fn error(&self, message: &str) -> ParseError {
+        ParseError::new(format!("Invalid WKB at byte {}: {}", self.position, message))
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], ParseError> {
+        match self.wkb.get(self.position..self.position + count) {
+            Some(bytes) => {
+                self.position += count;
+                Ok(bytes)
+            }
+            None => Err(self.error("unexpected end of data")),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        let bytes = self.bytes(4)?.try_into().unwrap();
+        Ok(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    fn f64(&mut self) -> Result<f64, ParseError> {
+        let bytes = self.bytes(8)?.try_into().unwrap();
+        let value = if self.little_endian { f64::from_le_bytes(bytes) } else { f64::from_be_bytes(bytes) };
+        if value.is_finite() {
+            Ok(value)
+        } else {
+            Err(self.error("coordinates must be finite numbers"))
+        }
+    }
+
+    fn coordinate(&mut self, dimensions: usize) -> Result<gt::Coordinate<f64>, ParseError> {
+        let coordinate = gt::Coordinate { x: self.f64()?, y: self.f64()? };
+        self.bytes(8 * (dimensions - 2))?;
+        Ok(coordinate)
+    }
+
+    fn coordinates(&mut self, dimensions: usize) -> Result<Vec<gt::Coordinate<f64>>, ParseError> {
+        let count = self.u32()? as usize;
+        // Checked against the remaining data so that a corrupted count can't allocate gigabytes.
+        if count > (self.wkb.len() - self.position) / (8 * dimensions) {
+            return Err(self.error("more positions than remaining data"));
+        }
+        (0..count).map(|_| self.coordinate(dimensions)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wkb_round_trip() {
+        // POINT (1 2) in big endian.
+        let point = Geometry::from_wkb_hex("00000000013FF00000000000004000000000000000").unwrap();
+        assert_eq!(point.to_wkt(), "POINT (1 2)");
+        assert_eq!(point.to_wkb_hex(), "0101000000000000000000F03F0000000000000040");
+        // EWKB POINT Z with SRID 4326.
+        let point_z = Geometry::from_wkb_hex("01010000A0E6100000000000000000F03F00000000000000400000000000000840").unwrap();
+        assert_eq!(point_z.to_wkt(), "POINT (1 2)");
+        let polygon = Geometry::from_wkt("POLYGON ((0 0, 10 0, 10 10, 0 0), (1 1, 2 1, 2 2, 1 1))").unwrap();
+        assert_eq!(Geometry::from_wkb(&polygon.to_wkb()).unwrap().to_wkt(), polygon.to_wkt());
+        assert!(Geometry::from_wkb(&polygon.to_wkb()[..20]).is_err());
+    }
+}
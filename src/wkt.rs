@@ -0,0 +1,190 @@
+use std::{ fmt::Write, iter::Peekable, str::CharIndices };
+use geo_types as gt;
+
+use crate::{ error::ParseError, features::Geometry };
+
+// Well-known text (https://www.ogc.org/standards/sfa) of the geometries GeoTiles can hold.
+impl Geometry {
+    // eg. "POINT (-75.7 45.4)", "LINESTRING (-75.7 45.4, -75.8 45.5)" or "POLYGON ((...), (...))".
+    pub fn to_wkt(&self) -> String {
+        match self {
+            Geometry::Point(point) => format!("POINT ({})", coordinate_to_wkt(&point.0)),
+            Geometry::LineString(line_string) => format!("LINESTRING {}", coordinates_to_wkt(&line_string.0)),
+            Geometry::Polygon(polygon) => {
+                let mut rings = coordinates_to_wkt(&polygon.exterior().0);
+                for interior in polygon.interiors() {
+                    write!(rings, ", {}", coordinates_to_wkt(&interior.0)).unwrap();
+                }
+                format!("POLYGON ({})", rings)
+            }
+        }
+    }
+
+    // Also accepts EWKT (a leading "SRID=4326;") and Z/M coordinates, whose extra ordinates are dropped.
+    // Coordinates are expected to be longitude/latitude. Multi geometries and empty geometries are not supported.
+    pub fn from_wkt(wkt: &str) -> Result<Geometry, ParseError> {
+        let wkt = match wkt.trim_start().get(..5) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("srid=") => wkt.split_once(';').map_or("", |(_, wkt)| wkt),
+            _ => wkt,
+        };
+        let mut tokens = Tokenizer::new(wkt);
+        let geometry_type = tokens.word()?.to_uppercase();
+        if tokens.peek_word() {
+            let dimensions = tokens.word()?.to_uppercase();
+            if !["Z", "M", "ZM"].contains(&dimensions.as_str()) {
+                return Err(tokens.error(&format!("unexpected {}", dimensions)));
+            }
+        }
+        let geometry = match geometry_type.as_str() {
+            "POINT" => {
+                tokens.expect('(')?;
+                let coordinate = tokens.coordinate()?;
+                tokens.expect(')')?;
+                Geometry::Point(coordinate.into())
+            }
+            "LINESTRING" => {
+                let coordinates = tokens.coordinates()?;
+                if coordinates.len() < 2 {
+                    return Err(tokens.error("a line string needs at least 2 positions"));
+                }
+                Geometry::LineString(coordinates.into())
+            }
+            "POLYGON" => {
+                tokens.expect('(')?;
+                let mut rings = vec![tokens.coordinates()?];
+                while tokens.next_if(',') {
+                    rings.push(tokens.coordinates()?);
+                }
+                tokens.expect(')')?;
+                if rings.iter().any(|ring| ring.len() < 3) {
+                    return Err(tokens.error("a polygon ring needs at least 3 positions"));
+                }
+                let exterior = rings.remove(0);
+                Geometry::Polygon(gt::Polygon::new(exterior.into(), rings.into_iter().map(gt::LineString::from).collect()))
+            }
+            _ => return Err(ParseError::new(format!("Unsupported WKT geometry type: {}", geometry_type))),
+        };
+        tokens.end()?;
+        Ok(geometry)
+    }
+}
+
+fn coordinate_to_wkt(coordinate: &gt::Coordinate<f64>) -> String {
+    format!("{} {}", coordinate.x, coordinate.y)
+}
+
+fn coordinates_to_wkt(coordinates: &[gt::Coordinate<f64>]) -> String {
+    let coordinates: Vec<String> = coordinates.iter().map(coordinate_to_wkt).collect();
+    format!("({})", coordinates.join(", "))
+}
+
+struct Tokenizer<'a> {
+    wkt: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(wkt: &'a str) -> Tokenizer<'a> {
+        Tokenizer { wkt, chars: wkt.char_indices().peekable() }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn position(&mut self) -> usize {
+        self.chars.peek().map_or(self.wkt.len(), |(position, _)| *position)
+    }
+
+    fn error(&mut self, message: &str) -> ParseError {
+        ParseError::new(format!("Invalid WKT at position {}: {}", self.position(), message))
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, predicate: F) -> &'a str {
+        self.skip_whitespace();
+        let start = self.position();
+        while self.chars.next_if(|(_, c)| predicate(*c)).is_some() {}
+        let end = self.position();
+        &self.wkt[start..end]
+    }
+
+    fn peek_word(&mut self) -> bool {
+        self.skip_whitespace();
+        self.chars.peek().is_some_and(|(_, c)| c.is_ascii_alphabetic())
+    }
+
+    fn word(&mut self) -> Result<&'a str, ParseError> {
+        match self.take_while(|c| c.is_ascii_alphabetic()) {
+            "" => Err(self.error("expected a geometry type")),
+            word => Ok(word),
+        }
+    }
+
+    fn next_if(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if(|(_, c)| *c == expected).is_some()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        if self.next_if(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, ParseError> {
+        let number = self.take_while(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+        number.parse::<f64>().ok().filter(|number| number.is_finite()).ok_or_else(|| self.error("expected a number"))
+    }
+
+    // The x and y of a position, followed by up to two more ordinates (z and/or m).
+    fn coordinate(&mut self) -> Result<gt::Coordinate<f64>, ParseError> {
+        let coordinate = gt::Coordinate { x: self.number()?, y: self.number()? };
+        for _ in 0..2 {
+            self.skip_whitespace();
+            if self.chars.peek().is_some_and(|(_, c)| !matches!(c, ',' | ')')) {
+                self.number()?;
+            }
+        }
+        Ok(coordinate)
+    }
+
+    fn coordinates(&mut self) -> Result<Vec<gt::Coordinate<f64>>, ParseError> {
+        self.expect('(')?;
+        let mut coordinates = vec![self.coordinate()?];
+        while self.next_if(',') {
+            coordinates.push(self.coordinate()?);
+        }
+        self.expect(')')?;
+        Ok(coordinates)
+    }
+
+    fn end(&mut self) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error("unexpected trailing characters")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wkt_round_trip() {
+        for wkt in &[
+            "POINT (-75.7 45.4)",
+            "LINESTRING (-75.7 45.4, -75.8 45.5)",
+            "POLYGON ((0 0, 10 0, 10 10, 0 0), (1 1, 2 1, 2 2, 1 1))",
+        ] {
+            assert_eq!(Geometry::from_wkt(wkt).unwrap().to_wkt(), *wkt);
+        }
+        assert_eq!(Geometry::from_wkt("SRID=4326;point z(1.5 -2e1 30)").unwrap().to_wkt(), "POINT (1.5 -20)");
+        assert!(Geometry::from_wkt("LINESTRING (1 2)").is_err());
+        assert!(Geometry::from_wkt("POINT (1 2) extra").is_err());
+        assert!(Geometry::from_wkt("MULTIPOINT ((1 2))").is_err());
+    }
+}
@@ -1,12 +1,75 @@
 extern crate osm_geo_mapper;
-use std::thread;
+use std::{
+    io::{ BufRead, BufReader, Read, Write },
+    net::TcpListener,
+    path::{ Path, PathBuf },
+    sync::Arc,
+    thread,
+};
 use osm_geo_mapper::{
-    geo_types, interface, features
+    error::Error, filter::Filter, geo_types, interface, features, nominatim, openstreetmap, operations, osm_parser, osmtogeojson,
+    pbf_parser, projection, retry::RetryPolicy, settings::Settings, viewer,
 };
+use structopt::StructOpt;
+
+// Local HTTP stand-in answering the given responses (status line, extra header lines, body) in order.
+// Returns its base url and a handle resolving to the bodies of the requests it received.
+fn serve_responses(responses: Vec<(&'static str, &'static str, &'static str)>) -> (String, thread::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let mut request_bodies = Vec::new();
+        for (status, headers, body) in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                let lowercase_line = line.to_lowercase();
+                if let Some(length) = lowercase_line.strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+            }
+            let mut request_body = vec![0; content_length];
+            reader.read_exact(&mut request_body).unwrap();
+            request_bodies.push(String::from_utf8(request_body).unwrap());
+            write!(stream, "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}", status, headers, body.len(), body).unwrap();
+        }
+        request_bodies
+    });
+    (url, handle)
+}
+
+// A uniquely named file in the temp directory, removed when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(extension: &str) -> TempFile {
+        TempFile(std::env::temp_dir().join(format!("osm_geo_mapper_test_{}.{}", uuid::Uuid::new_v4(), extension)))
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+
+    fn name(&self) -> String {
+        self.0.to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
 
 #[test]
 fn test_address_to_mapper() {
-    let mapper_result = interface::OSMGeoMapper::from_address("ottawa ontario".to_string(), Some(20));
+    let mapper_result = interface::OSMGeoMapper::from_address("ottawa ontario".to_string(), Some(20), None, None);
     assert!(mapper_result.is_ok());
     let mapper1 = mapper_result.unwrap();
     let mapper2 = mapper1.atomic_clone();
@@ -44,25 +107,18 @@ fn test_address_to_mapper() {
     assert!(some_building.is_some());
     let building = some_building.unwrap();
     let geotile: &features::GeoTile = building.last().unwrap().as_ref();
-    match *geotile {
-        features::GeoTile::Place { .. } => { },
-        _ => assert!(false)
-    }
+    assert!(matches!(*geotile, features::GeoTile::Place { .. }));
 }
 
 #[test]
 fn test_multiple_threads() {
-    let mapper_result = interface::OSMGeoMapper::from_address("ottawa ontario".to_string(), Some(20));
+    let mapper_result = interface::OSMGeoMapper::from_address("ottawa ontario".to_string(), Some(20), None, None);
     let mapper = mapper_result.unwrap();
     let mut threads = vec![];
-    for address in vec!["ottawa ontario".to_string(), "montreal quebec".to_string()] {
+    for address in ["ottawa ontario".to_string(), "montreal quebec".to_string()] {
         let mut mapper_clone = mapper.atomic_clone();
         threads.push(thread::spawn(move || {
-            let result = mapper_clone.load_more_from_address(address, Some(20));
-            match result {
-                Err(_) => {}
-                Ok(()) => {}
-            }
+            let _ = mapper_clone.load_more_from_address(address, Some(20));
         }));
     }
     for thread in threads {
@@ -74,4 +130,967 @@ fn test_multiple_threads() {
     let geo_tile = mapper.get_real(45.42114, -75.69006).unwrap();
     let value = geo_tile.last().unwrap().as_ref();
     assert!(matches!(value, features::GeoTile::Place { .. }));
-}
\ No newline at end of file
+}
+#[test]
+fn test_apply_osm_change() {
+    let osm_file = TempFile::new("osm");
+    let osc_file = TempFile::new("osc");
+    std::fs::write(osm_file.path(), r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="45.0" lon="-75.0"/>
+          <node id="2" lat="45.0" lon="-74.9999"/>
+          <node id="3" lat="45.0001" lon="-74.9999"/>
+          <node id="4" lat="45.0001" lon="-75.0"/>
+          <node id="5" lat="45.001" lon="-75.001"><tag k="amenity" v="bench"/></node>
+          <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><nd ref="4"/><nd ref="1"/><tag k="building" v="yes"/></way>
+        </osm>"#).unwrap();
+    std::fs::write(osc_file.path(), r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osmChange version="0.6">
+          <modify>
+            <way id="10" version="2"><nd ref="1"/><nd ref="2"/><nd ref="3"/><nd ref="4"/><nd ref="1"/><tag k="leisure" v="park"/></way>
+          </modify>
+          <delete>
+            <node id="5" version="2" lat="45.001" lon="-75.001"/>
+          </delete>
+          <create>
+            <node id="6" version="1" lat="45.002" lon="-75.002"><tag k="amenity" v="bench"/></node>
+          </create>
+        </osmChange>"#).unwrap();
+    let mut mapper = interface::OSMGeoMapper::from_osm_file(osm_file.name(), None, None, None).unwrap();
+    assert!(matches!(mapper.get_real(45.00005, -74.99995).unwrap()[0].as_ref(), features::GeoTile::Building { .. }));
+    assert!(mapper.get_real(45.001, -75.001).is_some());
+    mapper.apply_osm_change(osc_file.name()).unwrap();
+    let geo_tiles = mapper.get_real(45.00005, -74.99995).unwrap();
+    assert_eq!(geo_tiles.len(), 1);
+    assert!(matches!(geo_tiles[0].as_ref(), features::GeoTile::Leisure { .. }));
+    assert!(mapper.get_real(45.001, -75.001).is_none());
+    assert!(matches!(mapper.get_real(45.002, -75.002).unwrap()[0].as_ref(), features::GeoTile::Amenity { .. }));
+    assert!(mapper.osm_id_cells.read().unwrap().get("node/6").is_some());
+    assert!(mapper.osm_id_cells.read().unwrap().get("node/5").is_none());
+    std::fs::write(osc_file.path(), r#"<osmChange version="0.6"><delete><node version="3"/></delete></osmChange>"#).unwrap();
+    let error = mapper.apply_osm_change(osc_file.name()).unwrap_err();
+    match error.downcast_ref::<Error>() {
+        Some(Error::Parse(error)) => assert_eq!(error.file.as_deref(), Some(osc_file.name().as_str())),
+        _ => panic!("Expected a parse error, got {}", error),
+    }
+}
+
+#[test]
+fn test_osm_change_moved_node() {
+    let osm_file = TempFile::new("osm");
+    let osc_file = TempFile::new("osc");
+    std::fs::write(osm_file.path(), r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="45.0" lon="-75.0"/>
+          <node id="2" lat="45.0" lon="-74.9999"/>
+          <node id="3" lat="45.0001" lon="-74.9999"/>
+          <node id="4" lat="45.0001" lon="-75.0"/>
+          <node id="5" lat="45.001" lon="-75.001"/>
+          <node id="6" lat="45.001" lon="-75.0"/>
+          <way id="10"><nd ref="1"/><nd ref="2"/><nd ref="3"/><nd ref="4"/><nd ref="1"/><tag k="building" v="yes"/></way>
+          <way id="11"><nd ref="5"/><nd ref="6"/><tag k="highway" v="footway"/></way>
+        </osm>"#).unwrap();
+    std::fs::write(osc_file.path(), r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osmChange version="0.6">
+          <modify>
+            <node id="3" version="2" lat="45.0002" lon="-74.9998"/>
+            <node id="6" version="2" lat="45.002" lon="-75.0"/>
+          </modify>
+        </osmChange>"#).unwrap();
+    let mut mapper = interface::OSMGeoMapper::from_osm_file(osm_file.name(), None, None, None).unwrap();
+    assert!(mapper.get_real(45.00012, -74.99985).is_none());
+    assert!(mapper.get_real(45.0015, -75.0005).is_none());
+    mapper.apply_osm_change(osc_file.name()).unwrap();
+    let geo_tiles = mapper.get_real(45.00012, -74.99985).unwrap();
+    assert_eq!(geo_tiles.len(), 1);
+    assert!(matches!(geo_tiles[0].as_ref(), features::GeoTile::Building { .. }));
+    match mapper.get_by_osm_id("way/10").unwrap()[0].geometry() {
+        features::Geometry::Polygon(polygon) => {
+            assert!(polygon.exterior().points_iter().any(|point| point.x() == -74.9998 && point.y() == 45.0002));
+        },
+        geometry => panic!("Unexpected geometry {:?}", geometry),
+    }
+    assert!(matches!(mapper.get_real(45.0015, -75.0005).unwrap()[0].as_ref(), features::GeoTile::Highway { .. }));
+    assert!(mapper.get_real(45.001, -75.0).is_none());
+    assert!(mapper.get_by_osm_id("node/3").is_none());
+    assert_eq!(mapper.way_nodes.read().unwrap()["way/11"], vec![
+        (5, geo_types::Coordinate { x: -75.001, y: 45.001 }),
+        (6, geo_types::Coordinate { x: -75.0, y: 45.002 }),
+    ]);
+}
+
+#[test]
+fn test_overpass_query() {
+    let (url, handle) = serve_responses(vec![("200 OK", "", r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="45.0" lon="-75.0"/>
+          <node id="2" lat="45.0" lon="-74.999"/>
+          <way id="10"><nd ref="1"/><nd ref="2"/><tag k="railway" v="rail"/></way>
+        </osm>"#)]);
+    let query = "way[railway=rail](45,-75,46,-74); (._;>;); out body;";
+    let settings = Settings { overpass_url: format!("{}/api", url), ..Settings::default() };
+    let geojson_file = operations::get_geojson_file_by_overpass_query(query, &settings).unwrap();
+    let request_bodies = handle.join().unwrap();
+    assert!(request_bodies[0].starts_with("data=way%5Brailway%3Drail%5D"));
+    let mapper = interface::OSMGeoMapper::from_geojson_file(geojson_file.path().to_string(), None, None).unwrap();
+    let geo_tiles = mapper.get_real(45.0, -74.9995).unwrap();
+    assert!(matches!(geo_tiles[0].as_ref(), features::GeoTile::Railway { .. }));
+}
+
+#[test]
+fn test_uncached_downloads_are_removed() {
+    // The downloaded files are told apart from the ones of other tests by their content.
+    let marker = uuid::Uuid::new_v4().to_string();
+    let osm_data = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6"><node id="1" lat="45.0" lon="-75.0"><tag k="amenity" v="bench"/><tag k="name" v="{}"/></node></osm>"#, marker);
+    let malformed_osm_data = format!(r#"<?xml version="1.0" encoding="UTF-8"?><osm version="0.6"><node id="1" name="{}"#, marker);
+    let osm_data: &'static str = Box::leak(osm_data.into_boxed_str());
+    let malformed_osm_data: &'static str = Box::leak(malformed_osm_data.into_boxed_str());
+    let (url, handle) = serve_responses(vec![
+        ("200 OK", "", osm_data),
+        ("200 OK", "", osm_data),
+        ("200 OK", "", osm_data),
+        ("200 OK", "", malformed_osm_data),
+    ]);
+    let settings = Settings { overpass_url: url, cache: None, retry_policy: RetryPolicy::none(), ..Settings::default() };
+    let leftover_files = || -> Vec<PathBuf> {
+        std::fs::read_dir(std::env::temp_dir()).unwrap()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.to_str().is_some_and(|path| path.ends_with(".xml") || path.ends_with(".xml.geojson")))
+            .filter(|path| std::fs::read_to_string(path).is_ok_and(|content| content.contains(&marker)))
+            .collect()
+    };
+    let mut mapper = interface::OSMGeoMapper::from_lat_lon(45.0, -75.0, Some(100), Some(settings.clone()), None).unwrap();
+    assert!(mapper.get_real(45.0, -75.0).is_some());
+    assert!(leftover_files().is_empty());
+    mapper.load_more_from_lat_lon(45.0, -75.0, Some(100)).unwrap();
+    assert!(leftover_files().is_empty());
+    interface::OSMGeoMapper::from_overpass_query("node(1);out;".to_string(), None, Some(settings.clone()), None).unwrap();
+    assert!(leftover_files().is_empty());
+    // Removed when the conversion fails too.
+    assert!(interface::OSMGeoMapper::from_lat_lon(45.0, -75.0, Some(100), Some(settings), None).is_err());
+    assert!(leftover_files().is_empty());
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_overpass_errors() {
+    let osm_data = r#"<?xml version="1.0" encoding="UTF-8"?><osm version="0.6"></osm>"#;
+    let (url, handle) = serve_responses(vec![
+        ("429 Too Many Requests", "Retry-After: 0\r\n", ""),
+        ("200 OK", "", osm_data),
+        ("429 Too Many Requests", "Retry-After: 0\r\n", ""),
+        ("200 OK", "", r#"<?xml version="1.0" encoding="UTF-8"?>
+            <osm version="0.6"><remark> runtime error: Query timed out in "query" at line 1 after 2 seconds. </remark></osm>"#),
+        ("502 Bad Gateway", "", "<html><body>Bad Gateway</body></html>"),
+        ("200 OK", "", "<!DOCTYPE html><html><body>Error</body></html>"),
+        // Longer than the allowed wait, not retried.
+        ("429 Too Many Requests", "Retry-After: 3600\r\n", ""),
+        ("504 Gateway Timeout", "Retry-After: Wed, 21 Oct 2015 07:28:00 GMT\r\n", ""),
+    ]);
+    let retry_once = Settings {
+        overpass_url: url.clone(),
+        retry_policy: RetryPolicy { max_retries: 1, ..RetryPolicy::default() },
+        ..Settings::default()
+    };
+    let settings = Settings { overpass_url: url, retry_policy: RetryPolicy::none(), ..Settings::default() };
+    let result = openstreetmap::download_osm_data_by_overpass_query("node(1);out;", &retry_once);
+    assert!(result.is_ok());
+    let result = openstreetmap::download_osm_data_by_overpass_query("node(1);out;", &settings);
+    assert!(matches!(result, Err(Error::RateLimited { retry_after: Some(retry_after) }) if retry_after.as_secs() == 0));
+    let result = openstreetmap::download_osm_data_by_overpass_query("node(1);out;", &settings);
+    assert!(matches!(result, Err(Error::RemoteRuntimeError(message)) if message.starts_with("runtime error: Query timed out")));
+    let result = openstreetmap::download_osm_data_by_overpass_query("node(1);out;", &settings);
+    assert!(matches!(result, Err(Error::RemoteRuntimeError(message)) if message == "HTTP 502 Bad Gateway: <html><body>Bad Gateway</body></html>"));
+    let result = openstreetmap::download_osm_data_by_overpass_query("node(1);out;", &settings);
+    assert!(matches!(result, Err(Error::MalformedResponse(_))));
+    let result = openstreetmap::download_osm_data_by_overpass_query("node(1);out;", &retry_once);
+    assert!(matches!(result, Err(Error::RateLimited { retry_after: Some(retry_after) }) if retry_after.as_secs() == 3600));
+    // HTTP dates in the past mean no wait.
+    let result = openstreetmap::download_osm_data_by_overpass_query("node(1);out;", &settings);
+    assert!(matches!(result, Err(Error::RateLimited { retry_after: Some(retry_after) }) if retry_after.as_secs() == 0));
+    assert_eq!(handle.join().unwrap().len(), 8);
+}
+
+#[test]
+fn test_nominatim_errors() {
+    let (url, handle) = serve_responses(vec![
+        ("200 OK", "", r#"[{"lat": "45.4211435", "lon": "-75.6900574", "display_name": "Ottawa, Ontario, Canada", "class": "place", "type": "city", "importance": 0.8, "boundingbox": ["45.2", "45.5", "-76.0", "-75.2"]}]"#),
+        ("200 OK", "", "[]"),
+        ("200 OK", "", "<html>Oops</html>"),
+        ("429 Too Many Requests", "", ""),
+    ]);
+    let settings = Settings { nominatim_url: url, retry_policy: RetryPolicy::none(), ..Settings::default() };
+    let result = nominatim::get_address_lat_lon("ottawa".to_string(), &settings);
+    assert_eq!(result.unwrap(), (45.4211435, -75.6900574));
+    let result = nominatim::get_address_lat_lon("nowhere".to_string(), &settings);
+    assert!(matches!(result, Err(Error::NoGeocodingResult(address)) if address == "nowhere"));
+    let result = nominatim::get_address_lat_lon("ottawa".to_string(), &settings);
+    assert!(matches!(result, Err(Error::MalformedResponse(_))));
+    let result = nominatim::get_address_lat_lon("ottawa".to_string(), &settings);
+    assert!(matches!(result, Err(Error::RateLimited { retry_after: None })));
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_max_response_size() {
+    let (url, handle) = serve_responses(vec![("200 OK", "", r#"<?xml version="1.0" encoding="UTF-8"?><osm version="0.6"></osm>"#)]);
+    let settings = Settings { overpass_url: url, max_response_size: Some(10), ..Settings::default() };
+    let result = openstreetmap::download_osm_data_by_bbox(-75.0, 45.0, -74.9, 45.1, &settings);
+    assert!(matches!(result, Err(Error::ResponseTooLarge(10))));
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_reverse_geocode() {
+    let (url, handle) = serve_responses(vec![
+        ("200 OK", "", r#"{"display_name": "111, Wellington Street, Ottawa, K1A 0A9, Canada", "address": {"house_number": "111", "road": "Wellington Street", "city": "Ottawa", "postcode": "K1A 0A9", "country": "Canada"}}"#),
+        ("200 OK", "", r#"{"display_name": "Main Street, Wakefield, Canada", "address": {"road": "Main Street", "village": "Wakefield", "country": "Canada"}}"#),
+        ("200 OK", "", r#"{"error": "Unable to geocode"}"#),
+    ]);
+    let mut mapper = interface::OSMGeoMapper::from_geojson_file("resources/ottawa.xml.geojson".to_string(), None, None).unwrap();
+    mapper.settings = Settings { nominatim_url: url, retry_policy: RetryPolicy::none(), ..Settings::default() };
+    let address = mapper.reverse_geocode(45.4236, -75.7009).unwrap();
+    assert_eq!(address, nominatim::ReverseGeocodedAddress {
+        house_number: Some("111".to_string()),
+        road: Some("Wellington Street".to_string()),
+        city: Some("Ottawa".to_string()),
+        postcode: Some("K1A 0A9".to_string()),
+        country: Some("Canada".to_string()),
+        display_name: "111, Wellington Street, Ottawa, K1A 0A9, Canada".to_string(),
+    });
+    let address = nominatim::get_lat_lon_address(45.6, -75.9, &mapper.settings).unwrap();
+    assert_eq!(address.city, Some("Wakefield".to_string()));
+    assert_eq!(address.house_number, None);
+    let result = nominatim::get_lat_lon_address(0.0, 0.0, &mapper.settings);
+    assert!(matches!(result, Err(Error::NoGeocodingResult(_))));
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_geocoding_candidates() {
+    let candidates = r#"[
+        {"lat": "39.7990175", "lon": "-89.6439575", "display_name": "Springfield, Illinois, United States", "class": "boundary", "type": "administrative", "importance": 0.72, "boundingbox": ["39.6", "39.9", "-89.8", "-89.5"]},
+        {"lat": "37.2081729", "lon": "-93.2922715", "display_name": "Springfield, Missouri, United States", "class": "place", "type": "city", "boundingbox": ["37.08", "37.27", "-93.41", "-93.19"]}
+    ]"#;
+    let single_candidate = r#"[{"lat": "45.001", "lon": "-75.001", "display_name": "Park, Ottawa", "class": "leisure", "type": "park", "importance": 0.3, "boundingbox": ["45.0", "45.0015", "-75.0015", "-75.0"]}]"#;
+    let osm_data = r#"<?xml version="1.0" encoding="UTF-8"?><osm version="0.6"><node id="1" lat="45.001" lon="-75.001"><tag k="leisure" v="park"/></node></osm>"#;
+    let (url, handle) = serve_responses(vec![("200 OK", "", candidates), ("200 OK", "", single_candidate), ("200 OK", "", osm_data)]);
+    let settings = Settings { nominatim_url: url.clone(), overpass_url: url, cache: None, ..Settings::default() };
+    let candidates = nominatim::get_address_candidates("springfield".to_string(), 10, &settings).unwrap();
+    assert_eq!(candidates.len(), 2);
+    assert_eq!(candidates[0].kind, "administrative");
+    assert_eq!(candidates[0].importance, Some(0.72));
+    assert_eq!(candidates[0].bounding_box, (-89.8, 39.6, -89.5, 39.9));
+    assert_eq!(candidates[1].importance, None);
+    assert_eq!(candidates[1].to_string(), "Springfield, Missouri, United States (place=city)");
+    // The furthest edge of the bounding box is 0.001 degrees away.
+    let mapper = interface::OSMGeoMapper::from_address_bounding_box("park".to_string(), Some(500), Some(settings), None).unwrap();
+    assert_eq!(mapper.radius, 100);
+    assert!(mapper.get_real(45.001, -75.001).is_some());
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_find_address() {
+    let osm_file = TempFile::new("osm");
+    std::fs::write(osm_file.path(), r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="45.0" lon="-75.0"><tag k="addr:housenumber" v="100"/><tag k="addr:street" v="Main Street"/><tag k="addr:postcode" v="K1A 0A9"/></node>
+          <node id="2" lat="45.0" lon="-74.999"><tag k="addr:housenumber" v="110"/><tag k="addr:street" v="Main Street"/></node>
+          <way id="10"><nd ref="1"/><nd ref="2"/><tag k="addr:interpolation" v="even"/></way>
+          <node id="3" lat="45.001" lon="-75.001"><tag k="amenity" v="cafe"/><tag k="addr:housenumber" v="7"/><tag k="addr:street" v="Bank Street"/></node>
+        </osm>"#).unwrap();
+    let mut mapper = interface::OSMGeoMapper::from_osm_file(osm_file.name(), None, None, None).unwrap();
+    let matches = mapper.find_address("100 Main St");
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].coordinates, geo_types::Coordinate { x: -7500000, y: 4500000 });
+    assert!(!matches[0].interpolated);
+    assert!(matches!(mapper.find_address("7 bank st., Ottawa")[0].geo_tiles[0].as_ref(), features::GeoTile::Amenity { .. }));
+    assert_eq!(mapper.find_address("K1A 0A9").len(), 1);
+    assert!(mapper.find_address("110 Main St, K1A 0A9").is_empty());
+    // 104 is 40% of the way from 100 to 110, odd numbers aren't on this side of the street.
+    let matches = mapper.find_address("104 Main Street");
+    assert_eq!(matches.len(), 1);
+    assert!(matches[0].interpolated);
+    assert_eq!(matches[0].coordinates, geo_types::Coordinate { x: -7499960, y: 4500000 });
+    assert!(mapper.find_address("105 Main Street").is_empty());
+    assert!(mapper.find_address("120 Main Street").is_empty());
+    // The index follows the loaded features.
+    let more_file = TempFile::new("osm");
+    std::fs::write(more_file.path(), r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="4" lat="45.002" lon="-75.002"><tag k="addr:housenumber" v="12"/><tag k="addr:street" v="Elm Street"/></node>
+        </osm>"#).unwrap();
+    mapper.load_more_from_osm_file(more_file.name(), None).unwrap();
+    assert_eq!(mapper.find_address("12 Elm St")[0].coordinates, geo_types::Coordinate { x: -7500200, y: 4500200 });
+    mapper.remove_feature("node/2");
+    assert!(mapper.find_address("110 Main Street").is_empty());
+    assert!(mapper.find_address("104 Main Street").is_empty());
+    assert_eq!(mapper.find_address("100 Main St").len(), 1);
+    mapper.set_tile_scale(10_000.0).unwrap();
+    assert_eq!(mapper.find_address("12 Elm St")[0].coordinates, geo_types::Coordinate { x: -750020, y: 450020 });
+}
+
+#[test]
+fn test_spatial_queries() {
+    let osm_file = TempFile::new("osm");
+    let more_file = TempFile::new("osm");
+    std::fs::write(osm_file.path(), r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="45.0" lon="-75.0"><tag k="amenity" v="bench"/></node>
+          <node id="2" lat="45.001" lon="-75.0"><tag k="amenity" v="cafe"/></node>
+        </osm>"#).unwrap();
+    std::fs::write(more_file.path(), r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="3" lat="45.0002" lon="-75.0"><tag k="amenity" v="cafe"/></node>
+        </osm>"#).unwrap();
+    let mut mapper = interface::OSMGeoMapper::from_osm_file(osm_file.name(), None, None, None).unwrap();
+    let is_cafe = |geo_tile: &features::GeoTile| matches!(geo_tile, features::GeoTile::Amenity { amenity_type: features::AmenityType::Cafe, .. });
+    assert_eq!(mapper.nearest(45.0, -75.0, 1, is_cafe)[0].0.osm_id(), "node/2");
+    // The index follows the loaded features.
+    mapper.load_more_from_osm_file(more_file.name(), None).unwrap();
+    assert_eq!(mapper.nearest(45.0, -75.0, 1, is_cafe)[0].0.osm_id(), "node/3");
+    assert_eq!(mapper.features_in_bbox(-75.001, 44.999, -74.999, 45.0005).len(), 2);
+    mapper.remove_feature("node/1");
+    let within: Vec<String> = mapper.features_within(45.0, -75.0, 50.0).iter().map(|(geo_tile, _)| geo_tile.osm_id().to_string()).collect();
+    assert_eq!(within, vec!["node/3"]);
+}
+
+#[test]
+fn test_spatial_queries_at_coarse_scale() {
+    let geojson_file = TempFile::new("geojson");
+    std::fs::write(geojson_file.path(), r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": {"id": "way/1", "building": "yes"}, "geometry": {"type": "Polygon", "coordinates": [[[-75.0301, 45.0301], [-75.0299, 45.0301], [-75.0299, 45.0299], [-75.0301, 45.0299], [-75.0301, 45.0301]]]}},
+        {"type": "Feature", "properties": {"id": "node/2", "amenity": "cafe"}, "geometry": {"type": "Point", "coordinates": [-75.0, 45.0]}}
+    ]}"#).unwrap();
+    // A cell is a tenth of a degree wide, the building doesn't cover any.
+    let grid = projection::TileGrid::new(10.0, projection::Projection::Degrees);
+    let options = interface::LoadOptions { grid, ..Default::default() };
+    let mut mapper = interface::OSMGeoMapper::from_geojson_file(geojson_file.name(), None, Some(options)).unwrap();
+    let in_bbox = |mapper: &interface::OSMGeoMapper| -> Vec<String> {
+        mapper.features_in_bbox(-75.031, 45.029, -75.029, 45.031).iter().map(|geo_tile| geo_tile.osm_id().to_string()).collect()
+    };
+    assert!(mapper.cells_of("way/1").is_none());
+    assert_eq!(mapper.get_by_osm_id("way/1").unwrap().len(), 1);
+    assert_eq!(in_bbox(&mapper), vec!["way/1"]);
+    assert_eq!(mapper.nearest(45.03, -75.03, 1, |_| true)[0].0.osm_id(), "way/1");
+    // Still found once drawn on a finer grid and back.
+    mapper.set_tile_scale(features::TILE_SCALE).unwrap();
+    assert!(mapper.cells_of("way/1").is_some());
+    mapper.set_tile_scale(10.0).unwrap();
+    assert_eq!(in_bbox(&mapper), vec!["way/1"]);
+    mapper.remove_feature("way/1");
+    assert!(in_bbox(&mapper).is_empty());
+}
+
+#[test]
+fn test_malformed_features() {
+    let geojson_file = TempFile::new("geojson");
+    std::fs::write(geojson_file.path(), r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": {"id": "node/1", "amenity": "bench"}, "geometry": {"type": "Point", "coordinates": [-75.0, 45.0]}},
+        {"type": "Feature", "properties": {"id": "way/2", "highway": "path"}, "geometry": {"type": "LineString", "coordinates": [[-75.0, 45.0]]}},
+        {"type": "Feature", "properties": {"id": "node/3", "amenity": "bench"}, "geometry": {"type": "Point", "coordinates": "nowhere"}},
+        {"type": "Feature", "properties": {"id": "node/4", "amenity": "bench"}, "geometry": {"type": "Point", "coordinates": [-75.001, 45.001]}}
+    ]}"#).unwrap();
+    let geojson_file = geojson_file.name();
+    // Strict by default, the error locates the first malformed feature.
+    let error = interface::OSMGeoMapper::from_geojson_file(geojson_file.clone(), None, None).unwrap_err();
+    let error = match error.downcast_ref::<Error>() {
+        Some(Error::Parse(error)) => error,
+        _ => panic!("Expected a parse error, got {}", error),
+    };
+    assert_eq!(error.file.as_deref(), Some(geojson_file.as_str()));
+    assert_eq!(error.feature_index, Some(1));
+    assert_eq!(error.osm_id.as_deref(), Some("way/2"));
+    let lenient = interface::LoadOptions { lenient: true, ..Default::default() };
+    let mapper = interface::OSMGeoMapper::from_geojson_file(geojson_file.clone(), None, Some(lenient)).unwrap();
+    assert!(mapper.lenient);
+    assert!(mapper.get_real(45.0, -75.0).is_some());
+    assert!(mapper.get_real(45.001, -75.001).is_some());
+    let skipped: Vec<(Option<usize>, Option<String>)> = mapper.skipped_features.read().unwrap()
+        .iter()
+        .map(|error| (error.feature_index, error.osm_id.clone()))
+        .collect();
+    assert_eq!(skipped, vec![(Some(1), Some("way/2".to_string())), (Some(2), Some("node/3".to_string()))]);
+    let missing_file = interface::OSMGeoMapper::from_osm_file("does/not/exist.osm".to_string(), None, None, None).unwrap_err();
+    assert!(missing_file.to_string().starts_with("Parse error: does/not/exist.osm: "));
+}
+
+#[test]
+fn test_compressed_geojson_seq_file() {
+    let geojson_file = TempFile::new("geojsonl.gz");
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(br#"{"type": "Feature", "properties": {"id": "node/1", "amenity": "bench"}, "geometry": {"type": "Point", "coordinates": [-75.0, 45.0]}}
+{"type": "Feature", "properties": {"id": "node/2", "amenity": "bench"}, "geometry": {"type": "Point", "coordinates": [-75.001, 45.001]}}
+"#).unwrap();
+    std::fs::write(geojson_file.path(), encoder.finish().unwrap()).unwrap();
+    let mapper = interface::OSMGeoMapper::from_geojson_file(geojson_file.name(), None, None).unwrap();
+    assert!(mapper.get_real(45.0, -75.0).is_some());
+    assert!(mapper.get_real(45.001, -75.001).is_some());
+}
+
+#[test]
+fn test_gpx_overlay() {
+    let gpx_file = TempFile::new("gpx");
+    std::fs::write(gpx_file.path(), r#"<?xml version="1.0" encoding="UTF-8"?>
+        <gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+          <wpt lat="45.002" lon="-75.002"><ele>70</ele><name>Broken bench</name></wpt>
+          <trk><name>Survey</name><trkseg>
+            <trkpt lat="45.0" lon="-75.0"><ele>65</ele><time>2020-07-01T10:00:00Z</time></trkpt>
+            <trkpt lat="45.0" lon="-75.0001"><ele>68</ele><time>2020-07-01T10:01:00Z</time></trkpt>
+          </trkseg></trk>
+        </gpx>"#).unwrap();
+    let mut mapper = interface::OSMGeoMapper::default();
+    mapper.load_gpx_file(gpx_file.name()).unwrap();
+    match mapper.get_real(45.0, -75.00005).unwrap()[0].as_ref() {
+        features::GeoTile::Track { osm_id, name, start_time, end_time, min_elevation, max_elevation, .. } => {
+            assert_eq!(osm_id, "track/0");
+            assert_eq!(name.as_deref(), Some("Survey"));
+            assert_eq!(start_time.as_deref(), Some("2020-07-01T10:00:00Z"));
+            assert_eq!(end_time.as_deref(), Some("2020-07-01T10:01:00Z"));
+            assert_eq!((min_elevation.as_deref(), max_elevation.as_deref()), (Some("65"), Some("68")));
+        }
+        geo_tile => panic!("Expected a track, found {:?}", geo_tile),
+    }
+    match mapper.get_real(45.002, -75.002).unwrap()[0].as_ref() {
+        features::GeoTile::Waypoint { name, elevation, .. } => {
+            assert_eq!(name.as_deref(), Some("Broken bench"));
+            assert_eq!(elevation.as_deref(), Some("70"));
+        }
+        geo_tile => panic!("Expected a waypoint, found {:?}", geo_tile),
+    }
+}
+
+#[test]
+fn test_gpx_files() {
+    let first_file = TempFile::new("gpx");
+    let second_file = TempFile::new("gpx");
+    std::fs::write(first_file.path(), r#"<?xml version="1.0" encoding="UTF-8"?>
+        <gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+          <wpt lat="45.002" lon="-75.002"><name>Broken bench</name></wpt>
+          <trk><name>Morning</name><trkseg>
+            <trkpt lat="45.0" lon="-75.0"/><trkpt lat="45.0" lon="-75.0001"/>
+          </trkseg></trk>
+        </gpx>"#).unwrap();
+    // Another recording, with a track paused once.
+    std::fs::write(second_file.path(), r#"<?xml version="1.0" encoding="UTF-8"?>
+        <gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+          <wpt lat="45.003" lon="-75.003"><name>Fallen tree</name></wpt>
+          <trk><name>Evening</name>
+            <trkseg><trkpt lat="45.001" lon="-75.0"/><trkpt lat="45.001" lon="-75.0001"/></trkseg>
+            <trkseg><trkpt lat="45.001" lon="-75.0003"/><trkpt lat="45.001" lon="-75.0004"/></trkseg>
+          </trk>
+        </gpx>"#).unwrap();
+    let mut mapper = interface::OSMGeoMapper::from_gpx_file(first_file.name(), None, None).unwrap();
+    mapper.load_gpx_file(second_file.name()).unwrap();
+    // Both files have a waypoint/0 and a track/0, none of them is dropped.
+    assert_eq!(mapper.get_by_osm_id("waypoint/0").unwrap().len(), 2);
+    assert!(matches!(mapper.get_real(45.003, -75.003).unwrap()[0].as_ref(), features::GeoTile::Waypoint { .. }));
+    assert!(matches!(mapper.get_real(45.001, -75.00035).unwrap()[0].as_ref(), features::GeoTile::Track { .. }));
+    let names: Vec<Option<String>> = mapper.get_by_osm_id("track/0").unwrap()
+        .iter()
+        .map(|geo_tile| match geo_tile.as_ref() {
+            features::GeoTile::Track { name, .. } => name.clone(),
+            geo_tile => panic!("Expected a track, found {:?}", geo_tile),
+        })
+        .collect();
+    assert_eq!(names, vec![Some("Morning".to_string()), Some("Evening".to_string()), Some("Evening".to_string())]);
+    assert_eq!(mapper.remove_feature("track/0").unwrap().len(), 3);
+    assert!(mapper.get_real(45.001, -75.00035).is_none() && mapper.get_real(45.0, -75.00005).is_none());
+}
+
+#[test]
+fn test_csv_file() {
+    let csv_file = TempFile::new("tsv");
+    std::fs::write(csv_file.path(), "id\tamenity\thighway\tname\tgeometry
+node/1\tbench\t\t\tPOINT (-75 45)
+way/2\t\tfootway\tCanal path\tLINESTRING (-75 45.001, -75.0002 45.001)
+way/3\t\tfootway\t\tLINESTRING (-75 45.002)
+node/4\tcafe\t\t\t0101000000000000000000F03F0000000000000040
+\tbench\t\t\tPOINT (-75.003 45.003)
+").unwrap();
+    let csv_file = csv_file.name();
+    let error = interface::OSMGeoMapper::from_csv_file(csv_file.clone(), None, None, None).unwrap_err();
+    let error = match error.downcast_ref::<Error>() {
+        Some(Error::Parse(error)) => error,
+        _ => panic!("Expected a parse error, got {}", error),
+    };
+    assert_eq!((error.feature_index, error.osm_id.as_deref()), (Some(2), Some("way/3")));
+    let mut mapper = interface::OSMGeoMapper { lenient: true, ..Default::default() };
+    mapper.load_more_from_csv_file(csv_file, None).unwrap();
+    assert!(matches!(mapper.get_real(45.0, -75.0).unwrap()[0].as_ref(), features::GeoTile::Amenity { .. }));
+    match mapper.get_real(45.001, -75.0001).unwrap()[0].as_ref() {
+        features::GeoTile::Highway { osm_id, name, geometry, .. } => {
+            assert_eq!((osm_id.as_str(), name.as_deref()), ("way/2", Some("Canal path")));
+            assert_eq!(geometry.to_wkt(), "LINESTRING (-75 45.001, -75.0002 45.001)");
+        }
+        geo_tile => panic!("Expected a highway, found {:?}", geo_tile),
+    }
+    assert!(matches!(mapper.get_real(2.0, 1.0).unwrap()[0].as_ref(), features::GeoTile::Amenity { .. }));
+    // Rows without an id are identified by their position.
+    assert_eq!(mapper.get_real(45.003, -75.003).unwrap()[0].osm_id(), "csv/4");
+    assert_eq!(mapper.skipped_features.read().unwrap().len(), 1);
+}
+
+#[test]
+fn test_opl_file() {
+    let opl_file = TempFile::new("opl");
+    std::fs::write(opl_file.path(), "n1 v1 Tamenity=bench x-75.0 y45.0
+n2 v1 T x-75.0 y45.001
+n3 v1 T x-75.001 y45.001
+w10 v1 Thighway=footway,name=Canal%20%path Nn2,n3
+").unwrap();
+    assert_eq!(interface::FileFormat::from_path(opl_file.path()), Some(interface::FileFormat::Opl));
+    assert_eq!(interface::FileFormat::from_path("planet.osm.bz2"), Some(interface::FileFormat::Osm));
+    let mapper = interface::OSMGeoMapper::from_opl_file(opl_file.name(), None, None, None).unwrap();
+    assert!(matches!(mapper.get_real(45.0, -75.0).unwrap()[0].as_ref(), features::GeoTile::Amenity { .. }));
+    match mapper.get_real(45.001, -75.0005).unwrap()[0].as_ref() {
+        features::GeoTile::Highway { osm_id, name, .. } => assert_eq!((osm_id.as_str(), name.as_deref()), ("way/10", Some("Canal path"))),
+        geo_tile => panic!("Expected a highway, found {:?}", geo_tile),
+    }
+    // The untagged way nodes are not drawn on their own.
+    assert!(mapper.osm_id_cells.read().unwrap().get("node/2").is_none());
+}
+
+// Protobuf helpers to write a vector tile by hand.
+fn varint(mut value: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    while value >= 0x80 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+    bytes
+}
+
+fn protobuf_field(number: u64, payload: &[u8]) -> Vec<u8> {
+    [varint(number << 3 | 2), varint(payload.len() as u64), payload.to_vec()].concat()
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+// A vector tile layer with one feature per (id, key/value indexes, geometry type, geometry commands).
+fn vector_tile_layer(name: &str, keys: &[&str], values: &[&str], features: &[(u64, Vec<u64>, u64, Vec<u64>)]) -> Vec<u8> {
+    let mut layer = protobuf_field(1, name.as_bytes());
+    for (id, tags, geometry_type, commands) in features {
+        let feature = [
+            [varint(1 << 3), varint(*id)].concat(),
+            protobuf_field(2, &tags.iter().flat_map(|tag| varint(*tag)).collect::<Vec<u8>>()),
+            [varint(3 << 3), varint(*geometry_type)].concat(),
+            protobuf_field(4, &commands.iter().flat_map(|command| varint(*command)).collect::<Vec<u8>>()),
+        ].concat();
+        layer.extend(protobuf_field(2, &feature));
+    }
+    for key in keys {
+        layer.extend(protobuf_field(3, key.as_bytes()));
+    }
+    for value in values {
+        layer.extend(protobuf_field(4, &protobuf_field(1, value.as_bytes())));
+    }
+    layer
+}
+
+type PbfTags<'a> = &'a [(&'a str, &'a str)];
+type PbfNode<'a> = (i64, f64, f64, PbfTags<'a>);
+type PbfWay<'a> = (i64, &'a [i64], PbfTags<'a>);
+type PbfRelation<'a> = (i64, &'a [(u64, i64, &'a str)], PbfTags<'a>);
+
+// A PBF file with one raw (uncompressed) data block holding plain nodes (id, lat, lon, tags),
+// ways (id, node ids, tags) and relations (id, members as (type, id, role), tags).
+// Member types are 0 for nodes, 1 for ways and 2 for relations.
+fn pbf_contents(nodes: &[PbfNode], ways: &[PbfWay], relations: &[PbfRelation]) -> Vec<u8> {
+    fn string_index(strings: &mut Vec<String>, string: &str) -> u64 {
+        match strings.iter().position(|other| other == string) {
+            Some(index) => index as u64,
+            None => {
+                strings.push(string.to_string());
+                strings.len() as u64 - 1
+            }
+        }
+    }
+    fn packed(number: u64, values: Vec<u64>) -> Vec<u8> {
+        protobuf_field(number, &values.iter().flat_map(|value| varint(*value)).collect::<Vec<u8>>())
+    }
+    fn deltas(ids: Vec<i64>) -> Vec<u64> {
+        ids.iter().zip([0].iter().chain(ids.iter())).map(|(id, previous)| zigzag(id - previous)).collect()
+    }
+    fn tags(strings: &mut Vec<String>, tags: PbfTags) -> Vec<u8> {
+        let keys = tags.iter().map(|(key, _)| string_index(strings, key)).collect();
+        let values = tags.iter().map(|(_, value)| string_index(strings, value)).collect();
+        [packed(2, keys), packed(3, values)].concat()
+    }
+    let mut strings = vec![String::new()];
+    let mut groups = Vec::new();
+    for (id, lat, lon, node_tags) in nodes {
+        // Coordinates are in 100 nanodegrees with the default granularity.
+        let node = [
+            [varint(1 << 3), varint(zigzag(*id))].concat(),
+            tags(&mut strings, node_tags),
+            [varint(8 << 3), varint(zigzag((lat * 1e7).round() as i64))].concat(),
+            [varint(9 << 3), varint(zigzag((lon * 1e7).round() as i64))].concat(),
+        ].concat();
+        groups.extend(protobuf_field(2, &protobuf_field(1, &node)));
+    }
+    for (id, node_ids, way_tags) in ways {
+        let way = [[varint(1 << 3), varint(*id as u64)].concat(), tags(&mut strings, way_tags), packed(8, deltas(node_ids.to_vec()))].concat();
+        groups.extend(protobuf_field(2, &protobuf_field(3, &way)));
+    }
+    for (id, members, relation_tags) in relations {
+        let relation = [
+            [varint(1 << 3), varint(*id as u64)].concat(),
+            tags(&mut strings, relation_tags),
+            packed(8, members.iter().map(|(_, _, role)| string_index(&mut strings, role)).collect()),
+            packed(9, deltas(members.iter().map(|(_, id, _)| *id).collect())),
+            packed(10, members.iter().map(|(member_type, _, _)| *member_type).collect()),
+        ].concat();
+        groups.extend(protobuf_field(2, &protobuf_field(4, &relation)));
+    }
+    let string_table: Vec<u8> = strings.iter().flat_map(|string| protobuf_field(1, string.as_bytes())).collect();
+    let block = [protobuf_field(1, &string_table), groups].concat();
+    let blob = [protobuf_field(1, &block), varint(2 << 3), varint(block.len() as u64)].concat();
+    let header = [protobuf_field(1, b"OSMData"), varint(3 << 3), varint(blob.len() as u64)].concat();
+    [(header.len() as u32).to_be_bytes().to_vec(), header, blob].concat()
+}
+
+#[test]
+fn test_pbf_file() {
+    let pbf_file = TempFile::new("pbf");
+    std::fs::write(pbf_file.path(), pbf_contents(
+        &[(1, 45.0, -75.001, &[("amenity", "bench")]), (2, 45.0, -75.0, &[]), (3, 45.001, -75.0, &[])],
+        &[(10, &[2, 3], &[("highway", "footway")])],
+        &[],
+    )).unwrap();
+    let mapper = interface::OSMGeoMapper::from_pbf_file(pbf_file.name(), None, None, None, None).unwrap();
+    // Nodes are drawn at their own location (longitude first), like the ways.
+    assert_eq!(mapper.cells_of("node/1").unwrap().into_iter().collect::<Vec<_>>(), vec![geo_types::Coordinate { x: -7_500_100, y: 4_500_000 }]);
+    assert!(matches!(mapper.get_real(45.0, -75.001).unwrap()[0].as_ref(), features::GeoTile::Amenity { .. }));
+    assert!(matches!(mapper.get_real(45.0005, -75.0).unwrap()[0].as_ref(), features::GeoTile::Highway { .. }));
+}
+
+#[test]
+fn test_pbf_malformed_way() {
+    let pbf_file = TempFile::new("pbf");
+    // Node 9 and way 12 aren't part of the file (eg. a badly cut extract).
+    std::fs::write(pbf_file.path(), pbf_contents(
+        &[(1, 45.0, -75.001, &[("amenity", "bench")]), (2, 45.0, -75.0, &[])],
+        &[(10, &[2, 9], &[("highway", "footway")])],
+        &[(20, &[(1, 12, "outer")], &[("type", "multipolygon"), ("leisure", "park")])],
+    )).unwrap();
+    let error = interface::OSMGeoMapper::from_pbf_file(pbf_file.name(), None, None, None, None).unwrap_err();
+    let error = match error.downcast_ref::<Error>() {
+        Some(Error::Parse(error)) => error,
+        _ => panic!("Expected a parse error, got {}", error),
+    };
+    assert_eq!((error.file.as_deref(), error.osm_id.as_deref()), (Some(pbf_file.name().as_str()), Some("way/10")));
+    let lenient = interface::LoadOptions { lenient: true, ..Default::default() };
+    let mut mapper = interface::OSMGeoMapper::from_pbf_file(pbf_file.name(), None, None, None, Some(lenient)).unwrap();
+    assert!(matches!(mapper.get_real(45.0, -75.001).unwrap()[0].as_ref(), features::GeoTile::Amenity { .. }));
+    assert!(mapper.get_by_osm_id("way/10").is_none() && mapper.get_by_osm_id("relation/20").is_none());
+    mapper.load_more_from_pbf_file(pbf_file.name(), None, None).unwrap();
+    let skipped: Vec<String> = mapper.skipped_features.read().unwrap().iter().map(|error| error.osm_id.clone().unwrap()).collect();
+    assert_eq!(skipped, vec!["way/10", "relation/20", "way/10", "relation/20"]);
+}
+
+#[test]
+fn test_pbf_area() {
+    let pbf_file = TempFile::new("pbf");
+    // A bench inside the area and one outside, a path crossing its edge, a road away from it and
+    // a multipolygon made of a way touching the area and a way outside of it, and a route following the path
+    // and the road.
+    std::fs::write(pbf_file.path(), pbf_contents(
+        &[
+            (1, 45.0, -75.0, &[("amenity", "bench")]),
+            (2, 45.1, -75.1, &[("amenity", "bench")]),
+            (3, 45.0, -75.0005, &[]),
+            (4, 45.0, -74.998, &[]),
+            (5, 46.0, -76.0, &[]),
+            (6, 46.0, -76.001, &[]),
+            (7, 45.0005, -75.0005, &[]),
+            (8, 45.0005, -74.998, &[]),
+            (9, 44.998, -74.998, &[]),
+            (14, 44.998, -75.0005, &[]),
+        ],
+        &[
+            (10, &[3, 4], &[("highway", "footway")]),
+            (11, &[5, 6], &[("highway", "residential")]),
+            (12, &[8, 7, 14], &[]),
+            (13, &[14, 9, 8], &[]),
+        ],
+        &[
+            (20, &[(1, 12, "outer"), (1, 13, "outer")], &[("type", "multipolygon"), ("leisure", "park")]),
+            (21, &[(1, 10, ""), (1, 11, "")], &[("type", "route"), ("route", "hiking")]),
+        ],
+    )).unwrap();
+    let bbox = interface::Area::BoundingBox { left: -75.001, bottom: 44.999, right: -74.999, top: 45.001 };
+    let radius = interface::Area::Radius { latitude: 45.0, longitude: -75.0, radius: 100 };
+    let (left, bottom, right, top) = radius.to_bbox();
+    assert!([left + 75.001, bottom - 44.999, right + 74.999, top - 45.001].iter().all(|delta| delta.abs() < 1e-9));
+    for area in [bbox, radius].iter() {
+        let (left, bottom, right, top) = area.to_bbox();
+        let objs = pbf_parser::parse_pbf_file_by_bbox(pbf_file.name(), left, bottom, right, top, None).unwrap();
+        let osm_ids: Vec<String> = objs.keys().map(|id| pbf_parser::osm_id_to_string(*id)).collect();
+        // Ways keep all their nodes and multipolygons all their member ways, even outside of the area.
+        // Other relations only keep their members touching the area.
+        assert_eq!(osm_ids, vec![
+            "node/1", "node/3", "node/4", "node/7", "node/8", "node/9", "node/14", "way/10", "way/12", "way/13", "relation/20", "relation/21",
+        ]);
+        let mapper = interface::OSMGeoMapper::from_pbf_file(pbf_file.name(), None, Some(area.clone()), None, None).unwrap();
+        assert!(mapper.cells_of("node/1").is_some() && mapper.cells_of("node/2").is_none());
+        assert!(mapper.cells_of("way/11").is_none());
+        assert!(matches!(mapper.get_real(45.0, -74.9985).unwrap()[0].as_ref(), features::GeoTile::Highway { .. }));
+        assert!(matches!(mapper.get_real(44.9985, -74.9985).unwrap()[0].as_ref(), features::GeoTile::Leisure { .. }));
+    }
+}
+
+#[test]
+fn test_filtered_loads() {
+    let pbf_file = TempFile::new("pbf");
+    let osm_file = TempFile::new("osm");
+    // A bench, a park multipolygon and an old-style (untagged) multipolygon holding its tags on the outer way.
+    std::fs::write(pbf_file.path(), pbf_contents(
+        &[
+            (1, 45.0, -75.0, &[("amenity", "bench")]),
+            (2, 45.001, -75.001, &[]), (3, 45.001, -75.002, &[]), (4, 45.002, -75.002, &[]),
+            (5, 45.001, -75.0, &[]), (6, 45.001, -74.999, &[]), (7, 45.002, -74.999, &[]),
+        ],
+        &[(10, &[2, 3, 4, 2], &[]), (11, &[5, 6, 7, 5], &[("amenity", "parking")])],
+        &[
+            (20, &[(1, 10, "outer")], &[("type", "multipolygon"), ("leisure", "park")]),
+            (21, &[(1, 11, "outer")], &[("type", "multipolygon")]),
+        ],
+    )).unwrap();
+    std::fs::write(osm_file.path(), r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="45.0" lon="-75.0"><tag k="amenity" v="bench"/></node>
+          <node id="2" lat="45.001" lon="-75.001"/><node id="3" lat="45.001" lon="-75.002"/><node id="4" lat="45.002" lon="-75.002"/>
+          <node id="5" lat="45.001" lon="-75.0"/><node id="6" lat="45.001" lon="-74.999"/><node id="7" lat="45.002" lon="-74.999"/>
+          <way id="10"><nd ref="2"/><nd ref="3"/><nd ref="4"/><nd ref="2"/></way>
+          <way id="11"><nd ref="5"/><nd ref="6"/><nd ref="7"/><nd ref="5"/><tag k="amenity" v="parking"/></way>
+          <relation id="20"><member type="way" ref="10" role="outer"/><tag k="type" v="multipolygon"/><tag k="leisure" v="park"/></relation>
+          <relation id="21"><member type="way" ref="11" role="outer"/><tag k="type" v="multipolygon"/></relation>
+        </osm>"#).unwrap();
+    let filter: Filter = "amenity".parse().unwrap();
+    // The park and its members aren't parsed at all.
+    let expected = vec!["node/1", "node/5", "node/6", "node/7", "way/11", "relation/21"];
+    let objs = pbf_parser::parse_pbf_file(pbf_file.name(), Some(&filter)).unwrap();
+    assert_eq!(objs.keys().map(|id| pbf_parser::osm_id_to_string(*id)).collect::<Vec<_>>(), expected);
+    let objs = pbf_parser::parse_pbf_file_by_bbox(pbf_file.name(), -75.01, 44.99, -74.99, 45.01, Some(&filter)).unwrap();
+    assert_eq!(objs.keys().map(|id| pbf_parser::osm_id_to_string(*id)).collect::<Vec<_>>(), expected);
+    let osm_data = osm_parser::parse_osm_file(osm_file.name()).unwrap();
+    let filtered = osmtogeojson::filter_osm_data(&osm_data, &filter);
+    assert_eq!(filtered.relations.keys().collect::<Vec<_>>(), vec![&21]);
+    assert_eq!(filtered.ways.keys().collect::<Vec<_>>(), vec![&11]);
+    assert_eq!(filtered.nodes.len(), 4);
+    let pbf_mapper = interface::OSMGeoMapper::from_pbf_file(pbf_file.name(), None, None, Some(filter.clone()), None).unwrap();
+    let osm_mapper = interface::OSMGeoMapper::from_osm_file(osm_file.name(), None, Some(filter), None).unwrap();
+    for mapper in [pbf_mapper, osm_mapper].iter() {
+        assert!(mapper.get_by_osm_id("node/1").is_some());
+        assert!(matches!(mapper.get_by_osm_id("way/11").unwrap()[0].as_ref(), features::GeoTile::Amenity { .. }));
+        assert!(mapper.get_by_osm_id("relation/20").is_none() && mapper.get_by_osm_id("way/10").is_none());
+    }
+}
+
+#[test]
+fn test_overlapping_loads() {
+    let pbf_file = TempFile::new("pbf");
+    let osm_file = TempFile::new("osm");
+    std::fs::write(pbf_file.path(), pbf_contents(
+        &[(1, 45.0, -75.001, &[("amenity", "bench")]), (2, 45.0, -75.0, &[]), (3, 45.001, -75.0, &[])],
+        &[(10, &[2, 3], &[("highway", "footway")])],
+        &[],
+    )).unwrap();
+    std::fs::write(osm_file.path(), r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="45.0" lon="-75.001"><tag k="amenity" v="bench"/></node>
+          <node id="4" lat="45.002" lon="-75.0"><tag k="amenity" v="cafe"/></node>
+        </osm>"#).unwrap();
+    let mut mapper = interface::OSMGeoMapper::from_pbf_file(pbf_file.name(), None, None, None, None).unwrap();
+    let bench = mapper.get_by_osm_id("node/1").unwrap()[0].clone();
+    let footway = mapper.get_by_osm_id("way/10").unwrap()[0].clone();
+    // Objects that are already loaded are neither drawn again nor replaced.
+    mapper.load_more_from_pbf_file(pbf_file.name(), None, None).unwrap();
+    mapper.load_more_from_osm_file(osm_file.name(), None).unwrap();
+    assert!(Arc::ptr_eq(&mapper.get_by_osm_id("node/1").unwrap()[0], &bench));
+    assert!(Arc::ptr_eq(&mapper.get_by_osm_id("way/10").unwrap()[0], &footway));
+    assert_eq!(mapper.get_real(45.0, -75.001).unwrap().len(), 1);
+    assert_eq!(mapper.get_real(45.0005, -75.0).unwrap().len(), 1);
+    assert!(mapper.get_by_osm_id("node/4").is_some());
+}
+
+#[test]
+fn test_mbtiles_file() {
+    let mbtiles_file = TempFile::new("mbtiles");
+    // Tile 14/4747/5866 (Ottawa): a 100x100 building, a cafe and the label of a street on top of it.
+    let building = vector_tile_layer("building", &[], &[], &[(7, vec![], 3, vec![
+        9, zigzag(1000), zigzag(1000),
+        3 << 3 | 2, zigzag(100), 0, 0, zigzag(100), zigzag(-100), 0,
+        7,
+    ])]);
+    let poi = vector_tile_layer("poi", &["class", "subclass"], &["cafe"], &[(8, vec![0, 0, 1, 0], 1, vec![9, zigzag(2000), zigzag(3000)])]);
+    let label = vector_tile_layer("transportation_name", &["name"], &["Bank Street"], &[(9, vec![0, 0], 1, vec![9, zigzag(2000), zigzag(3000)])]);
+    let tile = [protobuf_field(3, &building), protobuf_field(3, &poi), protobuf_field(3, &label)].concat();
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&tile).unwrap();
+    let connection = rusqlite::Connection::open(mbtiles_file.path()).unwrap();
+    connection.execute_batch("CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);").unwrap();
+    // Rows are counted from the south.
+    connection.execute(
+        "INSERT INTO tiles VALUES (14, 4747, ?1, ?2)",
+        rusqlite::params![(1 << 14) - 1 - 5866, encoder.finish().unwrap()],
+    ).unwrap();
+    drop(connection);
+
+    assert_eq!(interface::FileFormat::from_path(mbtiles_file.path()), Some(interface::FileFormat::MBTiles));
+    let mbtiles_file = mbtiles_file.name();
+    let mapper = interface::OSMGeoMapper::from_mbtiles_file(mbtiles_file.clone(), None, None, None, None).unwrap();
+    match mapper.get_real(45.425345, -75.690168).unwrap()[0].as_ref() {
+        features::GeoTile::Building { osm_id, .. } => assert_eq!(osm_id, "building/7"),
+        geo_tile => panic!("Expected a building, found {:?}", geo_tile),
+    }
+    let geo_tiles = mapper.get_real(45.418004, -75.685072).unwrap();
+    assert_eq!(geo_tiles.len(), 1);
+    assert!(matches!(geo_tiles[0].as_ref(), features::GeoTile::Amenity { .. }));
+    let error = interface::OSMGeoMapper::from_mbtiles_file(mbtiles_file.clone(), Some(32), None, None, None).unwrap_err();
+    assert!(matches!(error.downcast_ref::<Error>(), Some(Error::Parse(_))));
+    // Nothing is read outside of the requested area.
+    let area = interface::Area::BoundingBox { left: -76.0, bottom: 44.0, right: -75.9, top: 44.1 };
+    let mapper = interface::OSMGeoMapper::from_mbtiles_file(mbtiles_file, Some(14), None, Some(area), None).unwrap();
+    assert!(mapper.data_structure.read().unwrap().is_empty());
+}
+
+#[test]
+fn test_feature_registry() {
+    let first_file = TempFile::new("geojson");
+    let second_file = TempFile::new("geojson");
+    let path = r#"{"type": "Feature", "properties": {"id": "way/1", "highway": "footway"}, "geometry": {"type": "LineString", "coordinates": [[-75.0, 45.0], [-75.0005, 45.0]]}}"#;
+    std::fs::write(first_file.path(), format!(r#"{{"type": "FeatureCollection", "features": [{},
+        {{"type": "Feature", "properties": {{"id": "node/2", "amenity": "bench"}}, "geometry": {{"type": "Point", "coordinates": [-75.0, 45.001]}}}}
+    ]}}"#, path)).unwrap();
+    // An overlapping area, with the same path.
+    std::fs::write(second_file.path(), format!(r#"{{"type": "FeatureCollection", "features": [{},
+        {{"type": "Feature", "properties": {{"id": "node/3", "amenity": "bench"}}, "geometry": {{"type": "Point", "coordinates": [-75.0005, 45.001]}}}}
+    ]}}"#, path)).unwrap();
+    let mut mapper = interface::OSMGeoMapper::from_geojson_file(first_file.name(), None, None).unwrap();
+    let path = mapper.get_by_osm_id("way/1").unwrap().remove(0);
+    let cells = mapper.cells_of("way/1").unwrap();
+    assert!(cells.contains(&geo_types::Coordinate { x: -7_500_020, y: 4_500_000 }));
+    mapper.load_more_from_geojson_file(second_file.name()).unwrap();
+    let geo_tiles = mapper.get_by_osm_id("way/1").unwrap();
+    assert_eq!(geo_tiles.len(), 1);
+    assert!(std::sync::Arc::ptr_eq(&geo_tiles[0], &path));
+    assert_eq!(mapper.cells_of("way/1").unwrap(), cells);
+    let geo_tiles = mapper.get_real(45.0, -75.0002).unwrap();
+    assert_eq!(geo_tiles.len(), 1);
+    assert!(std::sync::Arc::ptr_eq(&geo_tiles[0], &path));
+    assert!(mapper.get_by_osm_id("node/3").is_some());
+    assert!(std::sync::Arc::ptr_eq(&mapper.remove_feature("way/1").unwrap()[0], &path));
+    assert!(mapper.get_real(45.0, -75.0002).is_none());
+    assert!(mapper.get_by_osm_id("way/1").is_none() && mapper.cells_of("way/1").is_none());
+}
+
+#[test]
+fn test_tile_scale() {
+    let first_file = TempFile::new("geojson");
+    let second_file = TempFile::new("geojson");
+    std::fs::write(first_file.path(), r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": {"id": "way/1", "highway": "path"}, "geometry": {"type": "LineString", "coordinates": [[-75.0, 45.0], [-75.0625, 45.0]]}},
+        {"type": "Feature", "properties": {"id": "node/2", "amenity": "bench"}, "geometry": {"type": "Point", "coordinates": [-75.0, 45.001]}}
+    ]}"#).unwrap();
+    std::fs::write(second_file.path(), r#"{"type": "Feature", "properties": {"id": "node/3", "amenity": "bench"}, "geometry": {"type": "Point", "coordinates": [-75.01, 45.001]}}"#).unwrap();
+    let location = interface::Location::Coordinates { latitude: 45.001, longitude: -75.0 };
+    let mut mapper = interface::OSMGeoMapper::from_geojson_file(first_file.name(), Some(location.clone()), None).unwrap();
+    assert_eq!(mapper.tile_scale, features::TILE_SCALE);
+    assert_eq!(mapper.cells_of("way/1").unwrap().len(), 6250);
+    // Ten times coarser, the loaded features are drawn again.
+    mapper.set_tile_scale(10_000.0).unwrap();
+    assert_eq!(mapper.coordinates, geo_types::Coordinate { x: -750_000, y: 450_010 });
+    assert_eq!(mapper.cells_of("way/1").unwrap().len(), 625);
+    assert!(matches!(mapper.get_real(45.0, -75.03).unwrap()[0].as_ref(), features::GeoTile::Highway { .. }));
+    assert!(matches!(mapper.get(450_010, -750_000).unwrap()[0].as_ref(), features::GeoTile::Amenity { .. }));
+    assert!(mapper.get(4_500_100, -7_500_000).is_none());
+    mapper.load_more_from_geojson_file(second_file.name()).unwrap();
+    assert_eq!(mapper.cells_of("node/3").unwrap().into_iter().collect::<Vec<_>>(), vec![geo_types::Coordinate { x: -750_100, y: 450_010 }]);
+    assert_eq!(operations::from_tile_scale(mapper.coordinates.y, mapper.tile_scale), 45.001);
+    // Invalid scales are rejected without changing anything.
+    for tile_scale in [0.0, -1.0, f64::NAN, f64::INFINITY, 1e9].iter() {
+        let error = mapper.set_tile_scale(*tile_scale).unwrap_err();
+        assert!(matches!(error.downcast_ref::<Error>(), Some(Error::InvalidTileScale(_))));
+    }
+    assert_eq!(mapper.tile_scale, 10_000.0);
+    // Drawn on the given grid from the start.
+    let grid = projection::TileGrid::new(10_000.0, projection::Projection::Degrees);
+    let options = interface::LoadOptions { grid, ..Default::default() };
+    let loaded = interface::OSMGeoMapper::from_geojson_file(first_file.name(), Some(location), Some(options)).unwrap();
+    assert_eq!(loaded.grid(), grid);
+    assert_eq!(loaded.coordinates, geo_types::Coordinate { x: -750_000, y: 450_010 });
+    assert_eq!(loaded.cells_of("way/1"), mapper.cells_of("way/1"));
+    let options = interface::LoadOptions { grid: projection::TileGrid::new(0.0, projection::Projection::Degrees), ..Default::default() };
+    assert!(interface::OSMGeoMapper::from_geojson_file(first_file.name(), None, Some(options)).is_err());
+}
+
+#[test]
+fn test_projection() {
+    let file = TempFile::new("geojson");
+    std::fs::write(file.path(), r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": {"id": "way/1", "highway": "path"}, "geometry": {"type": "LineString", "coordinates": [[-75.0, 60.0], [-74.9, 60.0]]}},
+        {"type": "Feature", "properties": {"id": "node/2", "amenity": "bench"}, "geometry": {"type": "Point", "coordinates": [-74.95, 60.0]}}
+    ]}"#).unwrap();
+    let location = interface::Location::Coordinates { latitude: 60.0, longitude: -75.0 };
+    let mut mapper = interface::OSMGeoMapper::from_geojson_file(file.name(), Some(location), None).unwrap();
+    assert_eq!(mapper.grid(), projection::TileGrid::default());
+    assert_eq!(mapper.cells_of("way/1").unwrap().len(), 9_999);
+    // At 60 degrees north, a degree of longitude is half as long as a degree of latitude.
+    mapper.set_projection(projection::Projection::TransverseMercator { longitude: -75.0 }).unwrap();
+    let cells = mapper.cells_of("way/1").unwrap().len();
+    assert!((4_990..=5_010).contains(&cells), "{} cells", cells);
+    assert_eq!(mapper.coordinates, geo_types::Coordinate { x: -7_500_000, y: 6_000_000 });
+    let (latitude, longitude) = mapper.location();
+    assert!((latitude - 60.0).abs() < 1e-9 && (longitude + 75.0).abs() < 1e-9);
+    assert_eq!(mapper.cells_of("node/2").unwrap().into_iter().collect::<Vec<_>>(), vec![mapper.grid().to_tile(-74.95, 60.0)]);
+    assert!(matches!(mapper.get_real(60.0, -74.95).unwrap()[0].as_ref(), features::GeoTile::Amenity { .. }));
+}
+
+#[test]
+fn test_snapshot() {
+    let file = TempFile::new("geojson");
+    let snapshot_file = TempFile::new("bin");
+    std::fs::write(file.path(), r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": {"id": "way/1", "highway": "path", "name": "Trail"}, "geometry": {"type": "LineString", "coordinates": [[-75.0, 45.0], [-75.001, 45.0]]}},
+        {"type": "Feature", "properties": {"id": "way/2", "building": "yes", "addr:housenumber": "12", "addr:street": "Main Street"}, "geometry": {"type": "Polygon", "coordinates": [[[-75.0005, 45.0005], [-75.0, 45.0005], [-75.0, 45.001], [-75.0005, 45.001], [-75.0005, 45.0005]]]}},
+        {"type": "Feature", "properties": {"id": "node/3", "amenity": "bench"}, "geometry": {"type": "Point", "coordinates": [-75.0, 45.001]}}
+    ]}"#).unwrap();
+    let location = interface::Location::Coordinates { latitude: 45.001, longitude: -75.0 };
+    let mut mapper = interface::OSMGeoMapper::from_geojson_file(file.name(), Some(location), None).unwrap();
+    mapper.set_grid(50_000.0, projection::Projection::TransverseMercator { longitude: -75.0 }).unwrap();
+    mapper.save_snapshot(snapshot_file.name()).unwrap();
+    let loaded = interface::OSMGeoMapper::load_snapshot(snapshot_file.name()).unwrap();
+    assert_eq!(loaded.grid(), mapper.grid());
+    assert_eq!((loaded.coordinates, loaded.radius), (mapper.coordinates, mapper.radius));
+    assert_eq!(loaded.data_structure.read().unwrap().len(), mapper.data_structure.read().unwrap().len());
+    for osm_id in ["way/1", "way/2", "node/3"].iter() {
+        assert_eq!(loaded.cells_of(osm_id), mapper.cells_of(osm_id));
+    }
+    // Features covering many cells are still shared by them.
+    let cells: Vec<_> = loaded.cells_of("way/1").unwrap().into_iter().collect();
+    let path = loaded.get_by_osm_id("way/1").unwrap().remove(0);
+    assert!(cells.iter().all(|cell| loaded.get(cell.y, cell.x).unwrap().iter().any(|geo_tile| std::sync::Arc::ptr_eq(geo_tile, &path))));
+    assert_eq!(format!("{}", loaded.get_by_osm_id("way/2").unwrap()[0]), format!("{}", mapper.get_by_osm_id("way/2").unwrap()[0]));
+    assert_eq!(loaded.find_address("12 Main Street").len(), 1);
+    // Anything else is rejected.
+    std::fs::write(snapshot_file.path(), "not a snapshot").unwrap();
+    let error = interface::OSMGeoMapper::load_snapshot(snapshot_file.name()).unwrap_err();
+    assert!(matches!(error.downcast_ref::<Error>(), Some(Error::Parse(_))));
+}
+
+#[test]
+fn test_cli_geojson_file() {
+    let file = TempFile::new("geojson");
+    std::fs::write(file.path(), r#"{"type": "Feature", "properties": {"id": "node/1", "amenity": "bench"}, "geometry": {"type": "Point", "coordinates": [-75.0, 45.0]}}"#).unwrap();
+    // Without a location, the mapper starts on the first feature.
+    let options = viewer::cli::CLIOptions::from_iter(vec!["osm-geo-mapper", "--file", &file.name()]);
+    let mapper = viewer::cli_options_to_mapper(options).unwrap();
+    assert_eq!(mapper.coordinates, geo_types::Coordinate { x: -7_500_000, y: 4_500_000 });
+}